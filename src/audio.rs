@@ -0,0 +1,49 @@
+use std::vec::Vec;
+
+use interpreter::Context;
+
+// Output sample rate (Hz)
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// Amplitude of the (1-bit) output
+const AMPLITUDE: i16 = 8_192;
+
+// Default pattern; a 250 Hz square wave at the default pitch
+//  Everything but XO-CHIP (which can load its own) just has a buzzer.
+pub const DEFAULT_PATTERN: [u8; 16] = [0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF,
+                                       0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00];
+
+// Default pitch; plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+// Rate (bits per second) the pattern is played back at for `pitch`
+pub fn playback_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+// Render `count` stereo samples (interleaved) of the 1-bit pattern into `out`
+//  The pattern is only audible while ST is non-zero. `position` is the bit
+//  position within the pattern and is kept between calls so the output is continuous.
+pub fn render(c: &Context, position: &mut f32, count: usize, out: &mut Vec<i16>) {
+    out.clear();
+
+    let step = playback_rate(c.pitch) / SAMPLE_RATE as f32;
+
+    for _ in 0..count {
+        let sample = if c.st > 0 {
+            let bit = (*position as usize) % 128;
+            let set = (c.audio_pattern[bit / 8] >> (7 - (bit % 8))) & 1 != 0;
+
+            *position = (*position + step) % 128.0;
+
+            if set { AMPLITUDE } else { -AMPLITUDE }
+        } else {
+            *position = 0.0;
+
+            0
+        };
+
+        out.push(sample);
+        out.push(sample);
+    }
+}
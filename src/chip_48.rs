@@ -0,0 +1,35 @@
+use chip_8;
+use opcode::Opcode;
+use mmu::Mmu;
+use interpreter::{Runtime, Context};
+use axal;
+use quirks::Quirks;
+
+#[derive(Clone, Default)]
+pub struct Chip48 {
+    // CHIP-48 starts from the CHIP-8
+    //  It was a re-implementation for the HP48 that (unintentionally) changed the
+    //  semantics of a few instructions; SUPER-CHIP later inherited most of them.
+    chip_8: chip_8::Chip8,
+}
+
+impl Runtime for Chip48 {
+    fn quirks(&self) -> Quirks {
+        Quirks::chip_48()
+    }
+
+    fn reset(&mut self, c: &mut Context) {
+        // Reset CHIP-8
+        self.chip_8.reset(c);
+    }
+
+    fn execute(&mut self,
+               r: &mut axal::Runtime,
+               c: &mut Context,
+               m: &mut Mmu,
+               opcode: Opcode)
+               -> bool {
+        // CHIP-48 has the instructions of CHIP-8; it differs only in its quirks
+        self.chip_8.execute(r, c, m, opcode)
+    }
+}
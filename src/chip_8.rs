@@ -1,75 +1,77 @@
 use std::vec::Vec;
 use opcode::Opcode;
+use instruction::Instruction;
 use mmu::Mmu;
 use interpreter::{Runtime, Context};
-use rand::random;
 use axal;
 use axal::Key;
 
 // CHIP-8 hex keyboard -> modern keyboard
-const KEYBOARD_MAP: [Key; 0x10] = [Key::X, Key::Num1, Key::Num2, Key::Num3, Key::Q, Key::W,
+pub const KEYBOARD_MAP: [Key; 0x10] = [Key::X, Key::Num1, Key::Num2, Key::Num3, Key::Q, Key::W,
                                    Key::E, Key::A, Key::S, Key::D, Key::Z, Key::C, Key::Num4,
                                    Key::R, Key::F, Key::V];
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Chip8 {
 }
 
 impl Runtime for Chip8 {
     fn execute(&mut self,
-               r: &mut axal::Runtime,
+               _r: &mut axal::Runtime,
                c: &mut Context,
                m: &mut Mmu,
                opcode: Opcode)
                -> bool {
-        match opcode.unwrap() {
+        match Instruction::decode(opcode.extract_u16()) {
             // CLS
-            (0x0, 0x0, 0xE, 0x0) => {
+            Instruction::ClearScreen => {
                 // Clear the screen
-                for dot in &mut c.screen {
-                    *dot = false;
-                }
+                c.clear_screen();
             }
 
             // RET
-            (0x0, 0x0, 0xE, 0xE) => {
+            Instruction::Return => {
                 // Return from a subroutine
                 c.pc = c.stack_pop(m) as usize;
             }
 
             // JP u12
-            (0x1, ..) => {
+            Instruction::Jump(address) => {
                 // Jump to u12
-                c.pc = opcode.extract_u12() as usize;
+                c.pc = address as usize;
             }
 
             // CALL u12
-            (0x2, ..) => {
+            Instruction::Call(address) => {
                 // Call subroutine at u12
                 let pc = c.pc;
                 c.stack_push(m, pc as u16);
 
-                c.pc = opcode.extract_u12() as usize;
+                c.pc = address as usize;
             }
 
             // SE Vx, u8
-            (0x3, x, ..) => {
+            Instruction::SkipEqual(x, value) => {
                 // Skip next instruction if Vx == u8
-                if c.v[x as usize] == opcode.extract_u8() {
+                if c.v[x as usize] == value {
                     c.pc = c.pc.wrapping_add(2);
                 }
             }
 
             // SNE Vx, u8
-            (0x4, x, ..) => {
+            Instruction::SkipNotEqual(x, value) => {
                 // Skip next instruction if Vx != u8
-                if c.v[x as usize] != opcode.extract_u8() {
+                if c.v[x as usize] != value {
                     c.pc = c.pc.wrapping_add(2);
                 }
             }
 
             // SE Vx, Vy
-            (0x5, x, y, _) => {
+            //  The last nibble is not looked at; 5XY2 / 5XY3 (XO-CHIP's LD Vx-Vy)
+            //  are SE Vx, Vy without XO-CHIP.
+            Instruction::SkipEqualRegisters(x, y) |
+            Instruction::StoreRange(x, y) |
+            Instruction::RestoreRange(x, y) => {
                 // Skip next instruction if Vx == Vy
                 if c.v[x as usize] == c.v[y as usize] {
                     c.pc = c.pc.wrapping_add(2);
@@ -77,43 +79,55 @@ impl Runtime for Chip8 {
             }
 
             // LD Vx, u8
-            (0x6, x, ..) => {
+            Instruction::Load(x, value) => {
                 // Set Vx = u8
-                c.v[x as usize] = opcode.extract_u8();
+                c.v[x as usize] = value;
             }
 
             // ADD Vx, u8
-            (0x7, x, ..) => {
+            Instruction::Add(x, value) => {
                 // Set Vx = Vx + u8
-                c.v[x as usize] = c.v[x as usize].wrapping_add(opcode.extract_u8());
+                c.v[x as usize] = c.v[x as usize].wrapping_add(value);
             }
 
             // LD Vx, Vy
-            (0x8, x, y, 0x0) => {
+            Instruction::Move(x, y) => {
                 // Set Vx = Vy
                 c.v[x as usize] = c.v[y as usize];
             }
 
             // OR Vx, Vy
-            (0x8, x, y, 0x1) => {
-                // Set Vx = Vx OR Vy
+            Instruction::Or(x, y) => {
+                // Set Vx = Vx OR Vy; VF is reset if the variant does so (VF reset quirk)
                 c.v[x as usize] |= c.v[y as usize];
+
+                if c.quirks.vf_reset {
+                    c.v[0xF] = 0;
+                }
             }
 
             // AND Vx, Vy
-            (0x8, x, y, 0x2) => {
-                // Set Vx = Vx AND Vy
+            Instruction::And(x, y) => {
+                // Set Vx = Vx AND Vy; VF is reset if the variant does so (VF reset quirk)
                 c.v[x as usize] &= c.v[y as usize];
+
+                if c.quirks.vf_reset {
+                    c.v[0xF] = 0;
+                }
             }
 
             // XOR Vx, Vy
-            (0x8, x, y, 0x3) => {
-                // Set Vx = Vx XOR Vy
+            Instruction::Xor(x, y) => {
+                // Set Vx = Vx XOR Vy; VF is reset if the variant does so (VF reset quirk)
                 c.v[x as usize] ^= c.v[y as usize];
+
+                if c.quirks.vf_reset {
+                    c.v[0xF] = 0;
+                }
             }
 
             // ADD Vx, Vy
-            (0x8, x, y, 0x4) => {
+            Instruction::AddRegisters(x, y) => {
                 // Set Vx = Vx + Vy; Set VF = <carry>
                 let vx = c.v[x as usize] as u16;
                 let vy = c.v[y as usize] as u16;
@@ -124,7 +138,7 @@ impl Runtime for Chip8 {
             }
 
             // SUB Vx, Vy
-            (0x8, x, y, 0x5) => {
+            Instruction::Sub(x, y) => {
                 // Set Vx = Vx - Vy; Set VF = !<borrow>
                 let vx = c.v[x as usize];
                 let vy = c.v[y as usize];
@@ -134,14 +148,17 @@ impl Runtime for Chip8 {
             }
 
             // SHR Vx
-            (0x8, x, _, 0x6) => {
-                // Set Vx = Vx SHR 1; Set VF = Vx BIT 1
-                c.v[0xF] = c.v[x as usize] & 1;
-                c.v[x as usize] >>= 1;
+            Instruction::ShiftRight(x, y) => {
+                // Set Vx = Vy SHR 1; Set VF = Vy BIT 0
+                //  Vx is shifted in place instead of Vy unless the variant shifts Vy (shift quirk).
+                let v = c.v[if c.quirks.shift_vy { y } else { x } as usize];
+
+                c.v[x as usize] = v >> 1;
+                c.v[0xF] = v & 1;
             }
 
             // SUBN Vx, Vy
-            (0x8, x, y, 0x7) => {
+            Instruction::SubN(x, y) => {
                 // Set Vx = Vy - Vx; Set VF = !<borrow>
                 let vx = c.v[x as usize];
                 let vy = c.v[y as usize];
@@ -151,14 +168,17 @@ impl Runtime for Chip8 {
             }
 
             // SHL Vx, Vy
-            (0x8, x, y, 0xE) => {
+            Instruction::ShiftLeft(x, y) => {
                 // Set Vx = Vy SHL 1; Set VF = Vy BIT 7
-                c.v[0xF] = c.v[y as usize] >> 7;
-                c.v[x as usize] = c.v[y as usize] << 1;
+                //  Vx is shifted in place instead of Vy unless the variant shifts Vy (shift quirk).
+                let v = c.v[if c.quirks.shift_vy { y } else { x } as usize];
+
+                c.v[x as usize] = v << 1;
+                c.v[0xF] = v >> 7;
             }
 
             // SNE Vx, Vy
-            (0x9, x, y, 0) => {
+            Instruction::SkipNotEqualRegisters(x, y) => {
                 // Skip next instruction if Vx != Vy
                 if c.v[x as usize] != c.v[y as usize] {
                     c.pc = c.pc.wrapping_add(2);
@@ -166,112 +186,162 @@ impl Runtime for Chip8 {
             }
 
             // LD I, u12
-            (0xA, ..) => {
+            Instruction::LoadI(address) => {
                 // Set I = u12
-                c.i = opcode.extract_u12() as usize;
+                c.i = address as usize;
             }
 
             // JP V0, u12
-            (0xB, ..) => {
+            Instruction::JumpOffset(address) => {
                 // Jump to u12 + V0
-                c.pc = (opcode.extract_u12().wrapping_add(c.v[0] as u16)) as usize;
+                //  Vx is added instead (where x is the high nibble of u12) if the
+                //  variant does so (jump quirk).
+                let x = (address >> 8) as usize;
+                let v = c.v[if c.quirks.jump_vx { x } else { 0 }];
+
+                c.pc = (address.wrapping_add(v as u16)) as usize;
             }
 
             // RND Vx, u8
-            (0xC, x, ..) => {
+            Instruction::Random(x, mask) => {
                 // Set Vx = <random u8> AND u8
-                c.v[x as usize] = random::<u8>() & opcode.extract_u8();
+                c.v[x as usize] = c.rng.next_u8() & mask;
             }
 
             // SHOW Vx, Vy, u4
-            (0xD, x, y, n) => {
+            Instruction::Draw(x, y, n) => {
                 // Display n-byte sprite starting in memory at I at (Vx, Vy)
                 // Set VF = <collision>
+                if c.wait_for_vblank() {
+                    return true;
+                }
+
+                c.draws += 1;
 
-                let x = c.v[x as usize] as usize;
-                let y = c.v[y as usize] as usize;
+                //  The coordinates wrap; the sprite is clipped at the edges of the screen
+                //  or wraps around if the variant does so (clipping quirk).
+                let x = c.v[x as usize] as usize % c.screen_width;
+                let y = c.v[y as usize] as usize % c.screen_height;
 
                 // VF is cleared at the start of DRW so collision can be set easily
                 c.v[0xF] = 0;
+                c.dirty = true;
 
                 for i in 0..(n as usize) {
+                    if c.quirks.clip_sprites && y + i >= c.screen_height {
+                        break;
+                    }
+
                     let sy = (y + i) % c.screen_height;
 
                     for j in 0..8 {
+                        if c.quirks.clip_sprites && x + j >= c.screen_width {
+                            break;
+                        }
+
                         let sx = (x + j) % c.screen_width;
 
                         // Get VRAM offset
                         let offset = sy * c.screen_width + sx;
 
-                        // Get _current_ dot in the screen
+                        // Get _current_ dot in the screen (first plane)
                         let dot = &mut c.screen[offset];
-                        let was_set = *dot;
+                        let was_set = (*dot & 1) != 0;
 
                         // Read memory to get the _set_ value
                         let dot_set = (m.read(c.i + i) >> (7 - j)) & 1;
 
                         // XOR to determine the new state of the dot
-                        *dot = ((*dot as u8) ^ dot_set) != 0;
+                        *dot ^= dot_set;
 
                         // VF is set to indicate the transition 1 -> 0
-                        c.v[0xF] |= (was_set && !*dot) as u8;
+                        c.v[0xF] |= (was_set && (*dot & 1) == 0) as u8;
                     }
                 }
             }
 
             // SKP Vx
-            (0xE, x, 0x9, 0xE) => {
+            Instruction::SkipKey(x) => {
                 // Skip next instruction if key with the value of Vx is pressed
-                if r.input_keyboard_state(0, KEYBOARD_MAP[c.v[x as usize] as usize]) {
+                if c.is_key_down(0, c.v[x as usize]) {
                     c.pc = c.pc.wrapping_add(2);
                 }
             }
 
             // SKNP Vx
-            (0xE, x, 0xA, 0x1) => {
+            Instruction::SkipNotKey(x) => {
                 // Skip next instruction if key with the value of Vx is not pressed
-                if !r.input_keyboard_state(0, KEYBOARD_MAP[c.v[x as usize] as usize]) {
+                if !c.is_key_down(0, c.v[x as usize]) {
                     c.pc = c.pc.wrapping_add(2);
                 }
             }
 
             // LD Vx, DT
-            (0xF, x, 0x0, 0x7) => {
+            Instruction::LoadDelay(x) => {
                 // Set Vx = DT
                 c.v[x as usize] = c.dt;
             }
 
+            // LD Vx, K
+            Instruction::WaitKey(x) => {
+                // Wait for a key press, store the value of the key in Vx
+                //  The key is registered when it is released if the variant does so
+                //  (key release quirk). Until then this instruction is repeated.
+                let down = c.input[0];
+
+                if !c.key_wait {
+                    // Keys that are already pressed don't count until released
+                    c.key_wait = true;
+                    c.keys_down = down;
+                }
+
+                let keys = if c.quirks.key_release {
+                    c.keys_down & !down
+                } else {
+                    down & !c.keys_down
+                };
+
+                if keys != 0 {
+                    c.v[x as usize] = keys.trailing_zeros() as u8;
+                    c.key_wait = false;
+                } else {
+                    c.keys_down = down;
+                    c.pc = c.pc.wrapping_sub(2);
+                }
+            }
+
             // LD DT, Vx
-            (0xF, x, 0x1, 0x5) => {
+            Instruction::SetDelay(x) => {
                 // Set DT = Vx
                 c.dt = c.v[x as usize];
             }
 
             // LD ST, Vx
-            (0xF, x, 0x1, 0x8) => {
+            Instruction::SetSound(x) => {
                 // Set ST = Vx
                 c.st = c.v[x as usize];
             }
 
             // ADD I, Vx
-            (0xF, x, 0x1, 0xE) => {
+            Instruction::AddI(x) => {
                 // Set I = I + Vx
-                let r: u32 = c.i as u32 + c.v[x as usize] as u32;
+                //  I wraps around at the end of RAM (4 KiB; 64 KiB for XO-CHIP)
+                let r = c.i + c.v[x as usize] as usize;
 
-                c.i = (r & 0xFFF) as usize;
+                c.i = r & (m.size() - 1);
 
                 // If buffer overflow, register > VF must be set to 1, otherwise 0.
-                c.v[0xF] = (r > 0xFFF) as u8;
+                c.v[0xF] = (r >= m.size()) as u8;
             }
 
             // LD [I], FONT Vx
-            (0xF, x, 0x2, 0x9) => {
+            Instruction::LoadFont(x) => {
                 // Set I = location of sprite for digit Vx.
-                c.i = (c.v[x as usize] * 5) as usize;
+                c.i = (c.v[x as usize] as usize & 0xF) * 5;
             }
 
             // LD [I], BCD Vx
-            (0xF, x, 0x3, 0x3) => {
+            Instruction::LoadBcd(x) => {
                 // Store BCD representation of Vx in memory locations I, I+1, and I+2.
                 let r = c.v[x as usize];
                 let i = c.i;
@@ -282,25 +352,25 @@ impl Runtime for Chip8 {
             }
 
             // LD [I], Vx
-            (0xF, x, 0x5, 0x5) => {
+            Instruction::Store(x) => {
                 // Store registers V0 through Vx in memory starting at location I.
-                for j in 0..(x + 1) {
-                    let r = c.v[j as usize];
-
-                    m.write(c.i, r);
-
-                    c.i += 1;
+                //  I is left incremented as the variant does (load/store quirk).
+                for j in 0..(x as usize + 1) {
+                    m.write(c.i + j, c.v[j]);
                 }
+
+                c.i += c.quirks.load_store_increment.amount(x as usize);
             }
 
             // LD Vx, [I]
-            (0xF, x, 0x6, 0x5) => {
+            Instruction::Restore(x) => {
                 // Read registers V0 through Vx from memory starting at location I.
-                for j in 0..(x + 1) {
-                    c.v[j as usize] = m.read(c.i);
-
-                    c.i += 1;
+                //  I is left incremented as the variant does (load/store quirk).
+                for j in 0..(x as usize + 1) {
+                    c.v[j] = m.read(c.i + j);
                 }
+
+                c.i += c.quirks.load_store_increment.amount(x as usize);
             }
 
             _ => {
@@ -0,0 +1,151 @@
+use chip_8;
+use opcode::Opcode;
+use mmu::Mmu;
+use interpreter::{Runtime, Context};
+use axal;
+use error::Error;
+use savestate::{Reader, Writer};
+
+#[derive(Clone, Default)]
+pub struct Chip8e {
+    // CHIP-8E starts from the CHIP-8
+    chip_8: chip_8::Chip8,
+
+    // Set while `FX4F` waits for DT to reach 0
+    delaying: bool,
+}
+
+impl Runtime for Chip8e {
+    fn reset(&mut self, c: &mut Context) {
+        // Reset CHIP-8
+        self.chip_8.reset(c);
+
+        self.delaying = false;
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        w.bool(self.delaying);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error> {
+        self.delaying = r.bool()?;
+
+        Ok(())
+    }
+
+    fn execute(&mut self,
+               r: &mut axal::Runtime,
+               c: &mut Context,
+               m: &mut Mmu,
+               opcode: Opcode)
+               -> bool {
+        match opcode.unwrap() {
+            // STOP
+            (0x0, 0x0, 0xE, 0xD) => {
+                // Stop the program; halt until reset
+                c.halted = true;
+            }
+
+            // NOP
+            (0x0, 0x0, 0xF, 0x2) => {}
+
+            // WAIT DT
+            (0x0, 0x1, 0x5, 0x1) => {
+                // Wait until DT is 0
+                if c.dt != 0 {
+                    c.pc = c.pc.wrapping_sub(2);
+                }
+            }
+
+            // SKIP
+            (0x0, 0x1, 0x8, 0x8) => {
+                // Skip the next instruction
+                c.pc = c.pc.wrapping_add(2);
+            }
+
+            // SGT Vx, Vy
+            (0x5, x, y, 0x1) => {
+                // Skip next instruction if Vx > Vy
+                if c.v[x as usize] > c.v[y as usize] {
+                    c.pc = c.pc.wrapping_add(2);
+                }
+            }
+
+            // LD [I], Vx - Vy
+            (0x5, x, y, 0x2) => {
+                // Store registers Vx through Vy in memory starting at location I; I is left
+                // pointing after the last register stored
+                for j in (x as usize)..(y as usize + 1) {
+                    m.write(c.i, c.v[j]);
+                    c.i += 1;
+                }
+            }
+
+            // LD Vx - Vy, [I]
+            (0x5, x, y, 0x3) => {
+                // Read registers Vx through Vy from memory starting at location I; I is left
+                // pointing after the last register read
+                for j in (x as usize)..(y as usize + 1) {
+                    c.v[j] = m.read(c.i);
+                    c.i += 1;
+                }
+            }
+
+            // JB u8
+            (0xB, 0xB, ..) => {
+                // Branch u8 bytes backwards
+                c.pc = c.pc.wrapping_sub(opcode.extract_u8() as usize);
+            }
+
+            // JF u8
+            (0xB, 0xF, ..) => {
+                // Branch u8 bytes forwards
+                c.pc = c.pc.wrapping_add(opcode.extract_u8() as usize);
+            }
+
+            // OUT Vx
+            (0xF, _, 0x0, 0x3) => {
+                // Output Vx to port 3; there is nothing connected
+            }
+
+            // SKIP Vx
+            (0xF, x, 0x1, 0xB) => {
+                // Skip Vx bytes
+                c.pc = c.pc.wrapping_add(c.v[x as usize] as usize);
+            }
+
+            // DELAY Vx
+            (0xF, x, 0x4, 0xF) => {
+                // Set DT = Vx and wait until it is 0
+                if !self.delaying {
+                    c.dt = c.v[x as usize];
+                    self.delaying = true;
+                }
+
+                if c.dt != 0 {
+                    c.pc = c.pc.wrapping_sub(2);
+                } else {
+                    self.delaying = false;
+                }
+            }
+
+            // STROBE
+            (0xF, _, 0xE, 0x3) => {
+                // Wait for the strobe on port 3; there is nothing connected so never wait
+            }
+
+            // IN Vx
+            (0xF, x, 0xE, 0x7) => {
+                // Read port 3 into Vx; there is nothing connected
+                c.v[x as usize] = 0;
+            }
+
+            _ => {
+                // Unhandled by CHIP-8E
+                return self.chip_8.execute(r, c, m, opcode);
+            }
+        }
+
+        true
+    }
+}
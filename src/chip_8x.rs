@@ -1,3 +1,4 @@
+use std::cmp;
 use std::vec::Vec;
 
 use chip_8;
@@ -5,50 +6,105 @@ use opcode::Opcode;
 use mmu::Mmu;
 use interpreter::{Runtime, Context};
 use axal;
+use video;
+use error::Error;
+use savestate::{Reader, Writer};
 
-#[derive(Default)]
+// Background colors (as palette indices) in the order `02A0` steps through them:
+// Blue -> Black -> Green -> Red -> (Blue)
+const BACKGROUND_CYCLE: [usize; 4] = [2, 0, 4, 1];
+
+// Zones of the color lens set by `BXY0` are 8x4 dots
+const ZONE_WIDTH: usize = 8;
+const ZONE_HEIGHT: usize = 4;
+
+#[derive(Clone, Default)]
 pub struct Chip8x {
     // CHIP-8X starts from the CHIP-8
     chip_8: chip_8::Chip8,
 
-    // Maps CHIP-8X color index to a R3_G3_B2 color
+    // Maps CHIP-8X color index to a 0xRRGGBB color
     // TODO: Make this configurable
-    palette: Vec<u8>,
+    palette: Vec<u32>,
 
     // CHIP-8X defines a background color (color when dots are off)
-    background_color: u8,
+    background_color: u32,
+
+    // Position of the background color in BACKGROUND_CYCLE
+    background_index: usize,
 
     // CHIP-8X defines a color "lens" that defines
     // the colors of dots from the screen
-    color_lens: Vec<u8>,
+    color_lens: Vec<u32>,
+}
+
+impl Chip8x {
+    // Set the color of the `width`x`height` region at (x, y) of the color lens
+    //  The region is clipped at the edges of the screen.
+    fn set_color(&mut self,
+                 c: &mut Context,
+                 x: usize,
+                 y: usize,
+                 width: usize,
+                 height: usize,
+                 color: u32) {
+        for ly in y..cmp::min(y + height, c.screen_height) {
+            for lx in x..cmp::min(x + width, c.screen_width) {
+                self.color_lens[ly * c.screen_width + lx] = color;
+            }
+        }
+
+        c.dirty = true;
+    }
 }
 
 impl Runtime for Chip8x {
+    fn save_state(&self, w: &mut Writer) {
+        w.u32s(&self.palette);
+        w.u32(self.background_color);
+        w.usize(self.background_index);
+        w.u32s(&self.color_lens);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error> {
+        self.palette = r.u32s()?;
+        self.background_color = r.u32()?;
+        self.background_index = r.usize()?;
+        self.color_lens = r.u32s()?;
+        // The screen of the CHIP-8X is 64x32
+        if self.palette.len() != 8 || self.background_index >= BACKGROUND_CYCLE.len() ||
+           self.color_lens.len() != 64 * 32 {
+            return Err(Error::InvalidState);
+        }
+
+        Ok(())
+    }
+
     fn configure(&mut self, c: &mut Context) {
         // Initialize palette
         self.palette = vec![// Black
-                            0b000_000_00,
+                            0x000000,
 
                             // Red
-                            0b111_000_00,
+                            0xFF0000,
 
                             // Blue
-                            0b000_000_11,
+                            0x0000FF,
 
                             // Violet
-                            0b111_000_11,
+                            0xFF00FF,
 
                             // Green
-                            0b000_111_00,
+                            0x00FF00,
 
                             // Yellow
-                            0b111_111_00,
+                            0xFFFF00,
 
                             // Aqua
-                            0b000_111_11,
+                            0x00FFFF,
 
                             // White
-                            0b111_111_11];
+                            0xFFFFFF];
 
         // Initialize the color lens
         self.color_lens.resize(c.screen.len(), self.palette[7]);
@@ -58,8 +114,9 @@ impl Runtime for Chip8x {
         // Reset CHIP-8
         self.chip_8.reset(c);
 
-        // Reset background color
-        self.background_color = self.palette[0];
+        // Reset background color (to black)
+        self.background_index = 1;
+        self.background_color = self.palette[BACKGROUND_CYCLE[self.background_index]];
 
         // Clear the color lens
         for c in &mut self.color_lens {
@@ -70,8 +127,13 @@ impl Runtime for Chip8x {
         c.pc = 0x300;
     }
 
+    fn rom_address(&self) -> usize {
+        // CHIP-8X programs start at $300 (past the extended interpreter)
+        0x300
+    }
+
     fn insert_rom(&mut self, m: &mut Mmu, buffer: &[u8]) {
-        m.write_all(0x300, buffer);
+        m.write_all(self.rom_address(), buffer);
     }
 
     fn update_framebuffer(&mut self, c: &mut Context) {
@@ -83,15 +145,12 @@ impl Runtime for Chip8x {
             for x in 0..c.screen_width {
                 let offset = offset_y + x;
 
-                // Get dot from screen
-                let dot = c.screen[offset];
+                // Get (phase of) dot from screen
+                let phase = c.phase[offset];
 
                 // Blit to framebuffer
-                c.framebuffer[offset] = if dot {
-                    self.color_lens[offset]
-                } else {
-                    self.background_color
-                };
+                c.framebuffer[offset] =
+                    video::blend(self.background_color, self.color_lens[offset], phase.level);
             }
         }
     }
@@ -103,34 +162,69 @@ impl Runtime for Chip8x {
                opcode: Opcode)
                -> bool {
         match opcode.unwrap() {
+            // BGCOL
             (0x0, 0x2, 0xA, 0x0) => {
-                println!("unimplemented: {}", opcode);
+                // Step the background color (-> Blue -> Black -> Green -> Red ->)
+                self.background_index = (self.background_index + 1) % BACKGROUND_CYCLE.len();
+                self.background_color = self.palette[BACKGROUND_CYCLE[self.background_index]];
+
+                c.dirty = true;
             }
 
+            // ADD Vx, Vy (CHIP-8X)
             (0x5, x, y, 0x1) => {
-                println!("unimplemented: {}", opcode);
+                // Set Vx = Vx + Vy; VF is unaffected
+                c.v[x as usize] = c.v[x as usize].wrapping_add(c.v[y as usize]);
             }
 
+            // COL Vx, Vy
             (0xB, x, y, 0x0) => {
                 // Set foreground color of 1 or more 8x4 dot zones
                 let vx = c.v[x as usize];
-                let vx1 = c.v[(x + 1) as usize];
-                let color = c.v[y as usize] & 0b1111;
+                let vx1 = c.v[((x + 1) & 0xF) as usize];
+                let color = self.palette[(c.v[y as usize] & 0b111) as usize];
 
                 // The lower 4 bits of `VX`/`V[X+1]` is the horizontal/vertical zone index (0-7).
                 let horz = (vx & 0b1111) as usize;
                 let vert = (vx1 & 0b1111) as usize;
 
                 // The upper 4 bits of `VX`/`V[X+1]` is the horizontal/vertical size minus 1.
-                let width = (vx >> 4) + 1;
-                let height = (vx1 >> 4) + 1;
-
-                // TODO: Set color on the color lens
+                let width = ((vx >> 4) + 1) as usize;
+                let height = ((vx1 >> 4) + 1) as usize;
+
+                self.set_color(c,
+                               horz * ZONE_WIDTH,
+                               vert * ZONE_HEIGHT,
+                               width * ZONE_WIDTH,
+                               height * ZONE_HEIGHT,
+                               color);
             }
 
+            // COL Vx, Vy, N
             (0xB, x, y, n) => {
                 // Set foreground color of 1 or more 8x1 dot zones
-                println!("unimplemented: {}", opcode);
+                //  `VX`/`V[X+1]` is the horizontal/vertical dot index; the zone is 8xN dots.
+                let dx = (c.v[x as usize] & 0x3F) as usize;
+                let dy = (c.v[((x + 1) & 0xF) as usize] & 0x1F) as usize;
+                let color = self.palette[(c.v[y as usize] & 0b111) as usize];
+
+                self.set_color(c, dx, dy, 8, n as usize, color);
+            }
+
+            // SKP2 Vx
+            (0xE, x, 0xF, 0x2) => {
+                // Skip next instruction if key with the value of Vx is pressed (second keypad)
+                if c.is_key_down(1, c.v[x as usize]) {
+                    c.pc = c.pc.wrapping_add(2);
+                }
+            }
+
+            // SKNP2 Vx
+            (0xE, x, 0xF, 0x5) => {
+                // Skip next instruction if key with the value of Vx is not pressed (second keypad)
+                if !c.is_key_down(1, c.v[x as usize]) {
+                    c.pc = c.pc.wrapping_add(2);
+                }
             }
 
             _ => {
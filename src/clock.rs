@@ -0,0 +1,95 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Instant;
+
+// Source of the ticks that clock DT / ST
+//  The interpreter asks once per frame (at the vertical blank) how many ticks
+//  have passed; a virtual clock makes time advance only when told to (eg. to
+//  verify timer behavior without sleeping).
+pub trait Clock {
+    // Number of ticks since the last call
+    fn ticks(&mut self) -> u32;
+}
+
+// One tick per frame (so the timers run at the frame rate); deterministic (the default)
+#[derive(Default)]
+pub struct FrameClock;
+
+impl Clock for FrameClock {
+    fn ticks(&mut self) -> u32 {
+        1
+    }
+}
+
+// 60 Hz ticks that have passed by the wall clock
+//  Emulation is no longer deterministic; pausing and fast-forward affect the
+//  timers as they would on hardware left running.
+#[derive(Default)]
+pub struct RealTimeClock {
+    // Time (in ns) since the last tick and when it was last measured
+    elapsed: u64,
+    instant: Option<Instant>,
+}
+
+impl Clock for RealTimeClock {
+    fn ticks(&mut self) -> u32 {
+        let now = Instant::now();
+        if let Some(instant) = self.instant {
+            let elapsed = now.duration_since(instant);
+            self.elapsed += (elapsed.as_secs() * 1_000_000_000) + (elapsed.subsec_nanos() as u64);
+        }
+
+        self.instant = Some(now);
+
+        // 1/60 s => 16_666_666 ns
+        let ticks = self.elapsed / 16_666_666;
+        self.elapsed %= 16_666_666;
+
+        ticks as u32
+    }
+}
+
+// Ticks only when advanced (by the owner of a clone)
+#[derive(Clone, Default)]
+pub struct VirtualClock {
+    pending: Rc<Cell<u32>>,
+}
+
+impl VirtualClock {
+    // Advance time by `ticks` 60 Hz ticks; applied at the next frame
+    pub fn advance(&self, ticks: u32) {
+        self.pending.set(self.pending.get() + ticks);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn ticks(&mut self) -> u32 {
+        self.pending.replace(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_clock_ticks_when_advanced() {
+        let clock: VirtualClock = Default::default();
+        let mut ticks: Box<Clock> = Box::new(clock.clone());
+
+        assert_eq!(ticks.ticks(), 0);
+
+        clock.advance(2);
+        clock.advance(3);
+        assert_eq!(ticks.ticks(), 5);
+        assert_eq!(ticks.ticks(), 0);
+    }
+
+    #[test]
+    fn frame_clock_ticks_once() {
+        let mut clock = FrameClock;
+
+        assert_eq!(clock.ticks(), 1);
+        assert_eq!(clock.ticks(), 1);
+    }
+}
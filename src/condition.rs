@@ -0,0 +1,345 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use error::Error;
+use interpreter::Context;
+use mmu::Mmu;
+
+// Condition of a breakpoint; an expression over the registers, timers and RAM
+//  eg. `v[3] == 0x10 && i > 0x300`
+//   Values: numbers (`16`, `0x10`, `$10`), `v0` .. `vf` (or `v[n]`), `i`, `pc`,
+//           `sp`, `dt`, `st` and bytes of RAM (`ram[i + 2]`)
+//   Operators (by precedence, lowest first): `||`, `&&`, `==` `!=` `<` `<=`
+//           `>` `>=`, `|`, `&`, `+` `-`, `!` (and parentheses)
+//  Comparisons (and `!`) are 1 if they hold and 0 if not; the condition holds
+//  if it is not 0.
+#[derive(Clone, Debug)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    V(Box<Expr>),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    Ram(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut parser = Parser { chars: source.chars().peekable() };
+        let expr = parser.parse_expr(0)?;
+
+        parser.skip_whitespace();
+        if let Some(c) = parser.chars.next() {
+            return Err(invalid(&format!("unexpected '{}'", c)));
+        }
+
+        Ok(Condition {
+            source: source.to_string(),
+            expr: expr,
+        })
+    }
+
+    // True if the condition holds for the program in `c` (with RAM in `m`)
+    pub fn eval(&self, c: &Context, m: &Mmu) -> bool {
+        eval(&self.expr, c, m) != 0
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidCondition(message.to_string())
+}
+
+fn eval(expr: &Expr, c: &Context, m: &Mmu) -> i64 {
+    match *expr {
+        Expr::Number(n) => n,
+        Expr::V(ref x) => c.v[(eval(x, c, m) & 0xF) as usize] as i64,
+        Expr::I => c.i as i64,
+        Expr::Pc => c.pc as i64,
+        Expr::Sp => c.sp as i64,
+        Expr::Dt => c.dt as i64,
+        Expr::St => c.st as i64,
+        Expr::Ram(ref address) => m.peek(eval(address, c, m) as usize) as i64,
+        Expr::Not(ref a) => (eval(a, c, m) == 0) as i64,
+
+        Expr::Binary(op, ref a, ref b) => {
+            let a = eval(a, c, m);
+
+            // `||` and `&&` don't evaluate the right side unless needed
+            match op {
+                Op::Or if a != 0 => return 1,
+                Op::And if a == 0 => return 0,
+                _ => {}
+            }
+
+            let b = eval(b, c, m);
+            match op {
+                Op::Or | Op::And => (b != 0) as i64,
+                Op::Eq => (a == b) as i64,
+                Op::Ne => (a != b) as i64,
+                Op::Lt => (a < b) as i64,
+                Op::Le => (a <= b) as i64,
+                Op::Gt => (a > b) as i64,
+                Op::Ge => (a >= b) as i64,
+                Op::BitOr => a | b,
+                Op::BitAnd => a & b,
+                Op::Add => a.wrapping_add(b),
+                Op::Sub => a.wrapping_sub(b),
+            }
+        }
+    }
+}
+
+// Binary operators by precedence (lowest first)
+const PRECEDENCE: &'static [&'static [(&'static str, Op)]] =
+    &[&[("||", Op::Or)],
+      &[("&&", Op::And)],
+      &[("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt),
+        (">", Op::Gt)],
+      &[("|", Op::BitOr)],
+      &[("&", Op::BitAnd)],
+      &[("+", Op::Add), ("-", Op::Sub)]];
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    // Consume `token` if it is next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+
+        let mut ahead = self.chars.clone();
+        for c in token.chars() {
+            if ahead.next() != Some(c) {
+                return false;
+            }
+        }
+
+        // `|` and `&` are not the start of `||` and `&&`
+        if (token == "|" || token == "&") && ahead.peek() == token.chars().next().as_ref() {
+            return false;
+        }
+
+        self.chars = ahead;
+
+        true
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(invalid(&format!("expected '{}'", token)))
+        }
+    }
+
+    fn parse_expr(&mut self, level: usize) -> Result<Expr, Error> {
+        if level == PRECEDENCE.len() {
+            return self.parse_unary();
+        }
+
+        let mut expr = self.parse_expr(level + 1)?;
+        'operators: loop {
+            for &(token, op) in PRECEDENCE[level] {
+                if self.eat(token) {
+                    let rhs = self.parse_expr(level + 1)?;
+                    expr = Expr::Binary(op, Box::new(expr), Box::new(rhs));
+
+                    continue 'operators;
+                }
+            }
+
+            return Ok(expr);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, Error> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        if self.eat("(") {
+            let expr = self.parse_expr(0)?;
+            self.expect(")")?;
+
+            return Ok(expr);
+        }
+
+        if self.eat("$") {
+            return self.parse_number(16);
+        }
+
+        match self.chars.peek().cloned() {
+            Some(c) if c.is_digit(10) => {
+                if self.eat("0x") || self.eat("0X") {
+                    self.parse_number(16)
+                } else {
+                    self.parse_number(10)
+                }
+            }
+
+            Some(c) if c.is_alphabetic() => self.parse_name(),
+            Some(c) => Err(invalid(&format!("unexpected '{}'", c))),
+            None => Err(invalid("unexpected end")),
+        }
+    }
+
+    fn parse_number(&mut self, radix: u32) -> Result<Expr, Error> {
+        let mut digits = String::new();
+        while let Some(c) = self.chars.peek().cloned() {
+            if !c.is_digit(radix) {
+                break;
+            }
+
+            digits.push(c);
+            self.chars.next();
+        }
+
+        i64::from_str_radix(&digits, radix)
+            .map(Expr::Number)
+            .map_err(|_| invalid("invalid number"))
+    }
+
+    fn parse_name(&mut self) -> Result<Expr, Error> {
+        let mut name = String::new();
+        while let Some(c) = self.chars.peek().cloned() {
+            if !c.is_alphanumeric() {
+                break;
+            }
+
+            name.push(c.to_ascii_lowercase());
+            self.chars.next();
+        }
+
+        let expr = match &name[..] {
+            "i" => Expr::I,
+            "pc" => Expr::Pc,
+            "sp" => Expr::Sp,
+            "dt" => Expr::Dt,
+            "st" => Expr::St,
+
+            "v" | "ram" => {
+                self.expect("[")?;
+                let index = Box::new(self.parse_expr(0)?);
+                self.expect("]")?;
+
+                if name == "v" {
+                    Expr::V(index)
+                } else {
+                    Expr::Ram(index)
+                }
+            }
+
+            _ => {
+                // `v0` .. `vf`
+                let x = if name.len() == 2 && name.starts_with('v') {
+                    i64::from_str_radix(&name[1..], 16).ok()
+                } else {
+                    None
+                };
+
+                match x {
+                    Some(x) => Expr::V(Box::new(Expr::Number(x))),
+                    None => return Err(invalid(&format!("unknown name '{}'", name))),
+                }
+            }
+        };
+
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holds(source: &str, c: &Context, m: &Mmu) -> bool {
+        Condition::parse(source).unwrap().eval(c, m)
+    }
+
+    #[test]
+    fn eval_registers_and_ram() {
+        let mut c: Context = Default::default();
+        let mut m: Mmu = Default::default();
+        c.v[3] = 0x10;
+        c.v[0xF] = 1;
+        c.i = 0x300;
+        c.dt = 5;
+        m.poke(0x302, 0xAB);
+
+        assert!(holds("v[3] == 0x10", &c, &m));
+        assert!(holds("v3 == $10 && vf", &c, &m));
+        assert!(holds("i >= 0x300 && i < 0x301", &c, &m));
+        assert!(holds("ram[i + 2] == 0xAB", &c, &m));
+        assert!(holds("dt == 5 || st == 5", &c, &m));
+        assert!(holds("!st", &c, &m));
+        assert!(!holds("v[3] != 16", &c, &m));
+        assert!(!holds("pc > 0 && dt", &c, &m));
+    }
+
+    #[test]
+    fn precedence() {
+        let c: Context = Default::default();
+        let m: Mmu = Default::default();
+
+        assert!(holds("1 + 2 == 3", &c, &m));
+        assert!(holds("6 & 3 == 2", &c, &m));
+        assert!(holds("4 | 1 - 1 == 4", &c, &m));
+        assert!(holds("0 && 1 || 1", &c, &m));
+        assert!(!holds("0 && (1 || 1)", &c, &m));
+        assert!(holds("!(1 - 1)", &c, &m));
+    }
+
+    #[test]
+    fn reject_invalid() {
+        for source in &["", "v[3] ==", "(1", "1 2", "x == 1", "ram 1", "0x", "v3 = 1"] {
+            assert!(Condition::parse(source).is_err(), "{:?} parsed", source);
+        }
+    }
+
+    #[test]
+    fn display_source() {
+        let condition = Condition::parse("v[3] == 0x10 && i > 0x300").unwrap();
+
+        assert_eq!(condition.to_string(), "v[3] == 0x10 && i > 0x300");
+    }
+}
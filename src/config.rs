@@ -0,0 +1,279 @@
+use std::path::Path;
+
+use axal::Key;
+use toml;
+
+use chip_8;
+use interpreter::Mode;
+use quirks::Quirks;
+use storage;
+use video::Color;
+
+// Settings loaded from `xchip.toml` (in the config directory) and from the
+// `.toml` file next to the ROM (eg. `game.toml` for `game.ch8`); the latter
+// takes precedence
+//
+//     variant = "super-chip"
+//     # quirks = "schip-1.1"         # a preset, or a table (below)
+//     palette = ["#000000", "#FFFFFF", "#AAAAAA", "#555555"]
+//     speed = 30                     # instructions per frame
+//
+//     [quirks]
+//     preset = "vip"
+//     clip_sprites = false
+//     load_store_increment = "x"
+//
+//     [keys]
+//     0 = "x"
+//     1 = "1"
+//
+#[derive(Default)]
+pub struct Config {
+    pub variant: Option<Mode>,
+    pub quirks: Option<Quirks>,
+    pub palette: Option<[Color; 4]>,
+    pub instructions_per_frame: Option<usize>,
+    pub keymap: Option<[Key; 16]>,
+}
+
+impl Config {
+    // Load the settings for the ROM at `filename`
+    pub fn load(filename: &str) -> Self {
+        let global = storage::config_dir()
+            .and_then(|dir| Config::read(&dir.join("xchip.toml")))
+            .unwrap_or_default();
+
+        let rom = Config::read(&Path::new(filename).with_extension("toml")).unwrap_or_default();
+
+        Config {
+            variant: rom.variant.or(global.variant),
+            quirks: rom.quirks.or(global.quirks),
+            palette: rom.palette.or(global.palette),
+            instructions_per_frame: rom.instructions_per_frame.or(global.instructions_per_frame),
+            keymap: rom.keymap.or(global.keymap),
+        }
+    }
+
+    // Read settings from the file; `None` if there is none (or it can't be parsed)
+    //  Settings that are not understood are skipped (with a warning).
+    fn read(path: &Path) -> Option<Self> {
+        let buffer = storage::read(path)?;
+
+        Config::parse(path, &String::from_utf8_lossy(&buffer))
+    }
+
+    // Parse the settings in `text` (read from `path`; for warnings)
+    fn parse(path: &Path, text: &str) -> Option<Self> {
+        let value = match text.parse::<toml::Value>() {
+            Ok(value) => value,
+            Err(error) => {
+                eprintln!("warning: failed to parse {}: {}", path.display(), error);
+                return None;
+            }
+        };
+
+        let mut config: Config = Default::default();
+
+        if let Some(name) = value.get("variant").and_then(|v| v.as_str()) {
+            config.variant = Mode::from_name(name);
+            if config.variant.is_none() {
+                eprintln!("warning: {}: unknown variant: {}", path.display(), name);
+            }
+        }
+
+        if let Some(quirks) = value.get("quirks") {
+            config.quirks = read_quirks(path, quirks);
+        }
+
+        if let Some(palette) = value.get("palette").and_then(|v| v.as_array()) {
+            let colors: Vec<Color> = palette.iter().filter_map(read_color).collect();
+            if colors.len() == 4 {
+                config.palette = Some([colors[0], colors[1], colors[2], colors[3]]);
+            } else {
+                eprintln!("warning: {}: palette must be 4 colors", path.display());
+            }
+        }
+
+        if let Some(speed) = value.get("speed").and_then(|v| v.as_integer()) {
+            if speed > 0 {
+                config.instructions_per_frame = Some(speed as usize);
+            } else {
+                eprintln!("warning: {}: speed must be positive", path.display());
+            }
+        }
+
+        if let Some(keys) = value.get("keys").and_then(|v| v.as_table()) {
+            let mut keymap = chip_8::KEYBOARD_MAP;
+            for (name, key) in keys {
+                let index = usize::from_str_radix(name, 16).ok().filter(|&i| i < 16);
+                let key = key.as_str().and_then(key_from_name);
+
+                match (index, key) {
+                    (Some(index), Some(key)) => keymap[index] = key,
+                    _ => eprintln!("warning: {}: invalid key mapping: {}", path.display(), name),
+                }
+            }
+
+            config.keymap = Some(keymap);
+        }
+
+        Some(config)
+    }
+}
+
+// Quirks by preset name or as a table of a preset (optional) and quirks
+fn read_quirks(path: &Path, value: &toml::Value) -> Option<Quirks> {
+    if let Some(name) = value.as_str() {
+        let quirks = Quirks::preset(name);
+        if quirks.is_none() {
+            eprintln!("warning: {}: unknown quirks preset: {}", path.display(), name);
+        }
+
+        return quirks;
+    }
+
+    let table = value.as_table()?;
+    let mut quirks = table.get("preset")
+        .and_then(|v| v.as_str())
+        .and_then(Quirks::preset)
+        .unwrap_or_default();
+
+    for (name, value) in table {
+        if name == "preset" {
+            continue;
+        }
+
+        let value = match value.as_bool() {
+            Some(true) => "on",
+            Some(false) => "off",
+            None => value.as_str().unwrap_or_default(),
+        };
+
+        if !quirks.set(name, value) {
+            eprintln!("warning: {}: invalid quirk: {}", path.display(), name);
+        }
+    }
+
+    Some(quirks)
+}
+
+// Color as "#RRGGBB" or an integer (0xRRGGBB)
+fn read_color(value: &toml::Value) -> Option<Color> {
+    if let Some(color) = value.as_integer() {
+        return Some(color as Color & 0xFFFFFF);
+    }
+
+    let color = value.as_str()?;
+    u32::from_str_radix(color.trim_start_matches('#'), 16).ok().map(|color| color & 0xFFFFFF)
+}
+
+// Keys of the keyboard that can be mapped to (those of the default layout)
+fn key_from_name(name: &str) -> Option<Key> {
+    Some(match &*name.to_lowercase() {
+        "1" => Key::Num1,
+        "2" => Key::Num2,
+        "3" => Key::Num3,
+        "4" => Key::Num4,
+        "q" => Key::Q,
+        "w" => Key::W,
+        "e" => Key::E,
+        "r" => Key::R,
+        "a" => Key::A,
+        "s" => Key::S,
+        "d" => Key::D,
+        "f" => Key::F,
+        "z" => Key::Z,
+        "x" => Key::X,
+        "c" => Key::C,
+        "v" => Key::V,
+
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Config {
+        Config::parse(Path::new("test.toml"), text).unwrap()
+    }
+
+    #[test]
+    fn parse_settings() {
+        let config = parse(r##"
+            variant = "super-chip"
+            palette = ["#000000", "#FFFFFF", 11184810, "555555"]  # 0xAAAAAA
+            speed = 30
+
+            [keys]
+            0 = "x"
+            a = "Q"
+        "##);
+
+        assert!(config.variant == Some(Mode::SuperChip));
+        assert_eq!(config.palette, Some([0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]));
+        assert_eq!(config.instructions_per_frame, Some(30));
+        assert!(config.quirks.is_none());
+
+        // The keys not mapped are those of the default layout
+        let keymap = config.keymap.unwrap();
+        match (&keymap[0x0], &keymap[0x1], &keymap[0xA]) {
+            (&Key::X, &Key::Num1, &Key::Q) => {}
+            _ => panic!("keys not mapped"),
+        }
+    }
+
+    #[test]
+    fn parse_quirks() {
+        let preset = parse(r#"quirks = "vip""#);
+        assert!(preset.quirks == Quirks::preset("vip"));
+
+        let table = parse(r#"
+            [quirks]
+            preset = "vip"
+            clip_sprites = false
+            load_store_increment = "x"
+        "#);
+
+        let mut quirks = Quirks::preset("vip").unwrap();
+        assert!(quirks.set("clip_sprites", "off"));
+        assert!(quirks.set("load_store_increment", "x"));
+        assert!(table.quirks == Some(quirks));
+    }
+
+    #[test]
+    fn skip_invalid_settings() {
+        let config = parse(r##"
+            variant = "chip-9"
+            quirks = "unknown"
+            palette = ["#000000", "#FFFFFF"]
+            speed = 0
+
+            [keys]
+            10 = "x"
+            1 = "y"
+        "##);
+
+        assert!(config.variant.is_none());
+        assert!(config.quirks.is_none());
+        assert!(config.palette.is_none());
+        assert!(config.instructions_per_frame.is_none());
+        let keymap = config.keymap.unwrap();
+        match (&keymap[0x0], &keymap[0x1]) {
+            (&Key::X, &Key::Num1) => {}
+            _ => panic!("invalid keys mapped"),
+        }
+
+        assert!(Config::parse(Path::new("test.toml"), "variant = ").is_none());
+    }
+
+    #[test]
+    fn colors_are_24_bit() {
+        let value = |text: &str| text.parse::<toml::Value>().unwrap();
+
+        assert_eq!(read_color(value("c = \"#FF123456\"").get("c").unwrap()), Some(0x123456));
+        assert_eq!(read_color(value("c = 2131899478").get("c").unwrap()), Some(0x123456));
+        assert_eq!(read_color(value("c = \"#GG0000\"").get("c").unwrap()), None);
+    }
+}
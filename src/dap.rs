@@ -0,0 +1,664 @@
+use std::cmp;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::vec::Vec;
+
+use axal;
+
+use Core;
+use debug::Debugger;
+use interpreter::Register;
+use json::Json;
+
+// Debug Adapter Protocol server (the `dap` feature)
+//  Listens on a port of localhost for a client (eg. VS Code) and maps its
+//  requests to the `Debugger` of the core: breakpoints (by address; see
+//  `setInstructionBreakpoints`), stepping, the call stack, registers as
+//  variables and disassembly. The connection is read by a thread; requests are
+//  handled by the core, between frames (see `poll`).
+pub struct Server {
+    incoming: Receiver<Incoming>,
+    client: Option<TcpStream>,
+
+    // Sequence number of the next message sent
+    seq: u64,
+
+    // Set once the client was told emulation stopped (until it is resumed); and
+    // while a step over / out runs (to tell why it stopped)
+    stopped: bool,
+    stepping: bool,
+
+    // Breakpoints set by lines of the source and by address (each set replaces
+    // those set before it the same way)
+    source_breakpoints: Vec<usize>,
+    instruction_breakpoints: Vec<usize>,
+}
+
+// What the thread reading the connection received
+enum Incoming {
+    Connected(TcpStream),
+    Request(Json),
+    Disconnected,
+}
+
+// The interpreter runs a single thread (of the protocol)
+const THREAD_ID: usize = 1;
+
+// Variables references of the registers and of the variables of the source
+// (`:monitor`; see `Symbols`)
+const REGISTERS: usize = 1;
+const MONITORS: usize = 2;
+
+// Largest message (and header line) read; the connection is dropped for one
+// that is larger
+const MAX_CONTENT_LENGTH: usize = 1 << 20;
+const MAX_HEADER_LENGTH: u64 = 1024;
+
+impl Server {
+    pub fn listen(port: u16) -> io::Result<Server> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || accept(listener, sender));
+        println!("xCHIP: debug adapter listening on port {}", port);
+
+        Ok(Server {
+            incoming: receiver,
+            client: None,
+            seq: 1,
+            stopped: false,
+            stepping: false,
+            source_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+        })
+    }
+
+    // Handle the requests of the client (called before every frame, also while
+    // paused) and tell it when emulation stopped
+    pub fn poll(&mut self, core: &mut Core, r: &mut axal::Runtime) {
+        while let Ok(incoming) = self.incoming.try_recv() {
+            match incoming {
+                Incoming::Connected(stream) => {
+                    println!("xCHIP: debug adapter connected");
+
+                    self.client = Some(stream);
+                    self.seq = 1;
+                    self.stopped = false;
+                    self.stepping = false;
+                }
+
+                Incoming::Request(request) => self.handle(core, r, &request),
+
+                Incoming::Disconnected => {
+                    self.client = None;
+                    core.resume();
+                }
+            }
+        }
+
+        if self.client.is_none() {
+            return;
+        }
+
+        if !core.is_paused() {
+            self.stopped = false;
+        } else if !self.stopped {
+            let reason = if core.breakpoint_hit().is_some() {
+                "breakpoint"
+            } else if core.watch_hit().is_some() {
+                "data breakpoint"
+            } else if self.stepping {
+                "step"
+            } else {
+                "pause"
+            };
+
+            self.stop(reason);
+        }
+    }
+
+    fn stop(&mut self, reason: &str) {
+        self.stopped = true;
+        self.stepping = false;
+
+        self.event("stopped",
+                   Json::object(vec![("reason", Json::str(reason)),
+                                     ("threadId", THREAD_ID.into()),
+                                     ("allThreadsStopped", true.into())]));
+    }
+
+    fn handle(&mut self, core: &mut Core, r: &mut axal::Runtime, request: &Json) {
+        let command = request.get("command").as_str().unwrap_or("");
+        let arguments = request.get("arguments");
+
+        let body = match command {
+            "initialize" => {
+                let capabilities =
+                    Json::object(vec![("supportsConfigurationDoneRequest", true.into()),
+                                      ("supportsConditionalBreakpoints", true.into()),
+                                      ("supportsInstructionBreakpoints", true.into()),
+                                      ("supportsDisassembleRequest", true.into()),
+                                      ("supportsSetVariable", true.into())]);
+
+                // Breakpoints may be set once the client is told it is initialized
+                self.respond(request, Ok(capabilities));
+                self.event("initialized", Json::Null);
+
+                return;
+            }
+
+            "launch" | "attach" => {
+                // A ROM may be given to run (else it is the one the runtime inserts)
+                match arguments.get("program").as_str() {
+                    Some(program) => {
+                        core.load_rom(program).map(|_| Json::Null).map_err(|error| error.to_string())
+                    }
+
+                    None => Ok(Json::Null),
+                }
+            }
+
+            "configurationDone" | "setExceptionBreakpoints" => Ok(Json::Null),
+
+            "setBreakpoints" => Ok(self.set_source_breakpoints(core, arguments)),
+
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(core, arguments)),
+
+            "threads" => {
+                let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", Json::str("CHIP-8"))]);
+
+                Ok(Json::object(vec![("threads", Json::Array(vec![thread]))]))
+            }
+
+            "stackTrace" => Ok(stack_trace(core)),
+
+            "scopes" => {
+                let scope = |name, reference: usize| {
+                    Json::object(vec![("name", Json::str(name)),
+                                      ("variablesReference", reference.into()),
+                                      ("expensive", false.into())])
+                };
+
+                let mut scopes = vec![scope("Registers", REGISTERS)];
+                if core.symbols().map_or(false, |symbols| !symbols.monitors().is_empty()) {
+                    scopes.push(scope("Monitors", MONITORS));
+                }
+
+                Ok(Json::object(vec![("scopes", Json::Array(scopes))]))
+            }
+
+            "variables" => {
+                match arguments.get("variablesReference").as_u64() {
+                    Some(reference) if reference == MONITORS as u64 => Ok(monitors(core)),
+                    _ => Ok(variables(core)),
+                }
+            }
+
+            "setVariable" => set_variable(core, arguments),
+
+            "continue" => {
+                core.resume();
+                self.stopped = false;
+
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+
+            "next" => {
+                core.step_over();
+                self.stopped = false;
+                self.stepping = true;
+
+                Ok(Json::Null)
+            }
+
+            "stepOut" => {
+                core.step_out();
+                self.stopped = false;
+                self.stepping = true;
+
+                Ok(Json::Null)
+            }
+
+            // A single instruction (there is nothing finer to step into)
+            "stepIn" => {
+                core.pause();
+                Debugger::step(core, r);
+
+                // The response goes before the event
+                self.respond(request, Ok(Json::Null));
+                self.stop("step");
+
+                return;
+            }
+
+            "pause" => {
+                core.pause();
+
+                Ok(Json::Null)
+            }
+
+            "disassemble" => Ok(disassemble(core, arguments)),
+
+            "disconnect" => {
+                core.resume();
+                self.respond(request, Ok(Json::Null));
+                self.client = None;
+
+                return;
+            }
+
+            _ => Err(format!("{} is not supported", command)),
+        };
+
+        self.respond(request, body);
+    }
+
+    // Replace the breakpoints with those of the request; an `instructionReference`
+    // is an address (as in `disassemble`) or a label
+    fn set_instruction_breakpoints(&mut self, core: &mut Core, arguments: &Json) -> Json {
+        for address in self.instruction_breakpoints.drain(..) {
+            core.remove_breakpoint(address);
+        }
+
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments.get("breakpoints").as_array() {
+            let address = breakpoint.get("instructionReference")
+                .as_str()
+                .and_then(|reference| {
+                    parse_address(reference)
+                        .or_else(|| core.symbols().and_then(|symbols| symbols.address(reference)))
+                })
+                .map(|address| (address as i64 + breakpoint.get("offset").as_i64().unwrap_or(0)) as usize);
+
+            let result = address.ok_or_else(|| "invalid address".to_string())
+                .and_then(|address| add_breakpoint(core, address, breakpoint));
+
+            if let Ok(address) = result {
+                self.instruction_breakpoints.push(address);
+            }
+
+            breakpoints.push(breakpoint_result(core, result));
+        }
+
+        Json::object(vec![("breakpoints", Json::Array(breakpoints))])
+    }
+
+    // Replace the breakpoints on lines of the source with those of the request
+    // (at the first instruction of the line, or of the next line with one)
+    fn set_source_breakpoints(&mut self, core: &mut Core, arguments: &Json) -> Json {
+        // Only the source of the symbols has lines to stop at (the breakpoints of
+        // other files are not set, and leave those of the source be)
+        let path = arguments.get("source").get("path").as_str().map(Path::new);
+        let is_source = match (path, core.symbols().and_then(|symbols| symbols.source())) {
+            (Some(path), Some(source)) => is_same_file(path, source),
+            _ => false,
+        };
+
+        if is_source {
+            for address in self.source_breakpoints.drain(..) {
+                core.remove_breakpoint(address);
+            }
+        }
+
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments.get("breakpoints").as_array() {
+            if !is_source {
+                breakpoints.push(breakpoint_result(core, Err("not the source of the program".to_string())));
+                continue;
+            }
+
+            let address = breakpoint.get("line")
+                .as_u64()
+                .and_then(|line| core.symbols().and_then(|symbols| symbols.line_address(line as usize)));
+
+            let result = address.ok_or_else(|| "no instruction at the line".to_string())
+                .and_then(|address| add_breakpoint(core, address, breakpoint));
+
+            if let Ok(address) = result {
+                self.source_breakpoints.push(address);
+            }
+
+            breakpoints.push(breakpoint_result(core, result));
+        }
+
+        Json::object(vec![("breakpoints", Json::Array(breakpoints))])
+    }
+
+    fn respond(&mut self, request: &Json, body: Result<Json, String>) {
+        let mut fields = vec![("type", Json::str("response")),
+                              ("request_seq", request.get("seq").clone()),
+                              ("command", request.get("command").clone())];
+
+        match body {
+            Ok(body) => {
+                fields.push(("success", true.into()));
+                if body != Json::Null {
+                    fields.push(("body", body));
+                }
+            }
+
+            Err(message) => {
+                fields.push(("success", false.into()));
+                fields.push(("message", Json::String(message)));
+            }
+        }
+
+        self.send(fields);
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(vec![("type", Json::str("event")), ("event", Json::str(event)), ("body", body)]);
+    }
+
+    fn send(&mut self, fields: Vec<(&str, Json)>) {
+        let mut fields = fields;
+        fields.insert(0, ("seq", Json::Number(self.seq as f64)));
+        self.seq += 1;
+
+        let content = Json::object(fields).to_string();
+        let message = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+
+        let failed = match self.client {
+            Some(ref mut stream) => stream.write_all(message.as_bytes()).is_err(),
+            None => false,
+        };
+
+        if failed {
+            self.client = None;
+        }
+    }
+}
+
+// Accept clients (one at a time) and pass on their requests
+fn accept(listener: TcpListener, sender: Sender<Incoming>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        let writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(_) => continue,
+        };
+
+        if sender.send(Incoming::Connected(writer)).is_err() {
+            return;
+        }
+
+        let mut reader = BufReader::new(stream);
+        while let Some(request) = read_message(&mut reader) {
+            if sender.send(Incoming::Request(request)).is_err() {
+                return;
+            }
+        }
+
+        if sender.send(Incoming::Disconnected).is_err() {
+            return;
+        }
+    }
+}
+
+// Read a message (headers and its content); `None` once the connection is closed
+// (or the client sent something that is not a message, or is too large)
+fn read_message(reader: &mut BufReader<TcpStream>) -> Option<Json> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if reader.by_ref().take(MAX_HEADER_LENGTH).read_line(&mut line).ok()? == 0 ||
+           !line.ends_with('\n') {
+            return None;
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+
+        if line.starts_with("Content-Length:") {
+            len = line["Content-Length:".len()..].trim().parse().ok();
+        }
+    }
+
+    let len = len?;
+    if len > MAX_CONTENT_LENGTH {
+        return None;
+    }
+
+    let mut content = vec![0; len];
+    reader.read_exact(&mut content).ok()?;
+
+    Json::parse(&String::from_utf8(content).ok()?)
+}
+
+// `0x200`, `$200` or `512`
+fn parse_address(text: &str) -> Option<usize> {
+    let text = text.trim();
+    if text.starts_with("0x") || text.starts_with("0X") {
+        usize::from_str_radix(&text[2..], 16).ok()
+    } else if text.starts_with('$') {
+        usize::from_str_radix(&text[1..], 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+// Add a breakpoint at `address` (with the condition of the `breakpoint` of the request)
+fn add_breakpoint(core: &mut Core, address: usize, breakpoint: &Json) -> Result<usize, String> {
+    match breakpoint.get("condition").as_str() {
+        Some(condition) => {
+            core.add_conditional_breakpoint(address, condition).map_err(|error| error.to_string())?
+        }
+
+        None => core.add_breakpoint(address),
+    }
+
+    Ok(address)
+}
+
+// Breakpoint of the response (with the line it is at, if there is a source)
+fn breakpoint_result(core: &Core, result: Result<usize, String>) -> Json {
+    match result {
+        Ok(address) => {
+            let mut fields = vec![("verified", true.into()),
+                                  ("instructionReference", address_reference(address))];
+
+            if let Some(line) = core.symbols().and_then(|symbols| symbols.line(address)) {
+                fields.push(("line", line.into()));
+            }
+
+            Json::object(fields)
+        }
+
+        Err(message) => Json::object(vec![("verified", false.into()), ("message", Json::String(message))]),
+    }
+}
+
+// Source of the response (if there is one)
+fn source(core: &Core) -> Option<Json> {
+    let path = core.symbols()?.source()?;
+    let name = path.file_name()?.to_string_lossy().into_owned();
+
+    Some(Json::object(vec![("name", Json::String(name)),
+                           ("path", Json::String(path.to_string_lossy().into_owned()))]))
+}
+
+// Paths are compared as they are if either can not be resolved
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn address_reference(address: usize) -> Json {
+    Json::String(format!("0x{:03X}", address))
+}
+
+// The instruction PC is at, then the `CALL`s of the subroutines it is in (the
+// innermost first)
+fn stack_trace(core: &Core) -> Json {
+    let pc = core.registers().pc;
+
+    // By label if there are symbols
+    let describe = |address: usize| match core.symbols() {
+        Some(symbols) => symbols.describe(address),
+        None => format!("${:03X}", address),
+    };
+
+    let mut frames = vec![(pc, describe(pc))];
+    for call in core.call_stack().iter().rev() {
+        frames.push((call.site, describe(call.site)));
+    }
+
+    let total = frames.len();
+    let frames = frames.into_iter()
+        .enumerate()
+        .map(|(id, (address, name))| {
+            let line = core.symbols().and_then(|symbols| symbols.line(address));
+            let mut fields = vec![("id", id.into()),
+                                  ("name", Json::String(name)),
+                                  ("line", line.unwrap_or(0).into()),
+                                  ("column", Json::Number(if line.is_some() { 1.0 } else { 0.0 })),
+                                  ("instructionPointerReference", address_reference(address))];
+
+            // The line is of the source
+            if let (Some(source), Some(_)) = (source(core), line) {
+                fields.push(("source", source));
+            }
+
+            Json::object(fields)
+        })
+        .collect();
+
+    Json::object(vec![("stackFrames", Json::Array(frames)), ("totalFrames", total.into())])
+}
+
+// Registers of the protocol (by name)
+fn registers() -> Vec<Register> {
+    let mut registers: Vec<Register> = (0..16).map(Register::V).collect();
+    registers.extend_from_slice(&[Register::I, Register::Pc, Register::Sp, Register::Dt, Register::St]);
+
+    registers
+}
+
+fn format_register(register: Register, value: usize) -> String {
+    match register {
+        Register::I | Register::Pc => format!("${:03X}", value),
+        _ => format!("${:02X}", value),
+    }
+}
+
+fn variables(core: &Core) -> Json {
+    let variables = registers()
+        .into_iter()
+        .map(|register| {
+            let value = core.register(register);
+
+            Json::object(vec![("name", Json::String(register.to_string())),
+                              ("value", Json::String(format_register(register, value))),
+                              ("variablesReference", Json::Number(0.0))])
+        })
+        .collect();
+
+    Json::object(vec![("variables", Json::Array(variables))])
+}
+
+// Variables of the source, as hex bytes
+fn monitors(core: &Core) -> Json {
+    let names: Vec<String> = match core.symbols() {
+        Some(symbols) => symbols.monitors().iter().map(|monitor| monitor.0.clone()).collect(),
+        None => Vec::new(),
+    };
+
+    let variables = names.iter()
+        .map(|name| {
+            let bytes = core.variable(name).unwrap_or_default();
+            let value = bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+
+            Json::object(vec![("name", Json::String(name.clone())),
+                              ("value", Json::String(value)),
+                              ("variablesReference", Json::Number(0.0))])
+        })
+        .collect();
+
+    Json::object(vec![("variables", Json::Array(variables))])
+}
+
+fn set_variable(core: &mut Core, arguments: &Json) -> Result<Json, String> {
+    let name = arguments.get("name").as_str().unwrap_or("");
+    let register = registers()
+        .into_iter()
+        .find(|register| register.to_string() == name)
+        .ok_or_else(|| format!("{} is not a register", name))?;
+
+    let value = arguments.get("value")
+        .as_str()
+        .and_then(parse_address)
+        .ok_or_else(|| "invalid value".to_string())?;
+
+    core.set_register(register, value);
+    let value = core.register(register);
+
+    Ok(Json::object(vec![("value", Json::String(format_register(register, value)))]))
+}
+
+// Instructions from `memoryReference` (offset by `offset` bytes and
+// `instructionOffset` instructions; an instruction is taken as 2 bytes to find
+// where to start)
+fn disassemble(core: &Core, arguments: &Json) -> Json {
+    let start = arguments.get("memoryReference").as_str().and_then(parse_address).unwrap_or(0) as i64 +
+                arguments.get("offset").as_i64().unwrap_or(0) +
+                arguments.get("instructionOffset").as_i64().unwrap_or(0) * 2;
+
+    // No more than there are words of RAM
+    let count = cmp::min(arguments.get("instructionCount").as_u64().unwrap_or(0),
+                         core.memory_size() as u64 / 2) as usize;
+
+    // Outside of RAM (the client asks for instructions around an address)
+    let unknown = |address: i64| {
+        Json::object(vec![("address", Json::String(format!("0x{:03X}", address.max(0)))),
+                          ("instruction", Json::str("??"))])
+    };
+
+    let mut instructions: Vec<Json> =
+        (0..count as i64).map(|n| start + n * 2).take_while(|&address| address < 0).map(&unknown).collect();
+
+    let mut next = start + instructions.len() as i64 * 2;
+    for entry in core.disassemble(next as usize, count - instructions.len()) {
+        next = (entry.address + entry.bytes.len()) as i64;
+
+        let instruction = match entry.target_label {
+            Some(ref label) => format!("{}  ; {}", entry.mnemonic, label),
+            None => entry.mnemonic.clone(),
+        };
+
+        let bytes: String = entry.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let mut fields = vec![("address", address_reference(entry.address)),
+                              ("instructionBytes", Json::String(bytes)),
+                              ("instruction", Json::String(instruction))];
+
+        if let Some(ref label) = entry.label {
+            fields.push(("symbol", Json::str(label)));
+        }
+
+        if let (Some(source), Some(line)) =
+            (source(core), core.symbols().and_then(|symbols| symbols.line(entry.address))) {
+            fields.push(("location", source));
+            fields.push(("line", line.into()));
+        }
+
+        instructions.push(Json::object(fields));
+    }
+
+    // Past the end of RAM
+    while instructions.len() < count {
+        instructions.push(unknown(next));
+        next += 2;
+    }
+
+    Json::object(vec![("instructions", Json::Array(instructions))])
+}
@@ -0,0 +1,114 @@
+use std::vec::Vec;
+
+use axal;
+
+use disasm::Disassembly;
+use error::Error;
+use interpreter::{Call, Register, State};
+use mmu::{Watch, WatchHit};
+use symbols::Symbols;
+use trace::TraceEntry;
+
+// Debugging interface of the core: registers, memory and stepping (for the
+// debugger of a frontend)
+//  NOTE: axal has no debugging interface (yet) to implement; this is the shape
+//  of the one the core provides until it does.
+pub trait Debugger {
+    // Registers and timers
+    fn registers(&self) -> State;
+
+    // Read / write a single register (or timer); values written are truncated to
+    // its size. Meant for use while paused (eg. to grant lives or skip a level
+    // for testing).
+    fn register(&self, register: Register) -> usize;
+    fn set_register(&mut self, register: Register, value: usize);
+
+    // Size of RAM (of the variant)
+    fn memory_size(&self) -> usize;
+
+    // Read / write a byte of RAM as the debugger (not the program): addresses wrap
+    // around, the interpreter area can be written even if protected, and nothing
+    // faults
+    fn read_memory(&self, address: usize) -> u8;
+    fn write_memory(&mut self, address: usize, value: u8);
+
+    // Read / write a range of RAM as `read_memory` / `write_memory` do (for a hex
+    // editor); a range read stops at the end of RAM
+    fn read_range(&self, address: usize, len: usize) -> &[u8];
+    fn write_range(&mut self, address: usize, bytes: &[u8]);
+
+    // Range of addresses (start, end) written since the last call, by the program
+    // or the debugger (or all of RAM, as a ROM or state is loaded); `None` if
+    // nothing was. A view of RAM need only refresh this.
+    fn take_memory_changed(&mut self) -> Option<(usize, usize)>;
+
+    // Pause and resume emulation (`run_next` presents silence while paused)
+    fn pause(&mut self);
+    fn resume(&mut self);
+    fn is_paused(&self) -> bool;
+
+    // Run a single instruction (or frame); usable while paused
+    fn step(&mut self, r: &mut axal::Runtime) -> State;
+    fn step_frame(&mut self, r: &mut axal::Runtime) -> State;
+
+    // Resume until the instruction after the next one (a `CALL` is run until it
+    // returns), or until the subroutine the program is in returns; emulation is
+    // paused there once the frame is presented (or earlier, at a breakpoint or
+    // watchpoint). The depth of the stack (SP) tells when a subroutine returned;
+    // outside of a subroutine (SP is 0) a step out pauses where it is.
+    fn step_over(&mut self);
+    fn step_out(&mut self);
+
+    // Pause before the instruction at `address` is run (once the frame is
+    // presented); the instruction is run when resumed
+    fn add_breakpoint(&mut self, address: usize);
+
+    // As `add_breakpoint`, only pausing if `condition` holds when the instruction
+    // is reached; eg. `v[3] == 0x10 && i > 0x300` (see `Condition` for what can
+    // be used)
+    fn add_conditional_breakpoint(&mut self, address: usize, condition: &str) -> Result<(), Error>;
+
+    // Breakpoints at the same address are counted apart; this removes the one last
+    // added there (the others still pause)
+    fn remove_breakpoint(&mut self, address: usize);
+    fn breakpoints(&self) -> Vec<usize>;
+
+    // Breakpoint emulation is paused at (`None` if it is not)
+    fn breakpoint_hit(&self) -> Option<usize>;
+
+    // Subroutines the program is in, the innermost last (a backtrace); each with
+    // the address of its `CALL`, of the subroutine and returned to
+    fn call_stack(&self) -> Vec<Call>;
+
+    // Last instructions run, oldest first (see `Core::set_trace`); empty if the
+    // trace is disabled
+    fn trace(&self) -> Vec<TraceEntry>;
+
+    // Disassemble `count` instructions from `address` (for a disassembly view; eg.
+    // from a little before PC): each with its bytes, mnemonic and the address a
+    // `JP` or `CALL` goes to, with labels if there is a symbol map
+    fn disassemble(&self, address: usize, count: usize) -> Vec<Disassembly>;
+
+    // Symbols of the program (labels, variables) if there is a symbol map for it
+    fn symbols(&self) -> Option<&Symbols>;
+
+    // Add a breakpoint at the label (or other symbol) `name`
+    fn add_breakpoint_by_name(&mut self, name: &str) -> Result<(), Error>;
+
+    // Add a breakpoint at the first instruction of `line` of the source (or of the
+    // next line with one); returns its address
+    fn add_line_breakpoint(&mut self, line: usize) -> Result<usize, Error>;
+
+    // Bytes of the variable `name` (of its `:monitor`, or the byte at its label)
+    fn variable(&self, name: &str) -> Option<Vec<u8>>;
+
+    // Pause after an instruction reads and/or writes any of the `len` bytes from
+    // `address` (once the frame is presented); instructions are not watched as
+    // they are fetched
+    fn add_watchpoint(&mut self, address: usize, len: usize, watch: Watch);
+    fn remove_watchpoint(&mut self, address: usize, len: usize);
+
+    // Access that emulation is paused after (who made it and the value before
+    // and after); `None` if it is not
+    fn watch_hit(&self) -> Option<WatchHit>;
+}
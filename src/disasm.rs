@@ -0,0 +1,85 @@
+use std::fmt;
+use std::vec::Vec;
+
+use instruction::Instruction;
+
+// Mnemonic of an instruction (see `Instruction`)
+//  `next` is the word that follows (the address of XO-CHIP's `F000 NNNN`).
+pub fn mnemonic(opcode: u16, next: u16) -> String {
+    match Instruction::decode(opcode) {
+        Instruction::LoadLongI => format!("LD I, ${:04X}", next),
+        instruction => instruction.to_string(),
+    }
+}
+
+// Instruction of a disassembly (see `Debugger::disassemble`)
+#[derive(Clone, Debug)]
+pub struct Disassembly {
+    pub address: usize,
+
+    // Bytes of the instruction (4 for `F000 NNNN`; otherwise 2)
+    pub bytes: Vec<u8>,
+
+    pub instruction: Instruction,
+    pub mnemonic: String,
+
+    // Label at the address (of the symbol map; see `Symbols`)
+    pub label: Option<String>,
+
+    // Address jumped to or called (`JP` / `CALL`), and where it is by label
+    pub target: Option<usize>,
+    pub target_label: Option<String>,
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${:03X}: ", self.address)?;
+        for byte in &self.bytes {
+            write!(f, "{:02X}", byte)?;
+        }
+
+        write!(f, "{:<w$}  {}", "", self.mnemonic, w = 8 - self.bytes.len() * 2)?;
+
+        if let Some(ref label) = self.target_label {
+            write!(f, "  ; {}", label)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Disassemble `count` instructions from `address` (stopping at the end of RAM,
+// of `size` bytes) as read by `read`
+//  Instructions follow one another from `address`; a window around an address
+//  (eg. PC) should start at an instruction, as data is decoded as if it were code.
+pub fn disassemble<F>(read: F, size: usize, address: usize, count: usize) -> Vec<Disassembly>
+    where F: Fn(usize) -> u8
+{
+    let mut instructions = Vec::with_capacity(count);
+    let mut address = address;
+
+    while instructions.len() < count && address < size {
+        let word = |address: usize| ((read(address) as u16) << 8) | (read(address + 1) as u16);
+        let opcode = word(address);
+        let instruction = Instruction::decode(opcode);
+
+        let target = match instruction {
+            Instruction::Jump(target) | Instruction::Call(target) => Some(target as usize),
+            _ => None,
+        };
+
+        instructions.push(Disassembly {
+            address: address,
+            bytes: (0..instruction.len()).map(|i| read(address + i)).collect(),
+            instruction: instruction,
+            mnemonic: mnemonic(opcode, word(address + 2)),
+            label: None,
+            target: target,
+            target_label: None,
+        });
+
+        address += instruction.len();
+    }
+
+    instructions
+}
@@ -0,0 +1,155 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+// Failure to load a ROM (or a savestate)
+#[derive(Debug)]
+pub enum Error {
+    // The ROM could not be read
+    Io(io::Error),
+
+    // The ROM is empty
+    Empty,
+
+    // The ROM does not fit in the RAM of the mode it is run in
+    TooLarge {
+        size: usize,
+        max_size: usize,
+        mode: &'static str,
+    },
+
+    // The savestate is truncated or otherwise not one written by `Core::serialize`
+    InvalidState,
+
+    // The savestate is of a newer format than this release can read
+    UnsupportedState { version: u16 },
+
+    // The savestate is of a format too old to be upgraded
+    IncompatibleState { version: u16 },
+
+    // There is no ROM inserted (to save the state of)
+    NoRom,
+
+    // Nothing was saved in the savestate slot
+    EmptySlot { slot: usize },
+
+    // The savestate (or movie) could not be read or written
+    StateIo(io::Error),
+
+    // The movie is truncated or otherwise not one written by `Movie::serialize`
+    InvalidMovie,
+
+    // The movie is of a newer format than this release can read
+    UnsupportedMovie { version: u16 },
+
+    // The movie was recorded with another ROM than the one inserted
+    WrongRom,
+
+    // The savestate was not saved during the movie being recorded (or is of a
+    // frame past its end)
+    StateNotInMovie,
+
+    // The condition of a breakpoint could not be parsed (see `Condition`)
+    InvalidCondition(String),
+
+    // There is no symbol of the name (or no symbol map for the ROM)
+    UnknownSymbol(String),
+
+    // The line (or any after it) of the source has no instruction (or there is no
+    // map of lines for the ROM)
+    NoCodeAtLine { line: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => write!(f, "failed to read ROM: {}", error),
+            Error::Empty => write!(f, "ROM is empty"),
+
+            Error::TooLarge { size, max_size, mode } => {
+                write!(f,
+                       "ROM is too large for {} ({} bytes; at most {} bytes fit)",
+                       mode,
+                       size,
+                       max_size)
+            }
+
+            Error::InvalidState => write!(f, "savestate is invalid"),
+
+            Error::UnsupportedState { version } => {
+                write!(f, "savestate is of a newer format (version {})", version)
+            }
+
+            Error::IncompatibleState { version } => {
+                write!(f,
+                       "savestate is of a format too old to be read (version {})",
+                       version)
+            }
+
+            Error::NoRom => write!(f, "no ROM is inserted"),
+            Error::EmptySlot { slot } => write!(f, "savestate slot {} is empty", slot),
+            Error::StateIo(ref error) => write!(f, "failed to access savestate: {}", error),
+            Error::InvalidMovie => write!(f, "movie is invalid"),
+
+            Error::UnsupportedMovie { version } => {
+                write!(f, "movie is of a newer format (version {})", version)
+            }
+
+            Error::WrongRom => write!(f, "movie was recorded with another ROM"),
+            Error::StateNotInMovie => write!(f, "savestate is not of the movie being recorded"),
+            Error::InvalidCondition(ref error) => write!(f, "condition is invalid: {}", error),
+            Error::UnknownSymbol(ref name) => write!(f, "unknown symbol: {}", name),
+            Error::NoCodeAtLine { line } => write!(f, "no instruction at line {}", line),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_) => "failed to read ROM",
+            Error::Empty => "ROM is empty",
+            Error::TooLarge { .. } => "ROM is too large",
+            Error::InvalidState => "savestate is invalid",
+            Error::UnsupportedState { .. } => "savestate is of a newer format",
+            Error::IncompatibleState { .. } => "savestate is of a format too old to be read",
+            Error::NoRom => "no ROM is inserted",
+            Error::EmptySlot { .. } => "savestate slot is empty",
+            Error::StateIo(_) => "failed to access savestate",
+            Error::InvalidMovie => "movie is invalid",
+            Error::UnsupportedMovie { .. } => "movie is of a newer format",
+            Error::WrongRom => "movie was recorded with another ROM",
+            Error::StateNotInMovie => "savestate is not of the movie being recorded",
+            Error::InvalidCondition(_) => "condition is invalid",
+            Error::UnknownSymbol(_) => "unknown symbol",
+            Error::NoCodeAtLine { .. } => "no instruction at line",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(ref error) |
+            Error::StateIo(ref error) => Some(error),
+            Error::Empty |
+            Error::TooLarge { .. } |
+            Error::InvalidState |
+            Error::UnsupportedState { .. } |
+            Error::IncompatibleState { .. } |
+            Error::NoRom |
+            Error::EmptySlot { .. } |
+            Error::InvalidMovie |
+            Error::UnsupportedMovie { .. } |
+            Error::WrongRom |
+            Error::StateNotInMovie |
+            Error::InvalidCondition(_) |
+            Error::UnknownSymbol(_) |
+            Error::NoCodeAtLine { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
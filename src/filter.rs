@@ -0,0 +1,118 @@
+use std::cmp;
+use std::vec::Vec;
+
+// Post-processing applied to the framebuffer before it is presented
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    // Present the framebuffer as-is
+    None,
+
+    // Each dot is 3x3 with the bottom row darkened
+    Scanlines,
+
+    // Each dot is 3x3 with the bottom row and right column darkened
+    PixelGrid,
+
+    // Lit dots bleed slightly into their neighbours
+    Bloom,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::None
+    }
+}
+
+impl Filter {
+    // Smallest factor the framebuffer must be scaled by for the filter to be visible
+    pub fn min_scale(&self) -> usize {
+        match *self {
+            Filter::Scanlines | Filter::PixelGrid => 3,
+            Filter::None | Filter::Bloom => 1,
+        }
+    }
+}
+
+// Scale each channel of a 0xRRGGBB color by `factor` (saturating)
+fn shade(color: u32, factor: f32) -> u32 {
+    let mut r = 0;
+    for shift in &[0, 8, 16] {
+        let c = ((color >> shift) & 0xFF) as f32 * factor;
+
+        r |= (c.min(255.0) as u32) << shift;
+    }
+
+    r
+}
+
+// Add two 0xRRGGBB colors (saturating)
+fn add(a: u32, b: u32) -> u32 {
+    let mut r = 0;
+    for shift in &[0, 8, 16] {
+        let c = ((a >> shift) & 0xFF) + ((b >> shift) & 0xFF);
+
+        r |= cmp::min(c, 0xFF) << shift;
+    }
+
+    r
+}
+
+// Apply `filter` to the `width`x`height` framebuffer in `src` while scaling it up by
+// `scale`, writing the result to `dst`; returns the dimensions of the result
+pub fn apply(filter: Filter,
+             scale: usize,
+             src: &[u32],
+             width: usize,
+             height: usize,
+             dst: &mut Vec<u32>)
+             -> (usize, usize) {
+    let dst_width = width * scale;
+    let dst_height = height * scale;
+
+    dst.resize(dst_width * dst_height, 0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut color = src[y * width + x];
+
+            if filter == Filter::Bloom {
+                // Sum of the orthogonal neighbours (that are on the screen)
+                let mut glow = 0;
+                if x > 0 {
+                    glow = add(glow, shade(src[y * width + x - 1], 0.125));
+                }
+
+                if x + 1 < width {
+                    glow = add(glow, shade(src[y * width + x + 1], 0.125));
+                }
+
+                if y > 0 {
+                    glow = add(glow, shade(src[(y - 1) * width + x], 0.125));
+                }
+
+                if y + 1 < height {
+                    glow = add(glow, shade(src[(y + 1) * width + x], 0.125));
+                }
+
+                color = add(color, glow);
+            }
+
+            // Each dot becomes a `scale`x`scale` cell; scanlines and the
+            // pixel grid darken the edges of each cell
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let edge = match filter {
+                        Filter::Scanlines => dy == scale - 1,
+                        Filter::PixelGrid => dy == scale - 1 || dx == scale - 1,
+                        Filter::None | Filter::Bloom => false,
+                    };
+
+                    let offset = (y * scale + dy) * dst_width + (x * scale + dx);
+                    dst[offset] = if edge && scale > 1 { shade(color, 0.5) } else { color };
+                }
+            }
+        }
+    }
+
+    (dst_width, dst_height)
+}
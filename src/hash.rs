@@ -0,0 +1,62 @@
+// FNV-1a (64-bit)
+//  Used to identify ROMs (persistent storage, savestates) and states (movies,
+//  netplay); it is not cryptographic but it is stable across releases and
+//  platforms.
+
+const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hasher: Fnv1a = Default::default();
+    hasher.write(bytes);
+
+    hasher.finish()
+}
+
+// FNV-1a of data written in parts (as if it were one slice); integers are
+// written big-endian
+#[derive(Clone, Copy)]
+pub struct Fnv1a {
+    hash: u64,
+}
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a { hash: OFFSET_BASIS }
+    }
+}
+
+impl Fnv1a {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.hash ^= *b as u64;
+            self.hash = self.hash.wrapping_mul(PRIME);
+        }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.write(&[value]);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write(&[(value >> 8) as u8, value as u8]);
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        for shift in &[56, 48, 40, 32, 24, 16, 8, 0] {
+            self.write_u8((value >> shift) as u8);
+        }
+    }
+
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
@@ -0,0 +1,53 @@
+use chip_8;
+use opcode::Opcode;
+use mmu::Mmu;
+use interpreter::{Runtime, Context};
+use axal;
+
+// Opcode at $200 of every HIRES CHIP-8 ROM; jumps into the (patched) interpreter
+// code bundled with the ROM
+pub const ENTRY_OPCODE: [u8; 2] = [0x12, 0x60];
+
+#[derive(Clone, Default)]
+pub struct HiResChip8 {
+    // HIRES CHIP-8 starts from the CHIP-8
+    chip_8: chip_8::Chip8,
+}
+
+impl Runtime for HiResChip8 {
+    fn configure(&mut self, c: &mut Context) {
+        // Increase screen size to 64x64 (two pages of VIP video RAM)
+        c.resize_screen(64, 64);
+    }
+
+    fn reset(&mut self, c: &mut Context) {
+        // Reset CHIP-8
+        self.chip_8.reset(c);
+
+        // The ROM includes the interpreter patch at $200 - $2BF; the program
+        // itself starts at $2C0
+        c.pc = 0x2C0;
+    }
+
+    fn execute(&mut self,
+               r: &mut axal::Runtime,
+               c: &mut Context,
+               m: &mut Mmu,
+               opcode: Opcode)
+               -> bool {
+        match opcode.unwrap() {
+            // HRCLS
+            (0x0, 0x2, 0x3, 0x0) => {
+                // Clear the (64x64) screen
+                c.clear_screen();
+            }
+
+            _ => {
+                // Unhandled by HIRES CHIP-8
+                return self.chip_8.execute(r, c, m, opcode);
+            }
+        }
+
+        true
+    }
+}
@@ -0,0 +1,22 @@
+use interpreter::State;
+
+// Observer of execution (for tooling layered on the core: profilers, scripts,
+// achievements); see `Core::add_hooks`
+//  Every method does nothing by default; implement those of interest. Hooks are
+//  called as the program runs (not for frames that are run and rolled back, such
+//  as run-ahead) and must not take long: `on_before_exec` and `on_after_exec`
+//  are called for every instruction.
+pub trait Hooks {
+    // Before the instruction `opcode` at `pc` is run
+    fn on_before_exec(&mut self, _pc: usize, _opcode: u16, _state: &State) {}
+
+    // After the instruction `opcode` at `pc` was run (with the state after)
+    fn on_after_exec(&mut self, _pc: usize, _opcode: u16, _state: &State) {}
+
+    // After a sprite of `rows` rows (`DXYN`; 0 is 16 rows in hi-res) was drawn at
+    // (`x`, `y`); `collision` if it turned off a dot
+    fn on_draw(&mut self, _x: u8, _y: u8, _rows: u8, _collision: bool) {}
+
+    // When the program starts to wait for a key (`FX0A`) into `Vx`
+    fn on_key_wait(&mut self, _x: u8) {}
+}
@@ -0,0 +1,323 @@
+use std::fmt;
+
+// Instruction (of CHIP-8 and its SUPER-CHIP and XO-CHIP extensions), decoded
+// from its opcode
+//  The instructions of these variants are decoded alike, whether or not the
+//  variant being run has them. Those of other variants (CHIP-8E, CHIP-8X,
+//  MEGA-CHIP, ...) are not decoded here; their interpreters match on the opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    // SYS u12; a machine code routine of the original interpreter
+    MachineCall(u16),
+
+    // CLS
+    ClearScreen,
+
+    // RET
+    Return,
+
+    // JP u12
+    Jump(u16),
+
+    // CALL u12
+    Call(u16),
+
+    // SE Vx, u8
+    SkipEqual(u8, u8),
+
+    // SNE Vx, u8
+    SkipNotEqual(u8, u8),
+
+    // SE Vx, Vy
+    SkipEqualRegisters(u8, u8),
+
+    // SNE Vx, Vy
+    SkipNotEqualRegisters(u8, u8),
+
+    // LD Vx, u8
+    Load(u8, u8),
+
+    // ADD Vx, u8
+    Add(u8, u8),
+
+    // LD Vx, Vy
+    Move(u8, u8),
+
+    // OR Vx, Vy
+    Or(u8, u8),
+
+    // AND Vx, Vy
+    And(u8, u8),
+
+    // XOR Vx, Vy
+    Xor(u8, u8),
+
+    // ADD Vx, Vy
+    AddRegisters(u8, u8),
+
+    // SUB Vx, Vy
+    Sub(u8, u8),
+
+    // SHR Vx, Vy
+    ShiftRight(u8, u8),
+
+    // SUBN Vx, Vy
+    SubN(u8, u8),
+
+    // SHL Vx, Vy
+    ShiftLeft(u8, u8),
+
+    // LD I, u12
+    LoadI(u16),
+
+    // JP V0, u12
+    JumpOffset(u16),
+
+    // RND Vx, u8
+    Random(u8, u8),
+
+    // DRW Vx, Vy, u4
+    Draw(u8, u8, u8),
+
+    // SKP Vx
+    SkipKey(u8),
+
+    // SKNP Vx
+    SkipNotKey(u8),
+
+    // LD Vx, DT
+    LoadDelay(u8),
+
+    // LD Vx, K
+    WaitKey(u8),
+
+    // LD DT, Vx
+    SetDelay(u8),
+
+    // LD ST, Vx
+    SetSound(u8),
+
+    // ADD I, Vx
+    AddI(u8),
+
+    // LD F, Vx
+    LoadFont(u8),
+
+    // LD B, Vx
+    LoadBcd(u8),
+
+    // LD [I], Vx
+    Store(u8),
+
+    // LD Vx, [I]
+    Restore(u8),
+
+    // SCD u4 (SUPER-CHIP)
+    ScrollDown(u8),
+
+    // SCR (SUPER-CHIP)
+    ScrollRight,
+
+    // SCL (SUPER-CHIP)
+    ScrollLeft,
+
+    // EXIT (SUPER-CHIP)
+    Exit,
+
+    // LOW (SUPER-CHIP)
+    Low,
+
+    // HIGH (SUPER-CHIP)
+    High,
+
+    // LD HF, Vx (SUPER-CHIP)
+    LoadBigFont(u8),
+
+    // LD R, Vx (SUPER-CHIP)
+    SaveFlags(u8),
+
+    // LD Vx, R (SUPER-CHIP)
+    LoadFlags(u8),
+
+    // SCU u4 (XO-CHIP)
+    ScrollUp(u8),
+
+    // LD [I], Vx-Vy (XO-CHIP)
+    StoreRange(u8, u8),
+
+    // LD Vx-Vy, [I] (XO-CHIP)
+    RestoreRange(u8, u8),
+
+    // LD I, u16 (XO-CHIP); the address is the word that follows
+    LoadLongI,
+
+    // PLANE u2 (XO-CHIP)
+    Plane(u8),
+
+    // AUDIO (XO-CHIP)
+    Audio,
+
+    // PITCH Vx (XO-CHIP)
+    Pitch(u8),
+
+    // A word that is not an instruction (of these variants)
+    Data(u16),
+}
+
+impl Instruction {
+    pub fn decode(opcode: u16) -> Instruction {
+        use self::Instruction::*;
+
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+
+        match (opcode >> 12, x, y, n) {
+            (0x0, 0x0, 0xC, _) => ScrollDown(n),
+            (0x0, 0x0, 0xD, _) => ScrollUp(n),
+            (0x0, 0x0, 0xE, 0x0) => ClearScreen,
+            (0x0, 0x0, 0xE, 0xE) => Return,
+            (0x0, 0x0, 0xF, 0xB) => ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Exit,
+            (0x0, 0x0, 0xF, 0xE) => Low,
+            (0x0, 0x0, 0xF, 0xF) => High,
+            (0x0, ..) => MachineCall(nnn),
+            (0x1, ..) => Jump(nnn),
+            (0x2, ..) => Call(nnn),
+            (0x3, ..) => SkipEqual(x, nn),
+            (0x4, ..) => SkipNotEqual(x, nn),
+            (0x5, _, _, 0x2) => StoreRange(x, y),
+            (0x5, _, _, 0x3) => RestoreRange(x, y),
+
+            // The original interpreter does not look at the last nibble of `5XY0`
+            (0x5, ..) => SkipEqualRegisters(x, y),
+
+            (0x6, ..) => Load(x, nn),
+            (0x7, ..) => Add(x, nn),
+            (0x8, _, _, 0x0) => Move(x, y),
+            (0x8, _, _, 0x1) => Or(x, y),
+            (0x8, _, _, 0x2) => And(x, y),
+            (0x8, _, _, 0x3) => Xor(x, y),
+            (0x8, _, _, 0x4) => AddRegisters(x, y),
+            (0x8, _, _, 0x5) => Sub(x, y),
+            (0x8, _, _, 0x6) => ShiftRight(x, y),
+            (0x8, _, _, 0x7) => SubN(x, y),
+            (0x8, _, _, 0xE) => ShiftLeft(x, y),
+            (0x9, _, _, 0x0) => SkipNotEqualRegisters(x, y),
+            (0xA, ..) => LoadI(nnn),
+            (0xB, ..) => JumpOffset(nnn),
+            (0xC, ..) => Random(x, nn),
+            (0xD, ..) => Draw(x, y, n),
+            (0xE, _, 0x9, 0xE) => SkipKey(x),
+            (0xE, _, 0xA, 0x1) => SkipNotKey(x),
+            (0xF, 0x0, 0x0, 0x0) => LoadLongI,
+            (0xF, _, 0x0, 0x1) => Plane(x),
+            (0xF, 0x0, 0x0, 0x2) => Audio,
+            (0xF, _, 0x0, 0x7) => LoadDelay(x),
+            (0xF, _, 0x0, 0xA) => WaitKey(x),
+            (0xF, _, 0x1, 0x5) => SetDelay(x),
+            (0xF, _, 0x1, 0x8) => SetSound(x),
+            (0xF, _, 0x1, 0xE) => AddI(x),
+            (0xF, _, 0x2, 0x9) => LoadFont(x),
+            (0xF, _, 0x3, 0x0) => LoadBigFont(x),
+            (0xF, _, 0x3, 0x3) => LoadBcd(x),
+            (0xF, _, 0x3, 0xA) => Pitch(x),
+            (0xF, _, 0x5, 0x5) => Store(x),
+            (0xF, _, 0x6, 0x5) => Restore(x),
+            (0xF, _, 0x7, 0x5) => SaveFlags(x),
+            (0xF, _, 0x8, 0x5) => LoadFlags(x),
+
+            _ => Data(opcode),
+        }
+    }
+
+    // Length (in bytes) of the instruction; `F000 NNNN` is 4
+    pub fn len(&self) -> usize {
+        match *self {
+            Instruction::LoadLongI => 4,
+            _ => 2,
+        }
+    }
+
+    // Whether the instruction may skip the next one
+    pub fn is_skip(&self) -> bool {
+        match *self {
+            Instruction::SkipEqual(..) |
+            Instruction::SkipNotEqual(..) |
+            Instruction::SkipEqualRegisters(..) |
+            Instruction::SkipNotEqualRegisters(..) |
+            Instruction::SkipKey(_) |
+            Instruction::SkipNotKey(_) => true,
+
+            _ => false,
+        }
+    }
+}
+
+// Mnemonic of the instruction (as in the comments of the interpreters; Cowgod's
+// syntax, with that of the SUPER-CHIP and XO-CHIP extensions)
+//  The address of `F000 NNNN` is not a part of the opcode; it is shown as `LONG`
+//  (see `disasm::mnemonic` for the instruction with its address).
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            MachineCall(address) => write!(f, "SYS ${:03X}", address),
+            ClearScreen => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            Jump(address) => write!(f, "JP ${:03X}", address),
+            Call(address) => write!(f, "CALL ${:03X}", address),
+            SkipEqual(x, value) => write!(f, "SE V{:X}, ${:02X}", x, value),
+            SkipNotEqual(x, value) => write!(f, "SNE V{:X}, ${:02X}", x, value),
+            SkipEqualRegisters(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            SkipNotEqualRegisters(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Load(x, value) => write!(f, "LD V{:X}, ${:02X}", x, value),
+            Add(x, value) => write!(f, "ADD V{:X}, ${:02X}", x, value),
+            Move(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddRegisters(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SubN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            LoadI(address) => write!(f, "LD I, ${:03X}", address),
+            JumpOffset(address) => write!(f, "JP V0, ${:03X}", address),
+            Random(x, mask) => write!(f, "RND V{:X}, ${:02X}", x, mask),
+            Draw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey(x) => write!(f, "SKP V{:X}", x),
+            SkipNotKey(x) => write!(f, "SKNP V{:X}", x),
+            LoadDelay(x) => write!(f, "LD V{:X}, DT", x),
+            WaitKey(x) => write!(f, "LD V{:X}, K", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            LoadFont(x) => write!(f, "LD F, V{:X}", x),
+            LoadBcd(x) => write!(f, "LD B, V{:X}", x),
+            Store(x) => write!(f, "LD [I], V{:X}", x),
+            Restore(x) => write!(f, "LD V{:X}, [I]", x),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            LoadBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            SaveFlags(x) => write!(f, "LD R, V{:X}", x),
+            LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            StoreRange(x, y) => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            RestoreRange(x, y) => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            LoadLongI => write!(f, "LD I, LONG"),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Data(word) => write!(f, "DW ${:04X}", word),
+        }
+    }
+}
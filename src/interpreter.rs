@@ -1,44 +1,428 @@
+use std::cmp;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 use std::vec::Vec;
-use std::time::Instant;
 
 use axal;
+use rand;
 
 use super_chip;
+use xo_chip;
+use mega_chip;
 use chip_8;
 use chip_8x;
+use chip_48;
+use chip_8e;
+use hires_chip_8;
 use opcode::Opcode;
 use mmu;
+use quirks;
+use error::Error;
+use hash;
+use audio;
+use clock::Clock;
+use rng;
+use timing::{self, Timing};
+use savestate::{self, Reader, Writer};
+use video;
+use filter;
+use condition::Condition;
+use trace::{Trace, TraceEntry};
+use hooks::Hooks;
+use instruction::Instruction;
+
+// Number of (bit)planes of the screen (XO-CHIP)
+pub const PLANES: usize = 2;
+
+// Location of the 10-byte (8x10) font sprites for the digits 0-F (SUPER-CHIP)
+pub const FONT_10_ADDRESS: usize = 0x50;
+
+const FONT_10: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
 
 // Interpreter mode of operation
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum Mode {
     Chip8,
     HiResChip8,
     Chip8x,
     Chip10,
+    Chip48,
+    Chip8e,
     SuperChip,
     XoChip,
+    MegaChip,
+    Octo,
 }
 
 impl Mode {
+    // Name of the mode (as accepted by `-m <mode>`)
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Chip8 => "chip-8",
+            Mode::HiResChip8 => "hires-chip-8",
+            Mode::Chip8x => "chip-8x",
+            Mode::Chip10 => "chip-10",
+            Mode::Chip48 => "chip-48",
+            Mode::Chip8e => "chip-8e",
+            Mode::SuperChip => "super-chip",
+            Mode::XoChip => "xo-chip",
+            Mode::MegaChip => "mega-chip",
+            Mode::Octo => "octo",
+        }
+    }
+
+    // Parse a mode from its name or short name (eg. `super-chip` or `sc`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match &*name.to_lowercase() {
+            "chip-8" | "8" => Mode::Chip8,
+            "hires-chip-8" | "hires" => Mode::HiResChip8,
+            "chip-8x" | "8x" => Mode::Chip8x,
+            "chip-10" | "10" => Mode::Chip10,
+            "chip-48" | "48" => Mode::Chip48,
+            "chip-8e" | "8e" => Mode::Chip8e,
+            "super-chip" | "sc" => Mode::SuperChip,
+            "xo-chip" | "xo" => Mode::XoChip,
+            "mega-chip" | "mega" => Mode::MegaChip,
+            "octo" => Mode::Octo,
+
+            _ => return None,
+        })
+    }
+
+    // Construct the runtime that implements the mode
+    //  This is the only place that needs to know about every variant; the
+    //  interpreter only ever talks to the `Runtime`.
+    fn runtime(&self, super_chip_revision: super_chip::Revision) -> Box<Runtime> {
+        match *self {
+            Mode::HiResChip8 => Box::new(Default::default(): hires_chip_8::HiResChip8),
+            Mode::Chip8x => Box::new(Default::default(): chip_8x::Chip8x),
+            Mode::Chip48 => Box::new(Default::default(): chip_48::Chip48),
+            Mode::Chip8e => Box::new(Default::default(): chip_8e::Chip8e),
+            Mode::SuperChip => Box::new(super_chip::SuperChip::new(super_chip_revision)),
+            Mode::XoChip => Box::new(Default::default(): xo_chip::XoChip),
+            Mode::MegaChip => Box::new(Default::default(): mega_chip::MegaChip),
+            Mode::Octo => Box::new(xo_chip::XoChip::octo()),
+
+            // TODO: CHIP-10 (runs as CHIP-8 until then)
+            Mode::Chip8 | Mode::Chip10 => Box::new(Default::default(): chip_8::Chip8),
+        }
+    }
+
+    // Determine the mode from the ROM contents and then its file extension
+    fn detect(filename: &str, buffer: &[u8]) -> Self {
+        if buffer.starts_with(&hires_chip_8::ENTRY_OPCODE) {
+            return Mode::HiResChip8;
+        }
+
+        match Mode::from_file(filename) {
+            // The extension is not specific; look for instructions that only
+            // exist in a specific variant
+            Mode::XoChip => Mode::from_instructions(buffer),
+
+            mode => mode,
+        }
+    }
+
+    // True if the mode has every instruction of `other` (as far as `from_instructions`
+    // distinguishes them: CHIP-8, SUPER-CHIP, XO-CHIP)
+    //  CHIP-8E and CHIP-8X reuse some of the same opcodes; they are not checked.
+    fn supports(&self, other: Mode) -> bool {
+        match *self {
+            Mode::XoChip | Mode::Octo | Mode::Chip8e | Mode::Chip8x => true,
+            Mode::SuperChip | Mode::MegaChip => other != Mode::XoChip,
+            Mode::Chip8 | Mode::HiResChip8 | Mode::Chip10 | Mode::Chip48 => other == Mode::Chip8,
+        }
+    }
+
+    // Problems with the ROM that are likely why it won't run (in the mode)
+    fn diagnose(&self, buffer: &[u8]) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if buffer.len() % 2 != 0 {
+            warnings.push(format!("ROM is an odd length ({} bytes); instructions are 2 bytes",
+                                  buffer.len()));
+        }
+
+        if buffer.starts_with(&[0x00, 0x00]) {
+            warnings.push("ROM starts with $0000 (not an instruction); is it a CHIP-8 ROM?"
+                .to_string());
+        }
+
+        let needs = Mode::from_instructions(buffer);
+        if !self.supports(needs) {
+            warnings.push(format!("ROM has instructions of {} which {} does not have",
+                                  needs.name(),
+                                  self.name()));
+        }
+
+        warnings
+    }
+
     fn from_file(filename: &str) -> Self {
         let ext = Path::new(filename).extension().unwrap_or_default().to_string_lossy();
 
         match &*ext {
             "ch10" => Mode::Chip10,
             "c8x" => Mode::Chip8x,
+            "mc8" => Mode::MegaChip,
             _ => Mode::XoChip,
         }
     }
+
+    // Select the least extended variant that supports every instruction (that
+    // is recognized) in the ROM
+    //  Instructions are scanned at every even offset; data in the ROM can be
+    //  mistaken for instructions so this errs towards more extended variants.
+    fn from_instructions(buffer: &[u8]) -> Self {
+        let mut mode = Mode::Chip8;
+
+        for word in buffer.chunks(2) {
+            if word.len() < 2 {
+                break;
+            }
+
+            let (hi, lo) = (word[0], word[1]);
+            let op = ((hi >> 4), (hi & 0xF), (lo >> 4), (lo & 0xF));
+
+            match op {
+                // F000 NNNN, plane N, audio, pitch, scroll up, 5XY2 / 5XY3
+                (0xF, 0x0, 0x0, 0x0) |
+                (0xF, _, 0x0, 0x1) |
+                (0xF, 0x0, 0x0, 0x2) |
+                (0xF, _, 0x3, 0xA) |
+                (0x0, 0x0, 0xD, _) |
+                (0x5, _, _, 0x2) |
+                (0x5, _, _, 0x3) => {
+                    return Mode::XoChip;
+                }
+
+                // Scroll, exit, low / high, big font, RPL user flags
+                (0x0, 0x0, 0xC, _) |
+                (0x0, 0x0, 0xF, 0xB) |
+                (0x0, 0x0, 0xF, 0xC) |
+                (0x0, 0x0, 0xF, 0xD) |
+                (0x0, 0x0, 0xF, 0xE) |
+                (0x0, 0x0, 0xF, 0xF) |
+                (0xF, _, 0x3, 0x0) |
+                (0xF, _, 0x7, 0x5) |
+                (0xF, _, 0x8, 0x5) => {
+                    mode = Mode::SuperChip;
+                }
+
+                _ => {}
+            }
+        }
+
+        mode
+    }
 }
 
-#[derive(Default)]
+// What is done for instructions the variant does not have
+#[derive(Clone, Copy, PartialEq)]
+pub enum OpcodePolicy {
+    // Skip the instruction (as if it were a NOP)
+    Ignore,
+
+    // Stop the program (after the instruction)
+    Halt,
+
+    // Stop the program at the instruction (so it can be inspected)
+    Trap,
+}
+
+impl Default for OpcodePolicy {
+    fn default() -> Self {
+        OpcodePolicy::Halt
+    }
+}
+
+// What is done for `0NNN` (call the machine code routine at NNN) that the variant
+// does not have; the routines are part of the original interpreter (or the program)
+// and can't be run
+#[derive(Clone, Copy, PartialEq)]
+pub enum MachineCallPolicy {
+    // Skip the instruction (as if it were a NOP)
+    Ignore,
+
+    // Skip the instruction; the first call from each address is logged
+    Log,
+
+    // Stop the program at the instruction (so it can be inspected)
+    Trap,
+}
+
+impl Default for MachineCallPolicy {
+    fn default() -> Self {
+        MachineCallPolicy::Log
+    }
+}
+
+// Why the program was stopped (other than by exiting)
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fault {
+    // Access past the end of RAM by the instruction at `pc` (`AddressPolicy::Trap`)
+    Address { address: usize, pc: usize },
+
+    // Instruction at `pc` that the variant does not have
+    Opcode { opcode: u16, pc: usize },
+
+    // Call of the machine code routine at `address` by the instruction at `pc`
+    // (`MachineCallPolicy::Trap`)
+    MachineCall { address: usize, pc: usize },
+
+    // Write to the protected interpreter area by the instruction at `pc`
+    ProtectedWrite { address: usize, pc: usize },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::Address { address, pc } => {
+                write!(f, "access past the end of RAM (${:X}) at ${:03X}", address, pc)
+            }
+
+            Fault::Opcode { opcode, pc } => {
+                write!(f, "unsupported instruction ${:04X} at ${:03X}", opcode, pc)
+            }
+
+            Fault::MachineCall { address, pc } => {
+                write!(f, "machine call to ${:03X} at ${:03X}", address, pc)
+            }
+
+            Fault::ProtectedWrite { address, pc } => {
+                write!(f, "write to the interpreter area (${:03X}) at ${:03X}", address, pc)
+            }
+        }
+    }
+}
+
+// Summary of the state of the program (after a step; for debuggers)
+#[derive(Clone, Copy, Debug)]
+pub struct State {
+    pub pc: usize,
+    pub i: usize,
+    pub sp: u8,
+    pub v: [u8; 16],
+    pub dt: u8,
+    pub st: u8,
+
+    // Set when the program has exited or was stopped (see `Interpreter::fault`)
+    pub halted: bool,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PC=${:03X} I=${:03X} SP={} DT={} ST={}", self.pc, self.i, self.sp, self.dt, self.st)?;
+
+        for (x, v) in self.v.iter().enumerate() {
+            write!(f, " V{:X}={:02X}", x, v)?;
+        }
+
+        if self.halted {
+            write!(f, " (halted)")?;
+        }
+
+        Ok(())
+    }
+}
+
+// Subroutine call on the call stack (see `Interpreter::call_stack`)
+#[derive(Clone, Copy, Debug)]
+pub struct Call {
+    // Address of the `CALL`
+    pub site: usize,
+
+    // Address of the subroutine; `None` if not known (the call was made before
+    // the state was loaded)
+    pub target: Option<usize>,
+
+    // Address returned to
+    pub return_address: usize,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            Some(target) => write!(f, "${:03X}: CALL ${:03X}", self.site, target)?,
+            None => write!(f, "${:03X}: CALL", self.site)?,
+        }
+
+        write!(f, " (returns to ${:03X})", self.return_address)
+    }
+}
+
+// Register (or timer) of the program; for debuggers and scripts
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Register {
+    // V0 .. VF
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Register::V(x) => write!(f, "V{:X}", x & 0xF),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+// Resolution the screen is addressed at (SUPER-CHIP and later)
+#[derive(PartialEq, Clone, Copy)]
+pub enum DisplayMode {
+    // 64x32; each dot is a 2x2 region of the screen
+    Standard,
+
+    // 128x64; each dot is a single dot of the screen
+    Extended,
+}
+
+impl Default for DisplayMode {
+    fn default() -> Self {
+        DisplayMode::Standard
+    }
+}
+
+// Seed of `CXNN` (RND) in lockstep unless one is set
+const LOCKSTEP_SEED: u64 = 0;
+
+// Hex keys that are down on each keypad (bit `n` for key `n`)
+pub type Input = [u16; 2];
+
+#[derive(Clone, Default)]
 pub struct Context {
     // Framebuffer / Video RAM
-    pub framebuffer: Vec<u8>,
+    //  Each entry is a 0xRRGGBB color; this is converted to the pixel
+    //  format requested by the runtime once per frame.
+    pub framebuffer: Vec<u32>,
 
     // General registers (16x 8-bit)
     pub v: [u8; 16],
@@ -57,15 +441,81 @@ pub struct Context {
 
     // Display buffer (screen) and active resolution
     //  screen.len() == screen_width * screen_height
-    pub screen: Vec<bool>,
+    //  Each dot is a mask of the (bit)planes it is set in; only XO-CHIP has more than one.
+    pub screen: Vec<u8>,
     pub screen_width: usize,
     pub screen_height: usize,
 
+    // Planes that are drawn to, cleared, and scrolled (XO-CHIP)
+    pub plane_mask: u8,
+
+    // Colors of a dot by the planes it is set in (0xRRGGBB)
+    //  palette[0] is the background; palette[1] is used by all single-plane variants.
+    pub palette: [u32; 4],
+
+    // Phase (brightness) of each dot in the screen; dots that are turned off
+    // fade out over a few frames instead of disappearing at once
+    pub phase: Vec<video::Phase>,
+
+    // Quirks of the variant (or as overridden); consulted by instructions that
+    // behave differently between interpreters
+    pub quirks: quirks::Quirks,
+
+    // Active display mode; consulted by DRW and scrolls that depend on the resolution
+    pub display_mode: DisplayMode,
+
+    // Set when the screen (or anything else affecting its presentation)
+    // has changed since the framebuffer was last updated
+    pub dirty: bool,
+
     // Delay timer
     pub dt: u8,
 
     // Sound timer
     pub st: u8,
+
+    // Source of `CXNN` (RND)
+    pub rng: rng::Rng,
+
+    // Audio pattern buffer (128 1-bit samples) and the pitch it is played back at
+    //  Only XO-CHIP can change these.
+    pub audio_pattern: [u8; 16],
+    pub pitch: u8,
+
+    // Set when the program has exited (SUPER-CHIP `00FD`); no further
+    // instructions are executed until reset
+    pub halted: bool,
+
+    // Keys of the keyboard mapped to the hex keypad (overriding the default layout)
+    pub keymap: Option<[axal::Key; 16]>,
+
+    // Hex keys that are down on each keypad (bit `n` for key `n`; the second
+    // keypad is only used by CHIP-8X)
+    //  Latched at the start of every frame (see `Interpreter::set_input`) so that a
+    //  frame is run the same given the same input (movies).
+    pub input: Input,
+
+    // Set while `FX0A` waits for a key; with the keys that were down when last checked
+    pub key_wait: bool,
+    pub keys_down: u16,
+
+    // Set when a sprite is to be drawn and the variant waits for the vertical blank
+    // (display wait quirk); no further instructions are executed in the frame
+    pub vblank_wait: bool,
+
+    // Set at the vertical blank until the first instruction of the frame has run
+    pub in_vblank: bool,
+
+    // Sprites drawn since the ROM was inserted (for statistics)
+    pub draws: u64,
+
+    // Set to run without anything from outside the emulation (the wall clock,
+    // entropy, files) so that the same input always runs the same (lockstep)
+    pub lockstep: bool,
+
+    // Set while running frames that are rolled back (see `Interpreter::set_speculative`);
+    // nothing outside the emulation is changed (files, the clock)
+    pub speculative: bool,
 }
 
 impl Context {
@@ -82,13 +532,314 @@ impl Context {
         self.dt = 0;
         self.st = 0;
 
+        // Reset audio to the default buzzer
+        self.audio_pattern = audio::DEFAULT_PATTERN;
+        self.pitch = audio::DEFAULT_PITCH;
+
+        self.halted = false;
+        self.vblank_wait = false;
+        self.in_vblank = false;
+        self.key_wait = false;
+
         // Clear framebuffer
         self.framebuffer.clear();
 
         // Clear screen
         for dot in &mut self.screen {
-            *dot = false;
+            *dot = 0;
+        }
+
+        for phase in &mut self.phase {
+            *phase = Default::default();
+        }
+
+        // Select the first plane (the only one for all but XO-CHIP)
+        self.plane_mask = 1;
+
+        self.display_mode = DisplayMode::Standard;
+        self.dirty = true;
+    }
+
+    // Hash the state of the program that affects what it does next (registers,
+    // screen, timers, etc.); not its presentation (fading, palette)
+    fn hash(&self, h: &mut hash::Fnv1a) {
+        h.write(&self.v);
+        h.write_usize(self.i);
+        h.write_usize(self.pc);
+        h.write_u8(self.sp);
+        h.write_usize(self.stack_len);
+
+        h.write_usize(self.screen_width);
+        h.write_usize(self.screen_height);
+        h.write(&self.screen);
+        h.write_u8(self.plane_mask);
+        h.write_bool(self.display_mode == DisplayMode::Extended);
+
+        let mut quirks: Writer = Default::default();
+        self.quirks.save(&mut quirks);
+        h.write(&quirks.into_bytes());
+
+        h.write_u8(self.dt);
+        h.write_u8(self.st);
+        h.write_u64(self.rng.state());
+        h.write(&self.audio_pattern);
+        h.write_u8(self.pitch);
+
+        h.write_bool(self.halted);
+        h.write_bool(self.key_wait);
+        h.write_u16(self.keys_down);
+        h.write_bool(self.vblank_wait);
+        h.write_bool(self.in_vblank);
+    }
+
+    // Save the state of the program that peers must agree on (for sync states);
+    // as `hash`, less the stack size and quirks (settings that peers set alike)
+    fn save_sync(&self, w: &mut Writer) {
+        w.bytes(&self.v);
+        w.usize(self.i);
+        w.usize(self.pc);
+        w.u8(self.sp);
+
+        w.usize(self.screen_width);
+        w.usize(self.screen_height);
+        w.bytes(&self.screen);
+        w.u8(self.plane_mask);
+        w.bool(self.display_mode == DisplayMode::Extended);
+
+        w.u8(self.dt);
+        w.u8(self.st);
+        w.u64(self.rng.state());
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+
+        w.bool(self.halted);
+        w.bool(self.key_wait);
+        w.u16(self.keys_down);
+        w.bool(self.vblank_wait);
+        w.bool(self.in_vblank);
+    }
+
+    // Restore the state saved by `save_sync`; dots that were fading are no longer
+    //  The screen must be of `screen_size` (that of the variant).
+    fn load_sync(&mut self, r: &mut Reader, screen_size: (usize, usize)) -> Result<(), Error> {
+        r.bytes_into(&mut self.v)?;
+        self.i = r.usize()?;
+        self.pc = r.usize()?;
+        self.sp = r.u8()?;
+
+        let width = r.usize()?;
+        let height = r.usize()?;
+        let screen = r.bytes()?;
+        if (width, height) != screen_size || screen.len() != width * height {
+            return Err(Error::InvalidState);
+        }
+
+        self.resize_screen(width, height);
+        self.screen.copy_from_slice(screen);
+        for (dot, phase) in self.screen.iter().zip(self.phase.iter_mut()) {
+            phase.planes = *dot;
+            phase.level = if *dot != 0 { 1.0 } else { 0.0 };
+        }
+
+        self.plane_mask = r.u8()?;
+        self.display_mode = if r.bool()? {
+            DisplayMode::Extended
+        } else {
+            DisplayMode::Standard
+        };
+
+        self.dt = r.u8()?;
+        self.st = r.u8()?;
+        self.rng = rng::Rng::from_state(r.u64()?);
+        r.bytes_into(&mut self.audio_pattern)?;
+        self.pitch = r.u8()?;
+
+        self.halted = r.bool()?;
+        self.key_wait = r.bool()?;
+        self.keys_down = r.u16()?;
+        self.vblank_wait = r.bool()?;
+        self.in_vblank = r.bool()?;
+
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    // Save the state of the program (for savestates); the keymap and the
+    // framebuffer (which is redrawn from the screen) are not part of it
+    fn save(&self, w: &mut Writer) {
+        w.bytes(&self.v);
+        w.usize(self.i);
+        w.usize(self.pc);
+        w.u8(self.sp);
+        w.usize(self.stack_len);
+
+        w.usize(self.screen_width);
+        w.usize(self.screen_height);
+        w.bytes(&self.screen);
+        for phase in &self.phase {
+            w.u8(phase.planes);
+            w.f32(phase.level);
+        }
+
+        w.u8(self.plane_mask);
+        w.u32s(&self.palette);
+        self.quirks.save(w);
+        w.bool(self.display_mode == DisplayMode::Extended);
+
+        w.u8(self.dt);
+        w.u8(self.st);
+        w.u64(self.rng.state());
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+
+        w.bool(self.halted);
+        w.bool(self.key_wait);
+        w.u16(self.keys_down);
+        w.bool(self.vblank_wait);
+        w.bool(self.in_vblank);
+    }
+
+    // Restore the state saved by `save`; the screen must be of `screen_size` (that
+    // of the variant)
+    fn load(&mut self, r: &mut Reader, screen_size: (usize, usize)) -> Result<(), Error> {
+        r.bytes_into(&mut self.v)?;
+        self.i = r.usize()?;
+        self.pc = r.usize()?;
+        self.sp = r.u8()?;
+        self.stack_len = r.usize()?;
+
+        let width = r.usize()?;
+        let height = r.usize()?;
+        let screen = r.bytes()?;
+        if (width, height) != screen_size || screen.len() != width * height {
+            return Err(Error::InvalidState);
+        }
+
+        self.resize_screen(width, height);
+        self.screen.copy_from_slice(screen);
+        for phase in &mut self.phase {
+            phase.planes = r.u8()?;
+            phase.level = r.f32()?;
+        }
+
+        self.plane_mask = r.u8()?;
+        let palette = r.u32s()?;
+        if palette.len() != self.palette.len() {
+            return Err(Error::InvalidState);
+        }
+
+        self.palette.copy_from_slice(&palette);
+        self.quirks = quirks::Quirks::load(r)?;
+        self.display_mode = if r.bool()? {
+            DisplayMode::Extended
+        } else {
+            DisplayMode::Standard
+        };
+
+        self.dt = r.u8()?;
+        self.st = r.u8()?;
+        self.rng = rng::Rng::from_state(r.u64()?);
+        r.bytes_into(&mut self.audio_pattern)?;
+        self.pitch = r.u8()?;
+
+        self.halted = r.bool()?;
+        self.key_wait = r.bool()?;
+        self.keys_down = r.u16()?;
+        self.vblank_wait = r.bool()?;
+        self.in_vblank = r.bool()?;
+
+        self.dirty = true;
+
+        Ok(())
+    }
+
+    pub fn resize_screen(&mut self, width: usize, height: usize) {
+        self.screen_width = width;
+        self.screen_height = height;
+        self.screen.resize(width * height, Default::default());
+        self.phase.resize(width * height, Default::default());
+        self.dirty = true;
+    }
+
+    // Clear the selected planes of the screen
+    pub fn clear_screen(&mut self) {
+        for dot in &mut self.screen {
+            *dot &= !self.plane_mask;
+        }
+
+        self.dirty = true;
+    }
+
+    // Scroll the selected planes of the screen by (dx, dy) dots; dots scrolled
+    // off the screen are lost and dots scrolled in are off
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.screen_width as isize;
+        let height = self.screen_height as isize;
+        let previous = self.screen.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let sx = x - dx;
+                let sy = y - dy;
+
+                let dot = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    previous[(sy * width + sx) as usize]
+                } else {
+                    0
+                };
+
+                let offset = (y * width + x) as usize;
+                self.screen[offset] = (previous[offset] & !self.plane_mask) |
+                                      (dot & self.plane_mask);
+            }
+        }
+
+        self.dirty = true;
+    }
+
+    // Key of the keyboard mapped to hex key `n`
+    pub fn key(&self, n: u8) -> axal::Key {
+        self.keymap.unwrap_or(chip_8::KEYBOARD_MAP)[(n & 0xF) as usize]
+    }
+
+    // Wait for the vertical blank before drawing if the variant does so (display
+    // wait quirk); returns true if the instruction (at PC - 2) must wait
+    //  The COSMAC VIP only draws in the vertical blank (so sprites never tear);
+    //  the instruction is repeated as the first of the next frame.
+    pub fn wait_for_vblank(&mut self) -> bool {
+        if !self.quirks.display_wait || self.in_vblank {
+            return false;
         }
+
+        self.vblank_wait = true;
+        self.pc = self.pc.wrapping_sub(2);
+
+        true
+    }
+
+    // Hex keys that are down on each keypad (as the runtime reports them now)
+    pub fn read_input(&self, r: &mut axal::Runtime) -> Input {
+        let mut input = [0u16; 2];
+        for (port, down) in input.iter_mut().enumerate() {
+            for key in 0..16 {
+                if r.input_keyboard_state(port as u8, self.key(key)) {
+                    *down |= 1 << key;
+                }
+            }
+        }
+
+        input
+    }
+
+    // True if hex key `n` is down on `keypad` (0 or 1) as latched for the frame
+    pub fn is_key_down(&self, keypad: usize, n: u8) -> bool {
+        self.input[keypad] & (1 << (n & 0xF)) != 0
+    }
+
+    // Address in RAM of the entry of the stack at `sp`
+    pub fn stack_address(sp: u8) -> usize {
+        0x100 + (sp as usize) * 2
     }
 
     pub fn stack_push(&mut self, m: &mut mmu::Mmu, value: u16) {
@@ -96,15 +847,15 @@ impl Context {
         self.sp = self.sp.wrapping_add(1);
 
         // Write to RAM
-        let address = 0x100 + (self.sp as usize) * 2;
+        let address = Context::stack_address(self.sp);
 
-        m.write(address, (value >> 8) as u8);
-        m.write(address + 1, (value & 0xFF) as u8);
+        m.write_privileged(address, (value >> 8) as u8);
+        m.write_privileged(address + 1, (value & 0xFF) as u8);
     }
 
     pub fn stack_pop(&mut self, m: &mut mmu::Mmu) -> u16 {
         // Read from RAM
-        let address = 0x100 + ((self.sp as usize) * 2);
+        let address = Context::stack_address(self.sp);
 
         let hi = m.read(address);
         let lo = m.read(address + 1);
@@ -116,16 +867,68 @@ impl Context {
     }
 }
 
-pub trait Runtime {
+// Copy of a runtime behind a `Box` (for snapshots); implemented for every
+// runtime that is `Clone`
+pub trait RuntimeClone {
+    fn box_clone(&self) -> Box<Runtime>;
+}
+
+impl<T: 'static + Runtime + Clone> RuntimeClone for T {
+    fn box_clone(&self) -> Box<Runtime> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<Runtime> {
+    fn clone(&self) -> Box<Runtime> {
+        self.box_clone()
+    }
+}
+
+// Behavior of a variant (CHIP-8, SUPER-CHIP, XO-CHIP, etc.)
+//  The interpreter owns the fetch/execute loop, timers, video and audio and
+//  consults the runtime for anything that differs between variants. A variant
+//  is built from the one it extends by composition: it handles the opcodes
+//  (and quirks) that differ and hands everything else to the runtime it wraps,
+//  so no variant duplicates the dispatch of another.
+pub trait Runtime: RuntimeClone {
     // Initialize the context and RAM for the usage of this runtime
     fn configure(&mut self, c: &mut Context) {}
 
     // Reset state
     fn reset(&mut self, c: &mut Context) {}
 
+    // Size of addressable RAM (a power of 2)
+    fn memory_size(&self) -> usize {
+        mmu::DEFAULT_SIZE
+    }
+
+    // Quirks of the variant
+    fn quirks(&self) -> quirks::Quirks {
+        Default::default()
+    }
+
+    // Instructions run per (60 Hz) frame
+    fn instructions_per_frame(&self) -> usize {
+        8
+    }
+
+    // Address the ROM is loaded at (and execution begins at)
+    fn rom_address(&self) -> usize {
+        0x200
+    }
+
+    // Save the state of the runtime that is not in the context or RAM (for savestates)
+    fn save_state(&self, w: &mut Writer) {}
+
+    // Restore the state saved by `save_state`
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error> {
+        Ok(())
+    }
+
     // Insert ROM
     fn insert_rom(&mut self, m: &mut mmu::Mmu, buffer: &[u8]) {
-        m.write_all(0x200, buffer);
+        m.write_all(self.rom_address(), buffer);
     }
 
     // Update framebuffer (in context)
@@ -138,15 +941,29 @@ pub trait Runtime {
             for x in 0..c.screen_width {
                 let offset = offset_y + x;
 
-                // Get dot from screen
-                let dot = c.screen[offset];
+                // Get (phase of) dot from screen
+                let phase = c.phase[offset];
 
                 // Blit to framebuffer
-                c.framebuffer[offset] = if dot { 0xFF } else { 0x00 };
+                c.framebuffer[offset] = phase.color(&c.palette);
             }
         }
     }
 
+    // Render `count` (stereo) samples of audio into `out`
+    fn render_audio(&mut self,
+                    c: &Context,
+                    position: &mut f32,
+                    count: usize,
+                    out: &mut Vec<i16>) {
+        audio::render(c, position, count, out);
+    }
+
+    // Dimensions of the framebuffer (as presented to the runtime)
+    fn framebuffer_size(&self, c: &Context) -> (usize, usize) {
+        (c.screen_width, c.screen_height)
+    }
+
     // Execute passed operation; return false if unhandled
     fn execute(&mut self,
                r: &mut axal::Runtime,
@@ -156,6 +973,22 @@ pub trait Runtime {
                -> bool;
 }
 
+// Copy of the emulated state of the interpreter (kept in memory; eg. for run-ahead)
+#[derive(Clone)]
+pub struct Snapshot {
+    context: Context,
+    mmu: mmu::Mmu,
+    mode: Option<Mode>,
+    calls: Vec<Call>,
+    runtime: Option<Box<Runtime>>,
+    fault: Option<Fault>,
+    idle: bool,
+    instructions: u64,
+    instruction_remainder: usize,
+    cycles_owed: usize,
+    audio_position: f32,
+}
+
 #[derive(Default)]
 pub struct Interpreter {
     // Shared context used by all variants
@@ -164,21 +997,129 @@ pub struct Interpreter {
     // Memory management unit (incl. RAM)
     mmu: mmu::Mmu,
 
-    // Active runtime (CHIP-8, CHIP-8X, etc.)
+    // Active runtime (CHIP-8, CHIP-8X, etc.) and the mode it implements
     runtime: Option<Box<Runtime>>,
+    mode: Option<Mode>,
+
+    // Quirks overriding those of the runtime
+    quirks: Option<quirks::Quirks>,
+
+    // What is done for instructions the variant does not have
+    opcode_policy: OpcodePolicy,
+
+    // What is done for `0NNN` and the addresses calls were logged from
+    machine_call_policy: MachineCallPolicy,
+    logged_machine_calls: Vec<usize>,
+
+    // Protect the interpreter area from writes by the program
+    write_protection: bool,
+
+    // Instructions run since the ROM was inserted (for statistics)
+    instructions: u64,
+
+    // Why the program was stopped (if it was)
+    fault: Option<Fault>,
+
+    // Set if the last instruction left PC where it was (see `is_idle`)
+    idle: bool,
+
+    // Set once an access past the end of RAM was reported (`AddressPolicy::Warn`)
+    address_warned: bool,
 
-    // 60 Hz timer that controls DT / ST
-    timer_elapsed: u64,
-    timer_instant: Option<Instant>,
+    // Addresses execution stops at before running the instruction there (if the
+    // condition holds); the breakpoint the last instruction stopped at; and the
+    // one stopped at before (its instruction is run when execution continues)
+    breakpoints: Vec<(usize, Option<Condition>)>,
+    breakpoint_hit: Option<usize>,
+    stopped_at: Option<usize>,
+
+    // Stack depth (SP) execution stops at once the stack is back to it (step over /
+    // out); and the address stopped at when it was
+    step_depth: Option<u8>,
+    step_hit: Option<usize>,
+
+    // Access by the last instruction that hit a watchpoint (the instruction was run)
+    watch_hit: Option<mmu::WatchHit>,
+
+    // Subroutines the program is in (the innermost last); kept beside the stack in
+    // RAM, which only has the addresses returned to
+    calls: Vec<Call>,
+
+    // Last instructions run (if enabled)
+    trace: Trace,
+
+    // Observers of execution (by the id they were added as; see `add_hooks`) and
+    // the id of the next
+    hooks: Vec<(usize, Box<Hooks>)>,
+    next_hooks_id: usize,
+
+    // Instructions run per second (overriding the speed of the runtime)
+    instructions_per_second: Option<usize>,
+
+    // Rate frames are run at (the runtime calls `run_next` at this rate)
+    frame_rate: video::FrameRate,
+
+    // How long instructions take; and the machine cycles the last frame ran over
+    // by (`Timing::CosmacVip`)
+    timing: Timing,
+    cycles_owed: usize,
+
+    // Instructions (in frames) owed from previous frames; a speed that is not a
+    // multiple of the frame rate runs the fractions over the following frames
+    instruction_remainder: usize,
+
+    // Hash of the inserted ROM (see `hash::fnv1a`); identifies its savestates
+    rom_hash: Option<u64>,
+
+    // Revision of the SUPER-CHIP to behave as (in SUPER-CHIP mode)
+    super_chip_revision: super_chip::Revision,
+
+    // Pixel format requested by the runtime and the converted framebuffer
+    pixel_format: video::PixelFormat,
+    output: Vec<u8>,
+
+    // Framebuffer (and its size) that was last presented; used to detect
+    // frames that are identical even though something was drawn
+    presented: Vec<u32>,
+    presented_size: (usize, usize),
+
+    // Post-processing (and scaling) applied to presented frames
+    filter: filter::Filter,
+    scale: video::Scale,
+    filtered: Vec<u32>,
+
+    // Rotation applied to presented frames (for vertical displays)
+    rotation: video::Rotation,
+    rotated: Vec<u32>,
+
+    // Fade-out of dots turned off (flicker reduction)
+    phosphor_decay: video::PhosphorDecay,
+
+    // Colors of dots by plane (overriding the default palette)
+    plane_colors: Option<[video::Color; 4]>,
+
+    // Position in the audio pattern and the samples rendered for the last frame
+    audio_position: f32,
+    audio_samples: Vec<i16>,
+
+    // Source of the ticks that clock DT / ST (one per frame if not set)
+    clock: Option<Box<Clock>>,
+
+    // Seed of RND as set (from entropy on each reset if not set)
+    seed: Option<u64>,
 }
 
 impl Interpreter {
     pub fn configure(&mut self) {
+        // The interpreter area is written below (font)
+        self.mmu.set_protected(0);
+
         // The standard screen size is 64x32
-        self.context.screen_width = 64;
-        self.context.screen_height = 32;
-        self.context.screen.resize(self.context.screen_width * self.context.screen_height,
-                                   Default::default());
+        self.context.resize_screen(64, 32);
+
+        // Standard colors (white on black); the runtime may change these and
+        // the user may override them (below)
+        self.context.palette = video::DEFAULT_PALETTE;
 
         // TODO: Allow stack_len to be controlled somewhere
         self.context.stack_len = 256;
@@ -280,43 +1221,183 @@ impl Interpreter {
         self.mmu.write(0x4E, 0x80);
         self.mmu.write(0x4F, 0x80);
 
-        // TODO: Compatibility flags should be in here
+        // Setup 10-byte font sprites (SUPER-CHIP)
+        self.mmu.write_all(FONT_10_ADDRESS, &FONT_10);
+
+        // Protect the interpreter area (now that it is written)
+        self.apply_write_protection();
 
         // Configure runtime
         if let Some(ref mut runtime) = self.runtime {
             runtime.configure(&mut self.context);
         }
-    }
 
-    pub fn insert_rom(&mut self, filename: &str, mode: Option<Mode>) {
-        // Determine mode
-        let mode = mode.unwrap_or_else(|| Mode::from_file(filename));
+        if let Some(colors) = self.plane_colors {
+            self.context.palette = colors;
+        }
 
-        // Construct runtime
-        // TODO: Support other modes
-        self.runtime = Some(match mode {
-            Mode::Chip8x => Box::new(Default::default(): chip_8x::Chip8x),
+        self.apply_quirks();
+    }
 
-            _ => {
-                // TODO: Use XO-CHIP here
-                Box::new(Default::default(): chip_8::Chip8)
-            }
-        });
+    // Quirks in effect
+    pub fn quirks(&self) -> quirks::Quirks {
+        self.context.quirks
+    }
 
-        // Read in ROM
-        let mut stream = File::open(filename).unwrap();
+    pub fn set_quirks(&mut self, quirks: Option<quirks::Quirks>) {
+        self.quirks = quirks;
+        self.apply_quirks();
+    }
+
+    // Select the quirks of the runtime unless overridden (by the user)
+    fn apply_quirks(&mut self) {
+        self.context.quirks = match (self.quirks, &self.runtime) {
+            (Some(quirks), _) => quirks,
+            (None, &Some(ref runtime)) => runtime.quirks(),
+            (None, &None) => Default::default(),
+        };
+    }
+
+    // Instructions run per second by the active runtime (or as set)
+    pub fn instructions_per_second(&self) -> usize {
+        if let Some(instructions_per_second) = self.instructions_per_second {
+            return instructions_per_second;
+        }
+
+        match self.runtime {
+            Some(ref runtime) => runtime.instructions_per_frame() * 60,
+            None => 8 * 60,
+        }
+    }
+
+    // `None` restores the speed of the runtime
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: Option<usize>) {
+        self.instructions_per_second = instructions_per_second;
+        self.instruction_remainder = 0;
+    }
+
+    // Instructions to run in the next frame; carries the fraction of an
+    // instruction left over to the frames after (so eg. 540 Hz runs 9 per frame
+    // and 500 Hz alternates between 8 and 9 at 60 Hz)
+    pub fn next_frame_instructions(&mut self) -> usize {
+        let hz = self.frame_rate.hz();
+        self.instruction_remainder += self.instructions_per_second();
+
+        let instructions = self.instruction_remainder / hz;
+        self.instruction_remainder %= hz;
+
+        instructions
+    }
+
+    // Time available to the program in the next frame: instructions, or machine
+    // cycles (`Timing::CosmacVip`) less those the last frame ran over by
+    pub fn next_frame_cycles(&mut self) -> usize {
+        if self.timing == Timing::Uniform {
+            return self.next_frame_instructions();
+        }
+
+        let cycles = timing::vip_cycles_per_frame(self.frame_rate.hz());
+        let owed = cmp::min(cycles, self.cycles_owed);
+        self.cycles_owed -= owed;
+
+        cycles - owed
+    }
+
+    // Take `cycles` (that an instruction ran past the end of the frame) from the next frame
+    pub fn owe_cycles(&mut self, cycles: usize) {
+        self.cycles_owed += cycles;
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycles_owed = 0;
+    }
+
+    pub fn frame_rate(&self) -> video::FrameRate {
+        self.frame_rate
+    }
+
+    // The speed (in instructions per second) is kept; there are fewer (or more)
+    // instructions per frame
+    pub fn set_frame_rate(&mut self, frame_rate: video::FrameRate) {
+        self.frame_rate = frame_rate;
+        self.instruction_remainder = 0;
+    }
+
+    // `None` restores the default layout
+    pub fn set_keymap(&mut self, keymap: Option<[axal::Key; 16]>) {
+        self.context.keymap = keymap;
+    }
+
+    // Insert the ROM at `filename`; the interpreter is left unchanged if it can't be loaded
+    pub fn insert_rom(&mut self, filename: &str, mode: Option<Mode>) -> Result<(), Error> {
+        // Read in ROM
+        let mut stream = File::open(filename)?;
         let mut buffer = Vec::new();
-        stream.read_to_end(&mut buffer).unwrap();
+        stream.read_to_end(&mut buffer)?;
 
-        // Insert ROM
-        if let Some(ref mut runtime) = self.runtime {
-            runtime.insert_rom(&mut self.mmu, &buffer);
+        if buffer.is_empty() {
+            return Err(Error::Empty);
+        }
+
+        // Determine mode (unless forced)
+        let mode = match mode {
+            Some(mode) => mode,
+            None => {
+                let mode = Mode::detect(filename, &buffer);
+                println!("xCHIP: detected mode {} for {}", mode.name(), filename);
+
+                mode
+            }
+        };
+
+        // Report why the ROM may not run (it is run anyway)
+        for warning in mode.diagnose(&buffer) {
+            eprintln!("warning: {}: {}", filename, warning);
         }
 
+        // Construct runtime
+        let mut runtime = mode.runtime(self.super_chip_revision);
+
+        // The ROM must fit in RAM from where it is loaded; it would otherwise
+        // wrap around over the font (and itself)
+        let max_size = runtime.memory_size() - runtime.rom_address();
+        if buffer.len() > max_size {
+            return Err(Error::TooLarge {
+                size: buffer.len(),
+                max_size: max_size,
+                mode: mode.name(),
+            });
+        }
+
+        // Insert ROM (into RAM sized for the runtime)
+        self.mmu.resize(runtime.memory_size());
+        runtime.insert_rom(&mut self.mmu, &buffer);
+        self.runtime = Some(runtime);
+        self.mode = Some(mode);
+        self.rom_hash = Some(hash::fnv1a(&buffer));
+
+        // Loading the ROM is not the program accessing RAM
+        self.mmu.take_fault();
+        self.mmu.take_write_fault();
+
+        self.instructions = 0;
+        self.context.draws = 0;
+
         // Configure interpreter (and associated runtime)
         // The hook is here to allow for ROMs to eventually control
         // any parameters here.
         self.configure();
+
+        // Nor is writing the font (or the ROM; watchpoints)
+        self.mmu.take_watch_hit();
+
+        Ok(())
+    }
+
+    // Takes effect when the next ROM is inserted
+    pub fn set_super_chip_revision(&mut self, revision: super_chip::Revision) {
+        self.super_chip_revision = revision;
     }
 
     pub fn remove_rom(&mut self) {
@@ -325,11 +1406,26 @@ impl Interpreter {
 
         // Release runtime
         self.runtime = None;
+        self.mode = None;
+        self.rom_hash = None;
+        self.calls.clear();
+        self.trace.clear();
+        self.context.dirty = true;
     }
 
     pub fn reset(&mut self) {
         // Reset context
         self.context.reset();
+        self.fault = None;
+        self.address_warned = false;
+        self.logged_machine_calls.clear();
+        self.idle = false;
+        self.instruction_remainder = 0;
+        self.cycles_owed = 0;
+        self.step_depth = None;
+        self.calls.clear();
+        self.trace.clear();
+        self.reseed();
 
         // Reset associated runtime
         if let Some(ref mut runtime) = self.runtime {
@@ -337,47 +1433,1313 @@ impl Interpreter {
         }
     }
 
-    pub fn run_next(&mut self, r: &mut axal::Runtime) {
-        // If timer point reference is non-zero; check elapsed and
-        // clock ST / DT
-        if let Some(timer_instant) = self.timer_instant {
-            let elapsed = timer_instant.elapsed();
-            self.timer_elapsed += (elapsed.as_secs() * 1_000_000_000) +
-                                  (elapsed.subsec_nanos() as u64);
+    // Seed RND; the same seed gives the same sequence after each reset (for
+    // replays and netplay); `None` seeds from entropy
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+        self.reseed();
+    }
 
-            // 1/60 s => 16_666_666 ns
-            if self.timer_elapsed >= 16_666_666 {
-                self.timer_elapsed -= 16_666_666;
+    // Seed set (`None` if seeded from entropy)
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
 
-                if self.context.dt > 0 {
-                    self.context.dt -= 1;
-                }
+    // Variant of the inserted ROM
+    pub fn mode(&self) -> Option<Mode> {
+        self.mode
+    }
 
-                if self.context.st > 0 {
-                    self.context.st -= 1;
-                }
+    // Run without anything from outside the emulation: the timers tick once per
+    // frame (whatever the clock), RND is seeded with `LOCKSTEP_SEED` unless a seed
+    // is set, and persisted storage (RPL user flags) is neither read nor written
+    pub fn set_lockstep(&mut self, enabled: bool) {
+        self.context.lockstep = enabled;
+        self.reseed();
+    }
+
+    pub fn is_lockstep(&self) -> bool {
+        self.context.lockstep
+    }
+
+    fn reseed(&mut self) {
+        let seed = match self.seed {
+            Some(seed) => seed,
+            None if self.context.lockstep => LOCKSTEP_SEED,
+            None => rand::random(),
+        };
+
+        self.context.rng = rng::Rng::new(seed);
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.context.halted
+    }
+
+    // Serialize the emulated state (mode, registers, RAM, screen, timers, runtime)
+    // into a savestate (optionally compressed); `None` if no ROM is inserted
+    //  Settings (speed, palette, policies, etc.) are not part of it.
+    pub fn save_state(&self, compressed: bool) -> Option<Vec<u8>> {
+        self.save_state_with(compressed, |_| {})
+    }
+
+    // As `save_state` with further sections (written by `extra`)
+    pub fn save_state_with<F: FnOnce(&mut Writer)>(&self,
+                                                   compressed: bool,
+                                                   extra: F)
+                                                   -> Option<Vec<u8>> {
+        let mut w: Writer = Default::default();
+        self.write_state(&mut w)?;
+        extra(&mut w);
+
+        // Preview of the screen as last presented (there is none before the
+        // first frame)
+        if !self.presented.is_empty() {
+            let (width, height) = self.presented_size;
+            let thumbnail = video::Thumbnail::new(&self.presented, width, height);
+            w.section(b"THMB", |w| thumbnail.save(w));
+        }
+
+        Some(savestate::encode(&w.into_bytes(), compressed))
+    }
+
+    // Restore the emulated state from a savestate; the interpreter is left
+    // unchanged if it can't be
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let snapshot = self.snapshot();
+
+        let result = savestate::decode(state)
+            .and_then(|body| self.load_state_from(&mut Reader::new(&body)));
+
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+
+        result
+    }
+
+    fn load_state_from(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let sections = r.sections()?;
+
+        let mode = Mode::from_name(sections.get(b"MODE")?.str()?).ok_or(Error::InvalidState)?;
+        let mut runtime = mode.runtime(self.super_chip_revision);
+        let screen_size = self.screen_size(mode);
+
+        self.context.load(&mut sections.get(b"CTXT")?, screen_size)?;
+
+        let ram = sections.get(b"RAM ")?.bytes()?;
+        if ram.len() != runtime.memory_size() {
+            return Err(Error::InvalidState);
+        }
+
+        self.mmu.load_ram(ram);
+        runtime.load_state(&mut sections.get(b"RTME")?)?;
+        self.runtime = Some(runtime);
+        self.mode = Some(mode);
+
+        let mut r = sections.get(b"INTP")?;
+        self.idle = r.bool()?;
+        self.instruction_remainder = r.usize()?;
+        self.cycles_owed = r.usize()?;
+        self.audio_position = r.f32()?;
+        // A bit position within the 128-bit pattern
+        if !(self.audio_position >= 0.0 && self.audio_position < 128.0) {
+            return Err(Error::InvalidState);
+        }
+
+        self.fault = None;
+        self.rebuild_calls();
+
+        Ok(())
+    }
+
+    // Size of the screen of `mode` (as its runtime configures it); that of a
+    // state loaded must be the same
+    fn screen_size(&self, mode: Mode) -> (usize, usize) {
+        let context = self.configured_context(mode);
+
+        (context.screen_width, context.screen_height)
+    }
+
+    // Context as the runtime of `mode` configures it (from the standard screen
+    // and colors)
+    //  It is configured as in lockstep so the runtime reads nothing from disk
+    //  (eg. the SUPER-CHIP flags); only the screen and colors are of interest.
+    fn configured_context(&self, mode: Mode) -> Context {
+        let mut context: Context = Default::default();
+        context.resize_screen(64, 32);
+        context.palette = video::DEFAULT_PALETTE;
+        context.lockstep = true;
+        mode.runtime(self.super_chip_revision).configure(&mut context);
+
+        context
+    }
+
+    // Serialize the emulated state peers of a netplay session must agree on
+    // (registers, RAM, screen, timers, runtime) into a sync state; `None` if no
+    // ROM is inserted
+    //  Unlike a savestate it has nothing for presentation (fading dots, audio,
+    //  thumbnail) and no sections; it is only read by the same release running
+    //  the same ROM in the same variant (see `savestate::encode_sync`).
+    pub fn save_sync_state(&self) -> Option<Vec<u8>> {
+        let runtime = self.runtime.as_ref()?;
+
+        let mut w: Writer = Default::default();
+        self.context.save_sync(&mut w);
+        w.bytes(self.mmu.ram());
+        runtime.save_state(&mut w);
+        w.bool(self.idle);
+        w.usize(self.instruction_remainder);
+        w.usize(self.cycles_owed);
+
+        Some(savestate::encode_sync(&w.into_bytes()))
+    }
+
+    // Restore a sync state made by `save_sync_state`; nothing is changed if it is invalid
+    pub fn load_sync_state(&mut self, state: &[u8]) -> Result<(), Error> {
+        let snapshot = self.snapshot();
+
+        let result = savestate::decode_sync(state)
+            .and_then(|body| self.load_sync_state_from(&mut Reader::new(&body)));
+
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+
+        result
+    }
+
+    fn load_sync_state_from(&mut self, r: &mut Reader) -> Result<(), Error> {
+        let mode = match self.mode {
+            Some(mode) if self.runtime.is_some() => mode,
+            _ => return Err(Error::NoRom),
+        };
+
+        let screen_size = self.screen_size(mode);
+        self.context.load_sync(r, screen_size)?;
+
+        // RAM is the size of that of the variant
+        r.bytes_into(self.mmu.ram_mut())?;
+
+        if let Some(ref mut runtime) = self.runtime {
+            runtime.load_state(r)?;
+        }
+
+        self.idle = r.bool()?;
+        self.instruction_remainder = r.usize()?;
+        self.cycles_owed = r.usize()?;
+        self.fault = None;
+
+        if !r.is_empty() {
+            return Err(Error::InvalidState);
+        }
+
+        self.rebuild_calls();
+
+        Ok(())
+    }
+
+    // Hash of the emulated state (registers, RAM, screen, timers, runtime); the
+    // same state hashes the same (to detect when a movie or netplay desyncs)
+    //  Nothing is serialized but the state of the runtime (a few bytes for all
+    //  but MEGA-CHIP); all of RAM is read though (16 MiB for MEGA-CHIP).
+    pub fn state_hash(&self) -> Option<u64> {
+        let mode = self.mode?;
+        let runtime = self.runtime.as_ref()?;
+
+        let mut h: hash::Fnv1a = Default::default();
+        h.write(mode.name().as_bytes());
+        self.context.hash(&mut h);
+        h.write(self.mmu.ram());
+
+        let mut w: Writer = Default::default();
+        runtime.save_state(&mut w);
+        h.write(&w.into_bytes());
+
+        h.write_bool(self.idle);
+        h.write_usize(self.instruction_remainder);
+        h.write_usize(self.cycles_owed);
+
+        Some(h.finish())
+    }
+
+    // Write the sections of the emulated state; `None` if no ROM is inserted
+    fn write_state(&self, w: &mut Writer) -> Option<()> {
+        let mode = self.mode?;
+        let runtime = self.runtime.as_ref()?;
+
+        w.section(b"MODE", |w| w.str(mode.name()));
+        w.section(b"CTXT", |w| self.context.save(w));
+        w.section(b"RAM ", |w| w.bytes(self.mmu.ram()));
+        w.section(b"RTME", |w| runtime.save_state(w));
+        w.section(b"INTP", |w| {
+            w.bool(self.idle);
+            w.usize(self.instruction_remainder);
+            w.usize(self.cycles_owed);
+            w.f32(self.audio_position);
+        });
+
+        Some(())
+    }
+
+    // Preview of the screen kept in the savestate `state`; `None` if it has none
+    pub fn state_thumbnail(state: &[u8]) -> Option<video::Thumbnail> {
+        savestate::read_section(state, b"THMB", video::Thumbnail::load)
+    }
+
+    // Copy the emulated state (RAM, registers, screen, timers, runtime)
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            context: self.context.clone(),
+            mmu: self.mmu.clone(),
+            mode: self.mode,
+            calls: self.calls.clone(),
+            runtime: self.runtime.clone(),
+            fault: self.fault,
+            idle: self.idle,
+            instructions: self.instructions,
+            instruction_remainder: self.instruction_remainder,
+            cycles_owed: self.cycles_owed,
+            audio_position: self.audio_position,
+        }
+    }
+
+    // Return to the emulated state of `snapshot` (moved back in; take another
+    // snapshot to return to it again)
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        // Frames run after are as speculative as those before
+        let speculative = self.context.speculative;
+        self.context = snapshot.context;
+        self.context.speculative = speculative;
+
+        // What was written since the snapshot is written back (still changed)
+        let changed = self.mmu.take_changed();
+        self.mmu = snapshot.mmu;
+        if let Some((start, end)) = changed {
+            self.mmu.mark_changed(start, end);
+        }
+
+        self.mode = snapshot.mode;
+        self.calls = snapshot.calls;
+        self.runtime = snapshot.runtime;
+        self.fault = snapshot.fault;
+        self.idle = snapshot.idle;
+        self.instructions = snapshot.instructions;
+        self.instruction_remainder = snapshot.instruction_remainder;
+        self.cycles_owed = snapshot.cycles_owed;
+        self.audio_position = snapshot.audio_position;
+        self.context.dirty = true;
+    }
+
+    // Hash of the inserted ROM; `None` if no ROM is inserted
+    pub fn rom_hash(&self) -> Option<u64> {
+        self.rom_hash
+    }
+
+    // Instructions run and sprites drawn since the ROM was inserted
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn draws(&self) -> u64 {
+        self.context.draws
+    }
+
+    pub fn state(&self) -> State {
+        State {
+            pc: self.context.pc,
+            i: self.context.i,
+            sp: self.context.sp,
+            v: self.context.v,
+            dt: self.context.dt,
+            st: self.context.st,
+            halted: self.context.halted,
+        }
+    }
+
+    pub fn register(&self, register: Register) -> usize {
+        match register {
+            Register::V(x) => self.context.v[(x & 0xF) as usize] as usize,
+            Register::I => self.context.i,
+            Register::Pc => self.context.pc,
+            Register::Sp => self.context.sp as usize,
+            Register::Dt => self.context.dt as usize,
+            Register::St => self.context.st as usize,
+        }
+    }
+
+    // Set a register as the debugger (eg. while paused); values are truncated to
+    // the size of the register (I and PC wrap around as they are used)
+    pub fn set_register(&mut self, register: Register, value: usize) {
+        match register {
+            Register::V(x) => self.context.v[(x & 0xF) as usize] = value as u8,
+            Register::I => self.context.i = value,
+            Register::Pc => self.context.pc = value,
+            Register::Sp => self.context.sp = value as u8,
+            Register::Dt => self.context.dt = value as u8,
+            Register::St => self.context.st = value as u8,
+        }
+
+        // The program may no longer be spinning in place
+        self.idle = false;
+
+        if register == Register::Sp {
+            self.rebuild_calls();
+        }
+    }
+
+    // Subroutines the program is in (the innermost last)
+    pub fn call_stack(&self) -> &[Call] {
+        &self.calls
+    }
+
+    // Follow a `CALL` (SP went up) or return (SP went down) by the instruction at
+    // `pc` that was run with SP at `sp`
+    fn track_call(&mut self, pc: usize, sp: u8) {
+        if self.context.sp == sp.wrapping_add(1) {
+            let return_address = self.stack_entry(self.context.sp);
+
+            self.calls.push(Call {
+                site: pc,
+                target: Some(self.context.pc),
+                return_address: return_address,
+            });
+        } else if self.context.sp == sp.wrapping_sub(1) {
+            self.calls.pop();
+        }
+
+        // There is a call for each entry of the stack; once SP wraps around (a
+        // program that never returns) they are rebuilt so they can't grow past it
+        if self.calls.len() != self.context.sp as usize {
+            self.rebuild_calls();
+        }
+    }
+
+    // Rebuild the calls from the stack in RAM (a state was loaded or SP set); only
+    // the addresses returned to are known
+    fn rebuild_calls(&mut self) {
+        self.calls.clear();
+
+        for sp in 1..(self.context.sp as usize + 1) {
+            let return_address = self.stack_entry(sp as u8);
+
+            self.calls.push(Call {
+                // The `CALL` is the instruction before
+                site: return_address.wrapping_sub(2),
+                target: None,
+                return_address: return_address,
+            });
+        }
+    }
+
+    // Address stored in the stack entry at `sp`
+    fn stack_entry(&self, sp: u8) -> usize {
+        let address = Context::stack_address(sp);
+
+        ((self.mmu.peek(address) as usize) << 8) | (self.mmu.peek(address + 1) as usize)
+    }
+
+    // Size of RAM (of the variant)
+    pub fn memory_size(&self) -> usize {
+        self.mmu.size()
+    }
+
+    // Read / write RAM as a debugger (see `Mmu::peek`)
+    pub fn peek(&self, address: usize) -> u8 {
+        self.mmu.peek(address)
+    }
+
+    pub fn poke(&mut self, address: usize, value: u8) {
+        self.mmu.poke(address, value);
+    }
+
+    // RAM from `address` (up to `len` bytes; not past the end of RAM)
+    pub fn peek_range(&self, address: usize, len: usize) -> &[u8] {
+        self.mmu.peek_range(address, len)
+    }
+
+    // Write `bytes` from `address` as `poke` does (wrapping around)
+    pub fn poke_range(&mut self, address: usize, bytes: &[u8]) {
+        for (i, value) in bytes.iter().enumerate() {
+            self.mmu.poke(address + i, *value);
+        }
+    }
+
+    // Range of addresses (start, end) written since the last call (by the program,
+    // the debugger or a load); `None` if nothing was
+    pub fn take_memory_changed(&mut self) -> Option<(usize, usize)> {
+        self.mmu.take_changed()
+    }
+
+    pub fn set_address_policy(&mut self, policy: mmu::AddressPolicy) {
+        self.mmu.set_policy(policy);
+    }
+
+    pub fn set_opcode_policy(&mut self, policy: OpcodePolicy) {
+        self.opcode_policy = policy;
+    }
+
+    pub fn set_machine_call_policy(&mut self, policy: MachineCallPolicy) {
+        self.machine_call_policy = policy;
+    }
+
+    // Protect the interpreter area ($000 - $1FF; incl. the font) from writes by
+    // the program; a write stops the program
+    pub fn set_write_protection(&mut self, enabled: bool) {
+        self.write_protection = enabled;
+        self.apply_write_protection();
+    }
+
+    fn apply_write_protection(&mut self) {
+        self.mmu.set_protected(if self.write_protection { 0x200 } else { 0 });
+    }
+
+    // Why the program was stopped (if it was)
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    // True if no further instructions are run until the next frame (the program
+    // is waiting for the vertical blank)
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.context.vblank_wait
+    }
+
+    // True if the last instruction left PC where it was: a jump to itself (which
+    // many programs end with) or a wait (`FX0A`); the same instruction runs again
+    // and only the keys or the timers can change what it does
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    // Take the keys that are down as they were when `FX0A` last checked (if it
+    // waits); keys pressed or released in the meantime (eg. while paused) don't count
+    pub fn resync_keys(&mut self) {
+        if self.context.key_wait {
+            self.context.keys_down = self.context.input[0];
+        }
+    }
+
+    // Hex keys that are down on each keypad as the runtime reports them now
+    pub fn read_input(&self, r: &mut axal::Runtime) -> Input {
+        self.context.read_input(r)
+    }
+
+    // Latch the keys that are down for the instructions that follow (the frame)
+    pub fn set_input(&mut self, input: Input) {
+        self.context.input = input;
+    }
+
+    // Signal the start of a frame (the vertical blank)
+    //  DT / ST are clocked here (once per frame) rather than by the wall clock so that
+    //  emulation is deterministic: the same frames run the same regardless of
+    //  how fast (or when) the runtime asks for them.
+    pub fn vblank(&mut self) {
+        self.context.vblank_wait = false;
+        self.context.in_vblank = true;
+
+        // Frames run ahead leave the ticks to the frames that are kept
+        let ticks = match self.clock {
+            Some(ref mut clock) if !self.context.lockstep && !self.context.speculative => clock.ticks(),
+            _ => 1,
+        };
+
+        self.tick_timers(ticks);
+    }
+
+    // Clock DT / ST by `ticks` ticks
+    pub fn tick_timers(&mut self, ticks: u32) {
+        let ticks = cmp::min(ticks, 0xFF) as u8;
+
+        self.context.dt = self.context.dt.saturating_sub(ticks);
+        self.context.st = self.context.st.saturating_sub(ticks);
+    }
+
+    // `None` restores the frame clock (one tick per frame)
+    pub fn set_clock(&mut self, clock: Option<Box<Clock>>) {
+        self.clock = clock;
+    }
+
+    // Stop execution before the instruction at `address` is run
+    //  Breakpoints at the same address are kept apart (set by the user, the symbol
+    //  map or a debug adapter); each is removed on its own.
+    pub fn add_breakpoint(&mut self, address: usize) {
+        self.breakpoints.push((address, None));
+    }
+
+    // As `add_breakpoint`, only stopping if `condition` holds (see `Condition`)
+    pub fn add_conditional_breakpoint(&mut self,
+                                      address: usize,
+                                      condition: &str)
+                                      -> Result<(), Error> {
+        let condition = Condition::parse(condition)?;
+
+        self.breakpoints.push((address, Some(condition)));
+
+        Ok(())
+    }
+
+    // Remove the breakpoint last added at `address`
+    pub fn remove_breakpoint(&mut self, address: usize) {
+        if let Some(index) = self.breakpoints.iter().rposition(|breakpoint| breakpoint.0 == address) {
+            self.breakpoints.remove(index);
+        }
+    }
+
+    pub fn breakpoints(&self) -> Vec<usize> {
+        let mut addresses: Vec<usize> = self.breakpoints.iter().map(|breakpoint| breakpoint.0).collect();
+        addresses.sort();
+        addresses.dedup();
+
+        addresses
+    }
+
+    // Breakpoint the last instruction stopped at (it was not run); cleared when
+    // the next one runs
+    pub fn breakpoint_hit(&self) -> Option<usize> {
+        self.breakpoint_hit
+    }
+
+    // Stop execution after an instruction accesses (as `watch`) any of the `len`
+    // bytes from `address`
+    pub fn add_watchpoint(&mut self, address: usize, len: usize, watch: mmu::Watch) {
+        self.mmu.add_watchpoint(address, len, watch);
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize, len: usize) {
+        self.mmu.remove_watchpoint(address, len);
+    }
+
+    // Access of the last instruction that hit a watchpoint; cleared when the next one runs
+    pub fn watch_hit(&self) -> Option<mmu::WatchHit> {
+        self.watch_hit
+    }
+
+    // True if the last instruction stopped at a breakpoint, hit a watchpoint or
+    // finished a step over / out
+    pub fn is_stopped(&self) -> bool {
+        self.breakpoint_hit.is_some() || self.watch_hit.is_some() || self.step_hit.is_some()
+    }
+
+    // Stop before the instruction after the next one; a `CALL` is run until it
+    // returns (the instruction after it)
+    pub fn step_over(&mut self) {
+        self.step_depth = Some(self.context.sp);
+        self.skip_breakpoint();
+    }
+
+    // Stop once the subroutine the program is in returns (before the instruction
+    // after its `CALL`); false (and nothing is done) if the program is not in one
+    // (SP is 0)
+    pub fn step_out(&mut self) -> bool {
+        if self.context.sp == 0 {
+            return false;
+        }
+
+        self.step_depth = Some(self.context.sp - 1);
+        self.skip_breakpoint();
+
+        true
+    }
+
+    // Address the last step over / out stopped at (the instruction was not run);
+    // cleared when the next one runs
+    pub fn step_hit(&self) -> Option<usize> {
+        self.step_hit
+    }
+
+    // Run the next instruction even if there is a breakpoint at it (stepping)
+    pub fn skip_breakpoint(&mut self) {
+        self.stopped_at = Some(self.context.pc);
+    }
+
+    // True if execution stops at PC (before the instruction runs)
+    fn check_breakpoint(&mut self) -> bool {
+        if self.context.speculative {
+            return false;
+        }
+
+        let pc = self.context.pc;
+
+        self.breakpoint_hit = None;
+        self.step_hit = None;
+        if self.stopped_at.take() == Some(pc) {
+            return false;
+        }
+
+        // The stack is back to (or below) the depth stepped to; SP wraps around
+        if let Some(depth) = self.step_depth {
+            if depth.wrapping_sub(self.context.sp) as i8 >= 0 {
+                self.step_depth = None;
+                self.step_hit = Some(pc);
+                self.stopped_at = Some(pc);
+
+                return true;
             }
         }
 
+        // Any of the breakpoints at PC stops
+        let stop = {
+            let (context, mmu) = (&self.context, &self.mmu);
+
+            self.breakpoints.iter().filter(|breakpoint| breakpoint.0 == pc).any(|breakpoint| {
+                match breakpoint.1 {
+                    Some(ref condition) => condition.eval(context, mmu),
+                    None => true,
+                }
+            })
+        };
+
+        if !stop {
+            return false;
+        }
+
+        // A breakpoint in the subroutine ends the step
+        self.step_depth = None;
+        self.breakpoint_hit = Some(pc);
+        self.stopped_at = Some(pc);
+
+        true
+    }
+
+    // Run the next instruction; returns the time it took (in the units of
+    // `next_frame_cycles`)
+    pub fn run_next(&mut self, r: &mut axal::Runtime) -> usize {
+        if !self.context.speculative {
+            self.watch_hit = None;
+        }
+        if self.context.halted || self.check_breakpoint() {
+            return 0;
+        }
+
         // Read next 16-bit opcode (and increment PC)
+        let pc = self.context.pc;
+        let sp = self.context.sp;
+        // Frames rolled back are not traced (they never happened); the trace is not
+        // in snapshots as it is left as it was
+        let traced = self.trace.is_enabled() && !self.context.speculative;
+        let before = if traced { Some(self.state()) } else { None };
         let opcode = Opcode::read_next(&mut self.context.pc, &mut self.mmu);
+        self.instructions += 1;
+
+        let hooked = !self.hooks.is_empty() && !self.context.speculative;
+        let hooked_state = if hooked { Some(self.state()) } else { None };
+        let key_wait = self.context.key_wait;
+        if let Some(ref state) = hooked_state {
+            for hooks in &mut self.hooks {
+                hooks.1.on_before_exec(pc, opcode.extract_u16(), state);
+            }
+        }
+
+        let mut cycles = match self.timing {
+            Timing::Uniform => 1,
+            Timing::CosmacVip => timing::vip_cycles(&self.context, opcode),
+        };
 
         // Execute opcode (with runtime)
-        if let Some(ref mut runtime) = self.runtime {
-            if !runtime.execute(r, &mut self.context, &mut self.mmu, opcode) {
-                panic!("unhandled opcode: {}", opcode);
+        let handled = match self.runtime {
+            Some(ref mut runtime) => runtime.execute(r, &mut self.context, &mut self.mmu, opcode),
+            None => true,
+        };
+
+        self.track_call(pc, sp);
+        if let Some(ref before) = before {
+            self.record_trace(pc, opcode, before);
+        }
+
+        self.context.in_vblank = false;
+
+        // `0NNN` (that the variant does not have) calls a machine code routine of
+        // the original interpreter
+        let machine_call = !handled && opcode.unwrap().0 == 0x0;
+        if machine_call {
+            match self.machine_call_policy {
+                MachineCallPolicy::Ignore => {}
+
+                MachineCallPolicy::Log => {
+                    if !self.logged_machine_calls.contains(&pc) {
+                        println!("xCHIP: ignored machine call {} at ${:03X}", opcode, pc);
+                        self.logged_machine_calls.push(pc);
+                    }
+                }
+
+                MachineCallPolicy::Trap => {
+                    let fault = Fault::MachineCall {
+                        address: opcode.extract_u12() as usize,
+                        pc: pc,
+                    };
+
+                    eprintln!("error: {}", fault);
+
+                    self.context.pc = pc;
+                    self.context.halted = true;
+                    self.fault = Some(fault);
+                }
+            }
+        }
+
+        if !handled && !machine_call && self.opcode_policy != OpcodePolicy::Ignore {
+            let fault = Fault::Opcode {
+                opcode: opcode.extract_u16(),
+                pc: pc,
+            };
+
+            eprintln!("error: {}", fault);
+
+            if self.opcode_policy == OpcodePolicy::Trap {
+                self.context.pc = pc;
+            }
+
+            self.context.halted = true;
+            self.fault = Some(fault);
+        }
+
+        self.idle = self.context.pc == pc;
+
+        // Stop at an access past the end of RAM (`AddressPolicy::Trap`) or
+        // report the first one (`AddressPolicy::Warn`)
+        if let Some(address) = self.mmu.take_fault() {
+            let fault = Fault::Address {
+                address: address,
+                pc: pc,
+            };
+
+            if self.mmu.policy() == mmu::AddressPolicy::Warn {
+                if !self.address_warned {
+                    eprintln!("warning: {}", fault);
+                    self.address_warned = true;
+                }
+            } else {
+                eprintln!("error: {}", fault);
+
+                self.context.pc = pc;
+                self.context.halted = true;
+                self.fault = Some(fault);
+            }
+        }
+
+        // Stop at a write to the interpreter area (the write was dropped)
+        if let Some(address) = self.mmu.take_write_fault() {
+            let fault = Fault::ProtectedWrite {
+                address: address,
+                pc: pc,
+            };
+
+            eprintln!("error: {}", fault);
+
+            self.context.pc = pc;
+            self.context.halted = true;
+            self.fault = Some(fault);
+        }
+
+        // Stop after an access to a watched address
+        let hit = self.mmu.take_watch_hit();
+        if let (Some(mut hit), false) = (hit, self.context.speculative) {
+            hit.pc = pc;
+            self.watch_hit = Some(hit);
+            self.step_depth = None;
+        }
+
+        if self.timing == Timing::CosmacVip && self.context.pc == pc + 4 {
+            cycles += timing::vip_skip_cycles(opcode);
+        }
+
+        if let Some(ref before) = hooked_state {
+            self.call_after_hooks(pc, opcode, before, key_wait);
+        }
+
+        // The program was stopped by this instruction
+        if self.fault.is_some() && traced {
+            eprintln!("error: last instructions run:");
+            self.trace.dump();
+        }
+
+        cycles
+    }
+
+    fn record_trace(&mut self, pc: usize, opcode: Opcode, before: &State) {
+        let after = self.state();
+
+        let mut changes = Vec::new();
+        for x in 0..16 {
+            if after.v[x] != before.v[x] {
+                changes.push((Register::V(x as u8), after.v[x] as usize));
             }
         }
 
-        // Update timer point reference
-        self.timer_instant = Some(Instant::now());
+        if after.i != before.i {
+            changes.push((Register::I, after.i));
+        }
+
+        if after.sp != before.sp {
+            changes.push((Register::Sp, after.sp as usize));
+        }
+
+        if after.dt != before.dt {
+            changes.push((Register::Dt, after.dt as usize));
+        }
+
+        if after.st != before.st {
+            changes.push((Register::St, after.st as usize));
+        }
+
+        let next = ((self.mmu.peek(pc + 2) as u16) << 8) | (self.mmu.peek(pc + 3) as u16);
+
+        self.trace.push(TraceEntry {
+            pc: pc,
+            opcode: opcode.extract_u16(),
+            next: next,
+            changes: changes,
+        });
     }
 
-    pub fn screen_as_framebuffer(&mut self) -> (&[u8], usize, usize) {
+    fn call_after_hooks(&mut self, pc: usize, opcode: Opcode, before: &State, key_wait: bool) {
+        let state = self.state();
+
+        for hooks in &mut self.hooks {
+            let hooks = &mut hooks.1;
+            hooks.on_after_exec(pc, opcode.extract_u16(), &state);
+
+            match Instruction::decode(opcode.extract_u16()) {
+                Instruction::Draw(x, y, n) => {
+                    hooks.on_draw(before.v[x as usize], before.v[y as usize], n, state.v[0xF] != 0);
+                }
+
+                Instruction::WaitKey(x) if !key_wait && self.context.key_wait => hooks.on_key_wait(x),
+
+                _ => {}
+            }
+        }
+    }
+
+    // Call `hooks` as the program runs; returns the id to remove them by
+    pub fn add_hooks(&mut self, hooks: Box<Hooks>) -> usize {
+        let id = self.next_hooks_id;
+        self.next_hooks_id += 1;
+        self.hooks.push((id, hooks));
+
+        id
+    }
+
+    pub fn remove_hooks(&mut self, id: usize) {
+        self.hooks.retain(|hooks| hooks.0 != id);
+    }
+
+    // Set while running frames that are rolled back (run-ahead): the hooks are not
+    // called and breakpoints and watchpoints are not checked, as what they would
+    // report never happened; nor are they traced, the clock is not ticked and
+    // persisted storage (RPL user flags) is not written
+    pub fn set_speculative(&mut self, speculative: bool) {
+        self.context.speculative = speculative;
+    }
+
+    // Keep the last `len` instructions run (0 disables the trace; the default)
+    pub fn set_trace(&mut self, len: usize) {
+        self.trace.configure(len);
+    }
+
+    // Last instructions run (oldest first)
+    pub fn trace(&self) -> Vec<TraceEntry> {
+        self.trace.entries()
+    }
+
+    pub fn dump_trace(&self) {
+        self.trace.dump();
+    }
+
+    // Render `count` (stereo) samples of audio
+    pub fn audio_frame(&mut self, count: usize) -> &[i16] {
         if let Some(ref mut runtime) = self.runtime {
+            runtime.render_audio(&self.context,
+                                 &mut self.audio_position,
+                                 count,
+                                 &mut self.audio_samples);
+        } else {
+            self.audio_samples.clear();
+        }
+
+        &self.audio_samples
+    }
+
+    pub fn pixel_format(&self) -> video::PixelFormat {
+        self.pixel_format
+    }
+
+    pub fn set_pixel_format(&mut self, format: video::PixelFormat) {
+        self.pixel_format = format;
+        self.context.dirty = true;
+
+        // The next frame must be presented in the new format
+        self.presented.clear();
+    }
+
+    // `None` restores the colors of the variant
+    pub fn set_plane_colors(&mut self, colors: Option<[video::Color; 4]>) {
+        self.plane_colors = colors;
+        self.context.palette = match (colors, self.mode) {
+            (Some(colors), _) => colors,
+            (None, Some(mode)) => self.configured_context(mode).palette,
+            (None, None) => video::DEFAULT_PALETTE,
+        };
+
+        self.context.dirty = true;
+    }
+
+    pub fn set_phosphor_decay(&mut self, decay: video::PhosphorDecay) {
+        self.phosphor_decay = decay;
+    }
+
+    // Fade out dots that were turned off (and light those turned on); once at the
+    // end of every frame
+    pub fn fade(&mut self) {
+        if video::decay(self.phosphor_decay, &self.context.screen, &mut self.context.phase) {
+            self.context.dirty = true;
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: filter::Filter) {
+        self.filter = filter;
+        self.context.dirty = true;
+
+        // The next frame must be presented with the new filter
+        self.presented.clear();
+    }
+
+    pub fn set_scale(&mut self, scale: video::Scale) {
+        self.scale = scale;
+        self.context.dirty = true;
+
+        // The next frame must be presented at the new size
+        self.presented.clear();
+    }
+
+    pub fn set_rotation(&mut self, rotation: video::Rotation) {
+        self.rotation = rotation;
+        self.context.dirty = true;
+
+        // The next frame must be presented with the new rotation
+        self.presented.clear();
+    }
+
+    pub fn rotation(&self) -> video::Rotation {
+        self.rotation
+    }
+
+    // Factor the presented frame is scaled by (from the size of the screen)
+    pub fn output_scale(&self) -> usize {
+        cmp::max(self.scale.factor(), self.filter.min_scale())
+    }
+
+    // Returns `None` if the screen is unchanged since the last call (frame dupe); this
+    // is the case if nothing was drawn or what was drawn had no visible effect
+    //  (eg. a sprite erased and redrawn at the same position)
+    pub fn screen_as_framebuffer(&mut self) -> Option<(&[u8], usize, usize)> {
+        if !self.context.dirty {
+            return None;
+        }
+
+        let (width, height) = if let Some(ref mut runtime) = self.runtime {
             runtime.update_framebuffer(&mut self.context);
+            runtime.framebuffer_size(&self.context)
+        } else {
+            // No ROM; there is nothing to present
+            self.context.framebuffer.clear();
+            (0, 0)
+        };
+
+        self.context.dirty = false;
+
+        if self.presented_size == (width, height) && self.presented == self.context.framebuffer {
+            return None;
+        }
+
+        self.presented.clone_from(&self.context.framebuffer);
+        self.presented_size = (width, height);
+
+        // Post-process (filter, scale, and rotate) the framebuffer
+        let scale = self.output_scale();
+        let (mut width, mut height) = (width, height);
+        let mut frame = &self.context.framebuffer;
+
+        if self.filter != filter::Filter::None || scale != 1 {
+            let size = filter::apply(self.filter,
+                                     scale,
+                                     frame,
+                                     width,
+                                     height,
+                                     &mut self.filtered);
+
+            width = size.0;
+            height = size.1;
+            frame = &self.filtered;
         }
 
-        (&self.context.framebuffer, self.context.screen_width, self.context.screen_height)
+        if self.rotation != video::Rotation::R0 {
+            let size = video::rotate(self.rotation, frame, width, height, &mut self.rotated);
+
+            width = size.0;
+            height = size.1;
+            frame = &self.rotated;
+        }
+
+        // Convert to the requested pixel format (once per frame)
+        video::convert(self.pixel_format, frame, &mut self.output);
+
+        Some((&self.output, width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::VirtualClock;
+    use quirks::Quirks;
+    use instruction::Instruction;
+
+    // Runtime with no keys down; keys are given with `set_input`
+    struct NoInput;
+
+    impl axal::Runtime for NoInput {
+        fn input_keyboard_state(&mut self, _: u8, _: axal::Key) -> bool {
+            false
+        }
+
+        fn video_refresh(&mut self, _: &[u8], _: u32, _: u32) {}
+
+        fn audio_sample_batch(&mut self, _: &[i16]) {}
+    }
+
+    // Interpreter running `program` in `mode` (in lockstep; nothing is read from disk)
+    fn boot(mode: Mode, program: &[u16]) -> Interpreter {
+        let mut buffer = Vec::new();
+        for word in program {
+            buffer.push((word >> 8) as u8);
+            buffer.push(*word as u8);
+        }
+
+        let mut interpreter: Interpreter = Default::default();
+        interpreter.set_lockstep(true);
+
+        let mut runtime = mode.runtime(interpreter.super_chip_revision);
+        interpreter.mmu.resize(runtime.memory_size());
+        runtime.insert_rom(&mut interpreter.mmu, &buffer);
+        interpreter.runtime = Some(runtime);
+        interpreter.mode = Some(mode);
+        interpreter.configure();
+        interpreter.reset();
+
+        interpreter
+    }
+
+    // Run until PC is at `address`; a frame is started whenever a sprite waits for one
+    fn run_to(interpreter: &mut Interpreter, address: usize) {
+        for _ in 0..100 {
+            if interpreter.context.pc == address {
+                return;
+            }
+
+            if interpreter.is_waiting_for_vblank() {
+                interpreter.vblank();
+            }
+
+            interpreter.run_next(&mut NoInput);
+        }
+
+        panic!("PC did not reach ${:03X}", address);
+    }
+
+    // Whether the dot at (x, y) is set in the first plane
+    fn dot(interpreter: &Interpreter, x: usize, y: usize) -> bool {
+        let c = &interpreter.context;
+
+        c.screen[y * c.screen_width + x] & 1 != 0
+    }
+
+    #[test]
+    fn timers_follow_the_clock() {
+        let clock: VirtualClock = Default::default();
+        let mut interpreter: Interpreter = Default::default();
+        interpreter.set_clock(Some(Box::new(clock.clone())));
+        interpreter.context.dt = 10;
+        interpreter.context.st = 1;
+
+        interpreter.vblank();
+        assert_eq!((interpreter.context.dt, interpreter.context.st), (10, 1));
+
+        clock.advance(3);
+        interpreter.vblank();
+        assert_eq!((interpreter.context.dt, interpreter.context.st), (7, 0));
+
+        // Frames run ahead tick once and leave the time to the frames kept
+        clock.advance(2);
+        interpreter.set_speculative(true);
+        interpreter.vblank();
+        assert_eq!(interpreter.context.dt, 6);
+
+        interpreter.set_speculative(false);
+        interpreter.vblank();
+        assert_eq!(interpreter.context.dt, 4);
+
+        // As do frames in lockstep
+        clock.advance(2);
+        interpreter.set_lockstep(true);
+        interpreter.vblank();
+        assert_eq!(interpreter.context.dt, 3);
+    }
+
+    #[test]
+    fn quirks_follow_the_variant() {
+        // LD V0, 5; LD V1, 3; SHR V0, V1
+        let program = [0x6005, 0x6103, 0x8016];
+
+        let mut chip_8 = boot(Mode::Chip8, &program);
+        run_to(&mut chip_8, 0x206);
+        assert_eq!((chip_8.context.v[0], chip_8.context.v[0xF]), (1, 1));
+
+        let mut chip_48 = boot(Mode::Chip48, &program);
+        run_to(&mut chip_48, 0x206);
+        assert_eq!((chip_48.context.v[0], chip_48.context.v[0xF]), (2, 1));
+
+        // Overridden (until restored)
+        let mut overridden = boot(Mode::Chip8, &program);
+        overridden.set_quirks(Some(Quirks::chip_48()));
+        run_to(&mut overridden, 0x206);
+        assert_eq!(overridden.context.v[0], 2);
+
+        overridden.set_quirks(None);
+        assert!(overridden.quirks() == Quirks::vip());
+    }
+
+    #[test]
+    fn decode_instructions() {
+        assert_eq!(Instruction::decode(0x8016), Instruction::ShiftRight(0, 1));
+        assert_eq!(Instruction::decode(0xE1A2), Instruction::Data(0xE1A2));
+        assert_eq!(Instruction::decode(0xF000).len(), 4);
+        assert!(Instruction::decode(0xE19E).is_skip());
+        assert_eq!(Instruction::decode(0xD125).to_string(), "DRW V1, V2, 5");
+        assert_eq!(Instruction::decode(0xF20A).to_string(), "LD V2, K");
+
+        // LD V0, $5A; LD F, V0 (the digit is the low nibble)
+        let mut interpreter = boot(Mode::Chip8, &[0x605A, 0xF029]);
+        run_to(&mut interpreter, 0x204);
+        assert_eq!(interpreter.context.i, 0xA * 5);
+    }
+
+    #[test]
+    fn scroll_super_chip() {
+        // HIGH; a dot at (8, 8); SCD 2; SCR; SCL; SCL
+        let mut interpreter = boot(Mode::SuperChip,
+                                   &[0x00FF, 0x6008, 0xA210, 0xD001, 0x00C2, 0x00FB, 0x00FC,
+                                     0x00FC, 0x8000]);
+
+        run_to(&mut interpreter, 0x20A);
+        assert!(dot(&interpreter, 8, 10) && !dot(&interpreter, 8, 8));
+
+        run_to(&mut interpreter, 0x20C);
+        assert!(dot(&interpreter, 12, 10) && !dot(&interpreter, 8, 10));
+
+        run_to(&mut interpreter, 0x210);
+        assert!(dot(&interpreter, 4, 10) && !dot(&interpreter, 12, 10));
+    }
+
+    #[test]
+    fn scroll_up_selected_planes() {
+        // HIGH; a dot at (8, 8); SCU 3; PLANE 2; SCU 3
+        let mut interpreter = boot(Mode::XoChip,
+                                   &[0x00FF, 0x6008, 0xA20E, 0xD001, 0x00D3, 0xF201, 0x00D3, 0x8000]);
+
+        run_to(&mut interpreter, 0x20A);
+        assert!(dot(&interpreter, 8, 5) && !dot(&interpreter, 8, 8));
+
+        // The first plane is not selected
+        run_to(&mut interpreter, 0x20E);
+        assert!(dot(&interpreter, 8, 5) && !dot(&interpreter, 8, 2));
+    }
+
+    #[test]
+    fn draw_16x16_sprites() {
+        // HIGH; DRW V0, V0, 0 (twice)
+        let mut program = vec![0x00FF, 0xA208, 0xD000, 0xD000];
+        program.extend(vec![0xFFFF; 16]);
+        let mut interpreter = boot(Mode::SuperChip, &program);
+
+        run_to(&mut interpreter, 0x206);
+        assert!(dot(&interpreter, 0, 0) && dot(&interpreter, 15, 15));
+        assert!(!dot(&interpreter, 16, 0) && !dot(&interpreter, 0, 16));
+        assert_eq!(interpreter.context.v[0xF], 0);
+
+        // SUPER-CHIP 1.1 counts the rows that collided
+        run_to(&mut interpreter, 0x208);
+        assert!(!dot(&interpreter, 0, 0));
+        assert_eq!(interpreter.context.v[0xF], 16);
+    }
+
+    #[test]
+    fn clip_or_wrap_sprites() {
+        // LD V0, x; LD V1, 0; DRW V0, V1, 1 (8 dots)
+        let program = |x: u16| vec![0x6000 | x, 0x6100, 0xA208, 0xD011, 0xFF00];
+
+        let mut clipped = boot(Mode::Chip8, &program(62));
+        run_to(&mut clipped, 0x208);
+        assert!(dot(&clipped, 62, 0) && dot(&clipped, 63, 0) && !dot(&clipped, 0, 0));
+
+        // The coordinates wrap all the same
+        let mut wrapped_start = boot(Mode::Chip8, &program(64 + 62));
+        run_to(&mut wrapped_start, 0x208);
+        assert!(dot(&wrapped_start, 62, 0) && !dot(&wrapped_start, 0, 0));
+
+        let mut wrapped = boot(Mode::Chip8, &program(62));
+        wrapped.set_quirks(Some(Quirks { clip_sprites: false, ..Quirks::vip() }));
+        run_to(&mut wrapped, 0x208);
+        assert!(dot(&wrapped, 63, 0) && dot(&wrapped, 5, 0) && !dot(&wrapped, 6, 0));
+    }
+
+    #[test]
+    fn wait_for_key_release() {
+        // LD V5, K
+        let mut interpreter = boot(Mode::Chip8, &[0xF50A]);
+
+        interpreter.set_input([1 << 7, 0]);
+        interpreter.run_next(&mut NoInput);
+        interpreter.run_next(&mut NoInput);
+        assert!(interpreter.context.key_wait);
+        assert_eq!(interpreter.context.pc, 0x200);
+
+        interpreter.set_input([0, 0]);
+        interpreter.run_next(&mut NoInput);
+        assert!(!interpreter.context.key_wait);
+        assert_eq!((interpreter.context.v[5], interpreter.context.pc), (7, 0x202));
+    }
+
+    #[test]
+    fn wait_for_key_press() {
+        // LD V5, K
+        let mut interpreter = boot(Mode::Chip48, &[0xF50A]);
+
+        // A key down before the wait doesn't count
+        interpreter.set_input([1 << 3, 0]);
+        interpreter.run_next(&mut NoInput);
+        interpreter.run_next(&mut NoInput);
+        assert!(interpreter.context.key_wait);
+
+        interpreter.set_input([(1 << 3) | (1 << 7), 0]);
+        interpreter.run_next(&mut NoInput);
+        assert_eq!((interpreter.context.v[5], interpreter.context.pc), (7, 0x202));
+    }
+
+    #[test]
+    fn skip_long_instructions() {
+        // SE V0, 0; LD I, $1234; LD V1, 1
+        let mut interpreter = boot(Mode::XoChip, &[0x3000, 0xF000, 0x1234, 0x6101]);
+        interpreter.add_watchpoint(0x202, 4, mmu::Watch::Read);
+
+        interpreter.run_next(&mut NoInput);
+        assert_eq!(interpreter.context.pc, 0x206);
+        assert!(interpreter.watch_hit().is_none());
+
+        // SNE V0, 0 (not skipped)
+        let mut interpreter = boot(Mode::XoChip, &[0x4000, 0xF000, 0x1234, 0x6101]);
+        run_to(&mut interpreter, 0x206);
+        assert_eq!(interpreter.context.i, 0x1234);
     }
 }
@@ -0,0 +1,366 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::vec::Vec;
+
+// Deepest nesting of arrays and objects parsed (messages of the protocol are
+// not nearly as deep)
+const MAX_DEPTH: usize = 64;
+
+// JSON value (for the messages of the debug adapter; see `dap`)
+//  Just what the protocol needs: numbers are f64 and objects keep their order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars, 0)?;
+
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return None;
+        }
+
+        Some(value)
+    }
+
+    // Object of the pairs `fields`
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn str(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+
+    // Field `key` of an object; `Json::Null` if there is none (or this is not an object)
+    pub fn get(&self, key: &str) -> &Json {
+        static NULL: Json = Json::Null;
+
+        match *self {
+            Json::Object(ref fields) => {
+                fields.iter().find(|field| field.0 == key).map_or(&NULL, |field| &field.1)
+            }
+
+            _ => &NULL,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Json::Number(value) if value >= 0.0 => Some(value as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(value) => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match *self {
+            Json::Array(ref values) => values,
+            _ => &[],
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => write!(f, "{}", value),
+            Json::String(ref value) => write_string(f, value),
+
+            Json::Array(ref values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{}", value)?;
+                }
+
+                write!(f, "]")
+            }
+
+            Json::Object(ref fields) => {
+                write!(f, "{{")?;
+                for (i, &(ref key, ref value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().map_or(false, |c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+// Consume `word` (the rest of `true`, `false` or `null`)
+fn expect_word(chars: &mut Peekable<Chars>, word: &str) -> Option<()> {
+    for c in word.chars() {
+        if chars.next()? != c {
+            return None;
+        }
+    }
+
+    Some(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> Option<Json> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    skip_whitespace(chars);
+
+    match *chars.peek()? {
+        'n' => expect_word(chars, "null").map(|_| Json::Null),
+        't' => expect_word(chars, "true").map(|_| Json::Bool(true)),
+        'f' => expect_word(chars, "false").map(|_| Json::Bool(false)),
+        '"' => parse_string(chars).map(Json::String),
+
+        '[' => {
+            chars.next();
+
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Some(Json::Array(values));
+            }
+
+            loop {
+                values.push(parse_value(chars, depth + 1)?);
+
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => {}
+                    ']' => return Some(Json::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+
+        '{' => {
+            chars.next();
+
+            let mut fields = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Some(Json::Object(fields));
+            }
+
+            loop {
+                skip_whitespace(chars);
+                let key = parse_string(chars)?;
+
+                skip_whitespace(chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+
+                fields.push((key, parse_value(chars, depth + 1)?));
+
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => {}
+                    '}' => return Some(Json::Object(fields)),
+                    _ => return None,
+                }
+            }
+        }
+
+        _ => parse_number(chars),
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<Json> {
+    let mut text = String::new();
+    while let Some(c) = chars.peek().cloned() {
+        if !(c.is_digit(10) || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+            break;
+        }
+
+        text.push(c);
+        chars.next();
+    }
+
+    text.parse().ok().map(Json::Number)
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+
+            '\\' => {
+                let c = match chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+
+                    'u' => {
+                        let mut code = String::new();
+                        for _ in 0..4 {
+                            code.push(chars.next()?);
+                        }
+
+                        // Surrogate pairs are not joined (paths and names in practice
+                        // are not outside of the basic plane)
+                        u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(::std::char::from_u32)
+                            .unwrap_or('\u{fffd}')
+                    }
+
+                    c => c,
+                };
+
+                value.push(c);
+            }
+
+            c => value.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(Json::parse("null"), Some(Json::Null));
+        assert_eq!(Json::parse(" true "), Some(Json::Bool(true)));
+        assert_eq!(Json::parse("false"), Some(Json::Bool(false)));
+        assert_eq!(Json::parse("-12.5e1"), Some(Json::Number(-125.0)));
+        assert_eq!(Json::parse("\"a\\\"b\\n\\u0041\""), Some(Json::str("a\"b\nA")));
+        assert_eq!(Json::parse("[]"), Some(Json::Array(vec![])));
+        assert_eq!(Json::parse("{}"), Some(Json::Object(vec![])));
+    }
+
+    #[test]
+    fn parse_request() {
+        let request = Json::parse(r#"{"seq": 1, "command": "setBreakpoints",
+                                      "arguments": {"lines": [3, 7], "source": {}}}"#)
+            .unwrap();
+
+        assert_eq!(request.get("seq").as_u64(), Some(1));
+        assert_eq!(request.get("command").as_str(), Some("setBreakpoints"));
+
+        let lines: Vec<i64> = request.get("arguments")
+            .get("lines")
+            .as_array()
+            .iter()
+            .filter_map(|line| line.as_i64())
+            .collect();
+
+        assert_eq!(lines, vec![3, 7]);
+        assert_eq!(request.get("missing"), &Json::Null);
+        assert_eq!(request.get("seq").get("missing"), &Json::Null);
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert_eq!(Json::parse(""), None);
+        assert_eq!(Json::parse("nul"), None);
+        assert_eq!(Json::parse("[1,"), None);
+        assert_eq!(Json::parse("[1 2]"), None);
+        assert_eq!(Json::parse("{\"a\" 1}"), None);
+        assert_eq!(Json::parse("{1: 2}"), None);
+        assert_eq!(Json::parse("\"open"), None);
+        assert_eq!(Json::parse("1 2"), None);
+    }
+
+    #[test]
+    fn reject_deep_nesting() {
+        let shallow = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(Json::parse(&shallow).is_some());
+
+        let deep = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(Json::parse(&deep), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = Json::object(vec![("name", Json::str("tab\there \"quoted\"")),
+                                      ("count", 3usize.into()),
+                                      ("flag", true.into()),
+                                      ("nothing", Json::Null),
+                                      ("list", Json::Array(vec![Json::Number(-1.5), Json::str("\u{1}")]))]);
+
+        let text = value.to_string();
+        assert_eq!(text,
+                   r#"{"name":"tab\there \"quoted\"","count":3,"flag":true,"nothing":null,"list":[-1.5,"\u0001"]}"#);
+        assert_eq!(Json::parse(&text), Some(value));
+    }
+}
@@ -4,58 +4,1461 @@
 extern crate axal;
 
 extern crate rand;
+extern crate toml;
 
 mod mmu;
 mod opcode;
+mod hash;
+mod storage;
+mod quirks;
+mod config;
+mod error;
+mod clock;
+mod rng;
+mod timing;
+mod stats;
+mod savestate;
+mod slots;
+mod rewind;
+mod movie;
+mod debug;
+mod condition;
+mod instruction;
+mod disasm;
+mod trace;
+mod hooks;
+mod symbols;
+
+#[cfg(feature = "dap")]
+mod json;
+#[cfg(feature = "dap")]
+mod dap;
 
 mod chip_8;
 mod chip_8x;
+mod chip_48;
+mod chip_8e;
+mod hires_chip_8;
 mod super_chip;
+mod xo_chip;
+mod mega_chip;
 
 mod interpreter;
+mod video;
+mod audio;
+mod filter;
+
+pub use video::{Color, FrameRate, PixelFormat, PhosphorDecay, Rotation, Scale, Thumbnail,
+                DEFAULT_PALETTE, OCTO_PALETTE};
+pub use filter::Filter;
+pub use super_chip::Revision as SuperChipRevision;
+pub use interpreter::Mode as Variant;
+pub use mmu::{AddressPolicy, Watch, WatchHit};
+pub use error::Error;
+pub use interpreter::{Call, Fault, OpcodePolicy, MachineCallPolicy, Register, State};
+pub use quirks::{Quirks, LoadStoreIncrement, LoresDxy0};
+pub use clock::{Clock, FrameClock, RealTimeClock, VirtualClock};
+pub use timing::Timing;
+pub use stats::Stats;
+pub use slots::Slot as SaveSlot;
+pub use movie::Movie;
+pub use debug::Debugger;
+pub use instruction::Instruction;
+pub use disasm::Disassembly;
+pub use trace::TraceEntry;
+pub use hooks::Hooks;
+pub use symbols::Symbols;
+
+use std::cmp;
+use std::env;
+#[cfg(feature = "dap")]
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// Most frames run by a single call of `run_next` when catching up
+const MAX_CATCH_UP_FRAMES: usize = 4;
+
+// Most frames run ahead of the one emulated
+const MAX_RUN_AHEAD_FRAMES: usize = 2;
 
 #[derive(Default)]
 pub struct Core {
     interpreter: interpreter::Interpreter,
+
+    // Set if the last frame was not presented as it was identical to the one before
+    frame_dupe: bool,
+
+    // Variant forced by the user (detected from the ROM if not set)
+    variant: Option<Variant>,
+
+    // Quirks forced by the user (those of the variant if not set)
+    quirks: Option<Quirks>,
+
+    // Instructions run per second as set by the user (those of the variant if not set)
+    instructions_per_second: Option<usize>,
+
+    // Colors of dots set by the user (those of the configuration file or variant if not set)
+    plane_colors: Option<[Color; 4]>,
+
+    // Filename of the inserted ROM; re-inserted when the variant is changed
+    rom: Option<String>,
+
+    // Symbols of the inserted ROM (from an Octo symbol map next to it)
+    symbols: Option<Symbols>,
+
+    // Run the frames that were missed when `run_next` is called late
+    catch_up: bool,
+
+    // Frames run (and discarded) ahead of the one presented
+    run_ahead: usize,
+
+    // Pause at the vertical blank (the end of every frame)
+    break_on_vblank: bool,
+
+    // Compress savestates made by `serialize`
+    compress_states: bool,
+
+    // Recent states to rewind to
+    rewind: rewind::Rewind,
+
+    // Save the state when the ROM is removed, and restore it when the ROM is
+    // inserted again
+    auto_save: bool,
+    auto_resume: bool,
+
+    // Movie being recorded
+    recording: Option<Movie>,
+
+    // Movie being played back (in place of the keys of the runtime), the next
+    // frame of it, and the first frame that did not start as recorded
+    playback: Option<Movie>,
+    playback_frame: usize,
+    desync: Option<usize>,
+
+    // Frames run and presented, the rate instructions are run at, and how long the
+    // last frame took (see `Stats`); printed every second if `print_stats` is set
+    frames: u64,
+    frames_presented: u64,
+    rate: stats::Rate,
+    measured_rate: f64,
+    frame_time: Duration,
+    print_stats: bool,
+
+    // Debug adapter (see `listen_dap`)
+    #[cfg(feature = "dap")]
+    dap: Option<dap::Server>,
+
+    // Set while paused; and once resumed until the next frame
+    paused: bool,
+    resumed: bool,
+
+    // Samples presented while paused
+    silence: Vec<i16>,
+
+    // When the last frame was run and the time (in ns) owed since (catching up)
+    last_frame: Option<Instant>,
+    frame_debt: u64,
+}
+
+impl Core {
+    // Select the pixel format of presented frames; defaults to R3_G3_B2
+    pub fn set_pixel_format(&mut self, format: PixelFormat) {
+        self.interpreter.set_pixel_format(format);
+    }
+
+    // Select how quickly dots fade out after being turned off
+    //  `PhosphorDecay::Off` gives pixel-exact output.
+    pub fn set_phosphor_decay(&mut self, decay: PhosphorDecay) {
+        self.interpreter.set_phosphor_decay(decay);
+    }
+
+    // Set the colors of dots by the planes they are set in: off, plane 1, plane 2,
+    // and both planes (only XO-CHIP uses the second plane)
+    pub fn set_plane_colors(&mut self, colors: [Color; 4]) {
+        self.plane_colors = Some(colors);
+        self.interpreter.set_plane_colors(Some(colors));
+    }
+
+    // Select a post-processing filter (scanlines, pixel grid, bloom); off by default
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.interpreter.set_filter(filter);
+    }
+
+    // Render at an integer multiple of the screen size (for high-DPI displays)
+    //  Filters that need room (scanlines, pixel grid) render at 3x at least.
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.interpreter.set_scale(scale);
+    }
+
+    // Rotate presented frames clockwise (for vertical displays)
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.interpreter.set_rotation(rotation);
+    }
+
+    // Select the rate frames are run at (60 Hz by default); the runtime is expected
+    // to call `run_next` at it
+    //  The timers are clocked once per frame; the speed in instructions per second
+    //  is kept. The rate is not in `info()` (`axal::Info` has no field for it).
+    pub fn set_frame_rate(&mut self, frame_rate: FrameRate) {
+        self.interpreter.set_frame_rate(frame_rate);
+    }
+
+    pub fn frame_rate(&self) -> FrameRate {
+        self.interpreter.frame_rate()
+    }
+
+    // Present the frame `frames` (at most 2) ahead of the one emulated, as it
+    // would be if the input stays as it is; 0 (the default) disables run-ahead
+    //  This hides the frame(s) of latency most games have between a key press
+    //  and its effect on the screen (eg. the paddle of a Brix clone) at the cost
+    //  of running every frame `frames` more times.
+    //  Frames run ahead don't use up the ticks of the clock set or write the
+    //  RPL user flags; they are thrown away.
+    pub fn set_run_ahead(&mut self, frames: usize) {
+        self.run_ahead = cmp::min(frames, MAX_RUN_AHEAD_FRAMES);
+    }
+
+    // Pause emulation; `run_next` presents silence (and no new frame) until resumed
+    //  DT / ST are frozen (as they are clocked by frames) and a key pressed or
+    //  released while paused does not end a wait for a key (`FX0A`).
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.paused = false;
+            self.resumed = true;
+
+            // The pause is not time to catch up on
+            self.last_frame = None;
+            self.frame_debt = 0;
+        }
+    }
+
+    // Pause at the vertical blank, once the frame is presented (for debuggers);
+    // `resume` then runs a single frame
+    pub fn set_break_on_vblank(&mut self, enabled: bool) {
+        self.break_on_vblank = enabled;
+    }
+
+    // Save the state of the inserted ROM (RAM, registers, stack, timers, screen,
+    // variant and quirks) into a savestate; `None` if no ROM is inserted
+    //  While a movie is recorded (or played back) the state also holds the frame
+    //  of the movie it was saved at (see `deserialize`).
+    pub fn serialize(&self) -> Option<Vec<u8>> {
+        let frame = self.movie_position();
+
+        self.interpreter.save_state_with(self.compress_states, |w| {
+            if let Some(frame) = frame {
+                w.section(b"MOVI", |w| w.usize(frame));
+            }
+        })
+    }
+
+    // Compress savestates made by `serialize` (they are mostly zeros; eg. a 64 KiB
+    // XO-CHIP state is a few KiB); off by default
+    //  Compressed and uncompressed states are both restored by `deserialize`.
+    pub fn set_compress_states(&mut self, enabled: bool) {
+        self.compress_states = enabled;
+    }
+
+    // Restore a savestate made by `serialize`; nothing is changed if it is invalid
+    //  The ROM the state was saved from is expected to be inserted (the state
+    //  includes RAM and so the program itself).
+    //  While a movie is recorded or played back, the state must have been saved
+    //  during it; recording continues from the frame the state was saved at (the
+    //  frames after it are dropped) and counts as a re-record.
+    pub fn deserialize(&mut self, state: &[u8]) -> Result<(), Error> {
+        let movie_len = self.recording.as_ref().or(self.playback.as_ref()).map(|movie| movie.len());
+        let frame = match movie_len {
+            Some(len) => {
+                match savestate::read_section(state, b"MOVI", |r| r.usize()) {
+                    Some(frame) if frame <= len => Some(frame),
+                    _ => return Err(Error::StateNotInMovie),
+                }
+            }
+
+            None => None,
+        };
+
+        let snapshot = self.interpreter.snapshot();
+        self.interpreter.load_state(state)?;
+
+        // The state must be the one the movie started the frame from
+        if let Some(frame) = frame {
+            let movie = self.recording.as_ref().or(self.playback.as_ref());
+            if let (Some(movie), Some(state_hash)) = (movie, self.interpreter.state_hash()) {
+                if !movie.verify(frame, state_hash) {
+                    self.interpreter.restore(snapshot);
+                    return Err(Error::StateNotInMovie);
+                }
+            }
+        }
+
+        // The states kept are of another timeline
+        self.rewind.clear();
+
+        if let Some(frame) = frame {
+            if let Some(movie) = self.playback.take() {
+                self.recording = Some(movie);
+            }
+
+            if let Some(ref mut movie) = self.recording {
+                movie.rerecord(frame);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Save the state peers of a netplay session must agree on (registers, RAM,
+    // screen, timers) into a sync state; smaller and quicker than `serialize` to
+    // send every time the peers resync; `None` if no ROM is inserted
+    //  A sync state is only restored by the same release of the core running the
+    //  same ROM in the same variant.
+    pub fn serialize_sync(&self) -> Option<Vec<u8>> {
+        self.interpreter.save_sync_state()
+    }
+
+    // Restore a sync state made by `serialize_sync`; nothing is changed if it is invalid
+    pub fn deserialize_sync(&mut self, state: &[u8]) -> Result<(), Error> {
+        self.interpreter.load_sync_state(state)?;
+        self.rewind.clear();
+
+        Ok(())
+    }
+
+    // Keep a state every `interval` frames, up to `length` states, to rewind to
+    // (eg. 600 states every 6 frames are the last minute); `length` of 0 (the
+    // default) disables rewind
+    //  A state takes a few KiB (they are compressed).
+    pub fn set_rewind(&mut self, length: usize, interval: usize) {
+        self.rewind.configure(length, interval);
+    }
+
+    // Keep the last `len` instructions run (with their disassembly and the
+    // registers they changed); printed when the program is stopped by a fault
+    // (eg. an unknown opcode or a trapped access). 0 (the default) disables it
+    //  Recording slows every instruction; enable it to investigate a crash.
+    pub fn set_trace(&mut self, len: usize) {
+        self.interpreter.set_trace(len);
+    }
+
+    // Print the last instructions run
+    pub fn dump_trace(&self) {
+        self.interpreter.dump_trace();
+    }
+
+    // Symbols of the program (see `Symbols`); loaded from next to the ROM as it
+    // is inserted, or set by the frontend. The breakpoints of the source are set
+    // (and those of the symbols before removed).
+    pub fn set_symbols(&mut self, symbols: Option<Symbols>) {
+        if let Some(ref symbols) = self.symbols {
+            for &(_, address) in symbols.breakpoints() {
+                self.interpreter.remove_breakpoint(address);
+            }
+        }
+
+        if let Some(ref symbols) = symbols {
+            for &(_, address) in symbols.breakpoints() {
+                self.interpreter.add_breakpoint(address);
+            }
+        }
+
+        self.symbols = symbols;
+    }
+
+    // Line of the source the program stopped at (if there is a source)
+    fn print_source_line(&self) {
+        let symbols = match self.symbols {
+            Some(ref symbols) => symbols,
+            None => return,
+        };
+
+        let line = match symbols.line(self.interpreter.state().pc) {
+            Some(line) => line,
+            None => return,
+        };
+
+        let name = symbols.source()
+            .and_then(|source| source.file_name())
+            .map_or("source".into(), |name| name.to_string_lossy());
+
+        println!("xCHIP: {}:{}: {}",
+                 name,
+                 line,
+                 symbols.source_line(line).unwrap_or("").trim());
+    }
+
+    // Run a Debug Adapter Protocol server on `port` of localhost (the `dap`
+    // feature; also started by `XCHIP_DAP` in the environment as a ROM is
+    // inserted), for debuggers such as that of VS Code
+    //  Breakpoints are set by address (instruction breakpoints); registers are
+    //  the variables. A single client is served at a time.
+    #[cfg(feature = "dap")]
+    pub fn listen_dap(&mut self, port: u16) -> io::Result<()> {
+        self.dap = Some(dap::Server::listen(port)?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "dap")]
+    fn listen_dap_from_env(&mut self) {
+        if self.dap.is_some() {
+            return;
+        }
+
+        let value = match env::var("XCHIP_DAP") {
+            Ok(value) => value,
+            Err(_) => return,
+        };
+
+        match value.parse() {
+            Ok(port) => {
+                if let Err(error) = self.listen_dap(port) {
+                    eprintln!("error: failed to start debug adapter: {}", error);
+                }
+            }
+
+            Err(_) => eprintln!("warning: invalid port in XCHIP_DAP: {}", value),
+        }
+    }
+
+    // Handle the requests of the debug adapter client (if any)
+    #[cfg(feature = "dap")]
+    fn poll_dap(&mut self, r: &mut axal::Runtime) {
+        if let Some(mut dap) = self.dap.take() {
+            dap.poll(self, r);
+            self.dap = Some(dap);
+        }
+    }
+
+    // Observe execution (see `Hooks`); returns the id to remove the hooks by
+    pub fn add_hooks(&mut self, hooks: Box<Hooks>) -> usize {
+        self.interpreter.add_hooks(hooks)
+    }
+
+    pub fn remove_hooks(&mut self, id: usize) {
+        self.interpreter.remove_hooks(id);
+    }
+
+    // Go back `frames` frames (or as far as the states kept go); returns the
+    // number of frames gone back (0 if there are no states kept)
+    //  The state is restored from the last one kept at or before the frame.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let (rewound, state) = match self.rewind.rewind(frames) {
+            Some((rewound, state)) => (rewound, state.to_vec()),
+            None => return 0,
+        };
+
+        if let Err(error) = self.interpreter.load_state(&state) {
+            eprintln!("error: failed to rewind: {}", error);
+            self.rewind.clear();
+
+            return 0;
+        }
+
+        // A movie goes back as well (and is recorded again from there)
+        if let Some(ref mut movie) = self.recording {
+            let frame = movie.len().saturating_sub(rewound);
+            movie.rerecord(frame);
+        }
+
+        self.playback_frame = self.playback_frame.saturating_sub(rewound);
+
+        rewound
+    }
+
+    // Save the state of the ROM when it is removed (to `auto_resume` from); off by default
+    //  The state is kept for each ROM (by its hash) in the data directory
+    //  (eg. ~/.local/share/xchip/resume/); not in lockstep.
+    pub fn set_auto_save(&mut self, enabled: bool) {
+        self.auto_save = enabled;
+    }
+
+    // Restore the state saved when the ROM was last removed as it is inserted
+    // (suspend / resume); off by default
+    pub fn set_auto_resume(&mut self, enabled: bool) {
+        self.auto_resume = enabled;
+    }
+
+    // Hash of the emulated state (registers, RAM, screen, timers); to compare every
+    // few frames to spot when two sessions (netplay, replays) diverge; `None` if no
+    // ROM is inserted
+    pub fn state_hash(&self) -> Option<u64> {
+        self.interpreter.state_hash()
+    }
+
+    // Save the state of the inserted ROM into `slot` (replacing what is there)
+    //  Slots are kept for each ROM (by its hash) in the data directory
+    //  (eg. ~/.local/share/xchip/states/).
+    pub fn save_slot(&self, slot: usize) -> Result<(), Error> {
+        let rom_hash = self.interpreter.rom_hash().ok_or(Error::NoRom)?;
+        let state = self.serialize().ok_or(Error::NoRom)?;
+
+        slots::save(rom_hash, slot, &state)
+    }
+
+    // Restore the state saved in `slot` for the inserted ROM
+    pub fn load_slot(&mut self, slot: usize) -> Result<(), Error> {
+        let rom_hash = self.interpreter.rom_hash().ok_or(Error::NoRom)?;
+        let state = slots::load(rom_hash, slot)?;
+
+        self.deserialize(&state)
+    }
+
+    // Slots with a state saved for the inserted ROM (and when it was saved)
+    pub fn save_slots(&self) -> Vec<SaveSlot> {
+        match self.interpreter.rom_hash() {
+            Some(rom_hash) => slots::list(rom_hash),
+            None => Vec::new(),
+        }
+    }
+
+    // Preview of the screen (scaled down to at most 64 pixels wide) when the
+    // savestate `state` was made; to show when choosing a state to load
+    pub fn state_thumbnail(state: &[u8]) -> Option<Thumbnail> {
+        interpreter::Interpreter::state_thumbnail(state)
+    }
+
+    // As `state_thumbnail` for the state saved in `slot`
+    pub fn slot_thumbnail(&self, slot: usize) -> Option<Thumbnail> {
+        let rom_hash = self.interpreter.rom_hash()?;
+        let state = slots::load(rom_hash, slot).ok()?;
+
+        Core::state_thumbnail(&state)
+    }
+
+    // Record the keys down in every frame from now on (or from a reset) into a
+    // movie; to capture a session for a bug report or a TAS
+    //  The movie starts from a savestate (which includes the state of the random
+    //  numbers); resetting or loading a state while recording is not recorded.
+    pub fn start_recording(&mut self, from_reset: bool) -> Result<(), Error> {
+        let rom_hash = self.interpreter.rom_hash().ok_or(Error::NoRom)?;
+        let variant = self.interpreter.mode().ok_or(Error::NoRom)?;
+
+        if from_reset {
+            self.reset_all();
+        }
+
+        let state = self.interpreter.save_state(false).ok_or(Error::NoRom)?;
+
+        // States kept from before the recording can't be rewound to (the movie
+        // starts after them)
+        self.rewind.clear();
+        self.recording = Some(Movie::new(rom_hash,
+                                         variant,
+                                         self.interpreter.quirks(),
+                                         self.interpreter.seed(),
+                                         state));
+
+        Ok(())
+    }
+
+    // Stop recording; returns the movie recorded (`None` if not recording)
+    pub fn stop_recording(&mut self) -> Option<Movie> {
+        self.recording.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Play back `movie` from its starting state; the keys of the runtime are ignored
+    // until it ends (or is stopped)
+    //  Every frame is checked to start from the state it did when recorded; the
+    //  first that does not is reported (see `movie_desync`), and the movie keeps
+    //  playing.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), Error> {
+        let rom_hash = self.interpreter.rom_hash().ok_or(Error::NoRom)?;
+        if movie.rom_hash() != rom_hash {
+            return Err(Error::WrongRom);
+        }
+
+        self.interpreter.load_state(movie.state())?;
+        self.rewind.clear();
+        self.recording = None;
+        self.playback = Some(movie);
+        self.playback_frame = 0;
+        self.desync = None;
+
+        Ok(())
+    }
+
+    // Stop playback (the keys of the runtime are used again); returns the movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.playback.take()
+    }
+
+    pub fn is_playing_movie(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    // Frame of the movie that is played next
+    pub fn movie_frame(&self) -> usize {
+        self.playback_frame
+    }
+
+    // Frame of the movie recorded or played back that is run next
+    fn movie_position(&self) -> Option<usize> {
+        match (&self.recording, &self.playback) {
+            (&Some(ref movie), _) => Some(movie.len()),
+            (_, &Some(_)) => Some(self.playback_frame),
+            _ => None,
+        }
+    }
+
+    // First frame of the movie played back that did not start from the state it
+    // did when recorded (`None` if all have so far); only the frames hashed are
+    // checked (see `Movie::is_checked`)
+    //  Divergence is usually caused by settings that differ from those the movie
+    //  was recorded with (speed, timing, quirks).
+    pub fn movie_desync(&self) -> Option<usize> {
+        self.desync
+    }
+
+    // Counters of the work done since the ROM was inserted (instructions, frames,
+    // draws, etc.); to diagnose speed problems
+    pub fn stats(&self) -> Stats {
+        Stats {
+            instructions: self.interpreter.instructions(),
+            frames: self.frames,
+            frames_presented: self.frames_presented,
+            draws: self.interpreter.draws(),
+            instructions_per_second: self.measured_rate,
+            frame_time: self.frame_time,
+        }
+    }
+
+    // Print the statistics every second (also set by `XCHIP_STATS` in the environment)
+    pub fn set_print_stats(&mut self, enabled: bool) {
+        self.print_stats = enabled;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Run exactly one instruction (usable while paused; for debuggers) and present
+    // the screen if it changed
+    //  The instruction is run even if the frame would have ended; if it waits for
+    //  the vertical blank, the next frame is started (clocking the timers).
+    pub fn step_instruction(&mut self, r: &mut axal::Runtime) -> State {
+        // An instruction waiting for the vertical blank runs in the next frame
+        if self.interpreter.is_waiting_for_vblank() {
+            self.interpreter.vblank();
+        }
+
+        let input = self.interpreter.read_input(r);
+        self.interpreter.set_input(input);
+
+        // A breakpoint does not stop a step
+        self.interpreter.skip_breakpoint();
+        self.interpreter.run_next(r);
+
+        // Dots fade (or light up) as they would at the end of a frame
+        self.interpreter.fade();
+        self.present(r);
+
+        self.interpreter.state()
+    }
+
+    // Resume until the instruction after the next one is reached, running a `CALL`
+    // to its return (for debuggers); emulation is paused there once the frame is
+    // presented (or at a breakpoint or watchpoint hit on the way)
+    pub fn step_over(&mut self) {
+        self.interpreter.step_over();
+        self.resume();
+    }
+
+    // As `step_over`, until the subroutine the program is in returns; if it is
+    // not in one, emulation is paused where it is
+    pub fn step_out(&mut self) {
+        if self.interpreter.step_out() {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    // Run exactly one frame (usable while paused; for debuggers) and present it
+    pub fn step_frame(&mut self, r: &mut axal::Runtime) -> State {
+        self.run_frame(r);
+        self.present(r);
+
+        self.interpreter.state()
+    }
+
+    // State of the program (registers, timers)
+    pub fn state(&self) -> State {
+        self.interpreter.state()
+    }
+
+    // Run the frames that were missed (up to 4 at once) when the runtime calls
+    // `run_next` late (eg. a dropped frame) so the game keeps its speed; off by default
+    //  The frames run depend on the wall clock; leave this off for deterministic
+    //  emulation (replays, netplay).
+    pub fn set_catch_up(&mut self, enabled: bool) {
+        self.catch_up = enabled;
+        self.last_frame = None;
+        self.frame_debt = 0;
+    }
+
+    // Run in lockstep: the same ROM, settings and keys always run the same frames
+    // (replays, netplay, differential testing); off by default
+    //  Nothing from outside the emulation is used: one frame is run per call of
+    //  `run_next` (no catching up), the timers tick once per frame (whatever the
+    //  clock set), RND is seeded with a fixed seed (unless one is set) and the
+    //  RPL user flags are not persisted. See `check_determinism`.
+    pub fn set_lockstep(&mut self, enabled: bool) {
+        self.interpreter.set_lockstep(enabled);
+        self.last_frame = None;
+        self.frame_debt = 0;
+    }
+
+    pub fn is_lockstep(&self) -> bool {
+        self.interpreter.is_lockstep()
+    }
+
+    // Run the next `frames` frames twice (with the keys down now) and compare the
+    // states they start from; returns the first that differs (`None` if all are
+    // the same, as they are in lockstep)
+    //  Emulation is left where it was.
+    pub fn check_determinism(&mut self, r: &mut axal::Runtime, frames: usize) -> Option<usize> {
+        let snapshot = self.interpreter.snapshot();
+        let input = self.interpreter.read_input(r);
+        self.interpreter.set_speculative(true);
+
+        let mut hashes = Vec::with_capacity(frames);
+        for _ in 0..frames {
+            hashes.push(self.interpreter.state_hash());
+            self.interpreter.set_input(input);
+            self.run_frame_silently(r);
+        }
+
+        self.interpreter.restore(snapshot);
+        let snapshot = self.interpreter.snapshot();
+
+        let mut diverged = None;
+        for (frame, hash) in hashes.iter().enumerate() {
+            if self.interpreter.state_hash() != *hash {
+                diverged = Some(frame);
+                break;
+            }
+
+            self.interpreter.set_input(input);
+            self.run_frame_silently(r);
+        }
+
+        self.interpreter.restore(snapshot);
+        self.interpreter.set_speculative(false);
+
+        diverged
+    }
+
+    // Select how long instructions take: the same (as many as the speed per frame;
+    // the default) or as long as they took on the COSMAC VIP
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.interpreter.set_timing(timing);
+    }
+
+    // Select the SUPER-CHIP revision (1.0 or 1.1) to behave as; takes effect
+    // when the next ROM is inserted
+    pub fn set_super_chip_revision(&mut self, revision: SuperChipRevision) {
+        self.interpreter.set_super_chip_revision(revision);
+    }
+
+    // Override the quirks of the variant (`None` restores them)
+    //  See `Quirks::preset` for the quirks of well-known interpreters.
+    pub fn set_quirks(&mut self, quirks: Option<Quirks>) {
+        self.quirks = quirks;
+
+        let quirks = self.quirks();
+        self.interpreter.set_quirks(quirks);
+    }
+
+    // Set a single quirk from an option (eg. `clip_sprites` = `off`); takes effect
+    // immediately (mid-game) and is kept across ROMs as if set by `set_quirks`
+    //  This is the shape of core options for when the runtime supports them. Returns
+    //  false (and changes nothing) if the quirk or value is unknown.
+    pub fn set_quirk(&mut self, name: &str, value: &str) -> bool {
+        let mut quirks = self.interpreter.quirks();
+        if !quirks.set(name, value) {
+            return false;
+        }
+
+        self.set_quirks(Some(quirks));
+
+        true
+    }
+
+    // Quirks in effect: set by `set_quirks` or else by the preset named by
+    // `XCHIP_QUIRKS` in the environment
+    pub fn quirks(&self) -> Option<Quirks> {
+        if self.quirks.is_some() {
+            return self.quirks;
+        }
+
+        let name = env::var("XCHIP_QUIRKS").ok()?;
+        let quirks = Quirks::preset(&name);
+        if quirks.is_none() {
+            eprintln!("warning: unknown quirks preset in XCHIP_QUIRKS: {}", name);
+        }
+
+        quirks
+    }
+
+    // Set the speed in instructions run per (60 Hz) frame; `None` restores the speed
+    // of the variant (or as the ROM needs); takes effect immediately
+    //  Games range from ~7 (Pong) to several hundred (Octojam demos).
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: Option<usize>) {
+        self.set_instructions_per_second(instructions_per_frame.map(|n| n * 60));
+    }
+
+    // Set the speed in instructions run per second
+    //  Speeds that are not a multiple of 60 Hz (eg. 500 Hz) are kept exact by
+    //  carrying the fraction of an instruction over to the next frame.
+    pub fn set_instructions_per_second(&mut self, instructions_per_second: Option<usize>) {
+        self.instructions_per_second = instructions_per_second;
+
+        let instructions_per_second = self.instructions_per_second();
+        self.interpreter.set_instructions_per_second(instructions_per_second);
+    }
+
+    // Speed in effect: set by `set_instructions_per_second` (or `_per_frame`) or else
+    // by `XCHIP_SPEED` (instructions per frame) in the environment
+    pub fn instructions_per_second(&self) -> Option<usize> {
+        if self.instructions_per_second.is_some() {
+            return self.instructions_per_second;
+        }
+
+        let value = env::var("XCHIP_SPEED").ok()?;
+        let instructions_per_frame = value.parse().ok().filter(|&n: &usize| n > 0);
+        if instructions_per_frame.is_none() {
+            eprintln!("warning: invalid speed in XCHIP_SPEED: {}", value);
+        }
+
+        instructions_per_frame.map(|n| n * 60)
+    }
+
+    // Select the source of the ticks that clock DT / ST; `None` ticks once per frame
+    // (deterministic; the default)
+    //  `RealTimeClock` follows the wall clock; `VirtualClock` only ticks when advanced.
+    pub fn set_clock(&mut self, clock: Option<Box<Clock>>) {
+        self.interpreter.set_clock(clock);
+    }
+
+    // Seed the random numbers of `CXNN` (RND); the program then runs the same after
+    // every reset given the same input (replays, netplay, regression tests)
+    //  `None` (the default) seeds from entropy on every reset.
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.interpreter.set_seed(seed);
+    }
+
+    // Force the variant (CHIP-8, CHIP-48, SUPER-CHIP, XO-CHIP, etc.) to run ROMs as;
+    // `None` detects it from the ROM
+    //  An inserted ROM is re-inserted (and so reset) under the new variant.
+    pub fn set_variant(&mut self, variant: Option<Variant>) {
+        self.variant = variant;
+
+        if let Some(filename) = self.rom.clone() {
+            let variant = self.variant();
+            if let Err(error) = self.interpreter.insert_rom(&filename, variant) {
+                eprintln!("error: {}: {}", filename, error);
+                return;
+            }
+
+            self.interpreter.reset();
+            self.rewind.clear();
+        }
+    }
+
+    // Variant in effect: set by `set_variant` or else by `XCHIP_MODE` in the environment
+    pub fn variant(&self) -> Option<Variant> {
+        if self.variant.is_some() {
+            return self.variant;
+        }
+
+        let name = env::var("XCHIP_MODE").ok()?;
+        let variant = Variant::from_name(&name);
+        if variant.is_none() {
+            eprintln!("warning: unknown mode in XCHIP_MODE: {}", name);
+        }
+
+        variant
+    }
+
+    // Insert the ROM at `filename` (as `rom_insert` but reporting failure)
+    pub fn load_rom(&mut self, filename: &str) -> Result<(), Error> {
+        // Settings set through the API take precedence over the configuration files
+        let config = config::Config::load(filename);
+
+        let variant = self.variant().or(config.variant);
+        self.interpreter.insert_rom(filename, variant)?;
+        self.rom = Some(filename.to_string());
+
+        // Those of the ROM before are kept if this one can't be inserted (and
+        // none are left from it if this one has no configuration)
+        self.interpreter.set_plane_colors(self.plane_colors.or(config.palette));
+
+        let instructions_per_second = self.instructions_per_second()
+            .or(config.instructions_per_frame.map(|n| n * 60));
+        self.interpreter.set_instructions_per_second(instructions_per_second);
+        self.interpreter.set_keymap(config.keymap);
+
+        let quirks = self.quirks().or(config.quirks);
+        self.interpreter.set_quirks(quirks);
+        self.set_symbols(Symbols::load(filename));
+
+        self.frames = 0;
+        self.frames_presented = 0;
+        self.rate = Default::default();
+        self.rewind.clear();
+        if env::var_os("XCHIP_STATS").is_some() {
+            self.print_stats = true;
+        }
+
+        #[cfg(feature = "dap")]
+        self.listen_dap_from_env();
+
+        if self.auto_resume {
+            self.resume_state(filename);
+        }
+
+        Ok(())
+    }
+
+    // Select what is accessed for addresses past the end of RAM; wraps around by
+    // default (as the hardware does)
+    pub fn set_address_policy(&mut self, policy: AddressPolicy) {
+        self.interpreter.set_address_policy(policy);
+    }
+
+    // Select what is done for instructions the variant does not have; the
+    // program is stopped by default
+    pub fn set_opcode_policy(&mut self, policy: OpcodePolicy) {
+        self.interpreter.set_opcode_policy(policy);
+    }
+
+    // Select what is done for `0NNN` (machine code routines can't be run); skipped
+    // and logged by default
+    pub fn set_machine_call_policy(&mut self, policy: MachineCallPolicy) {
+        self.interpreter.set_machine_call_policy(policy);
+    }
+
+    // Protect the interpreter area ($000 - $1FF; incl. the font) from writes by the
+    // program; off by default
+    //  A write (eg. a runaway `FX55`) stops the program with a fault instead of
+    //  corrupting the font.
+    pub fn set_write_protection(&mut self, enabled: bool) {
+        self.interpreter.set_write_protection(enabled);
+    }
+
+    // Why the program was stopped (eg. an unsupported instruction, which
+    // usually means the ROM is for another variant); cleared on reset
+    pub fn fault(&self) -> Option<Fault> {
+        self.interpreter.fault()
+    }
+
+    // True if the program has exited (SUPER-CHIP `00FD`) or was stopped (see
+    // `fault`); the core presents the final frame until reset
+    pub fn is_halted(&self) -> bool {
+        self.interpreter.is_halted()
+    }
+
+    // True if the last frame run was a duplicate of the previous one (and so
+    // was not presented to the runtime)
+    pub fn is_frame_dupe(&self) -> bool {
+        self.frame_dupe
+    }
 }
 
 impl axal::Core for Core {
     fn info(&self) -> axal::Info {
+        let scale = self.interpreter.output_scale();
+        let rotation = self.interpreter.rotation();
+        let (width, height) = rotation.apply_size(64 * scale, 32 * scale);
+        let (max_width, max_height) = rotation.apply_size(256 * scale, 192 * scale);
+
+        // TODO: Report the frame rate once `axal::Info` has a field for it; until
+        //  then the runtime has to ask `Core::frame_rate`
         axal::Info::new("xCHIP", env!("CARGO_PKG_VERSION"))
-            .pixel_format(axal::PixelFormat::R3_G3_B2)
-            .size(64, 32)
-            .max_size(128, 64)
+            .pixel_format(self.interpreter.pixel_format().to_axal())
+            .size(width as u32, height as u32)
+            .max_size(max_width as u32, max_height as u32)
     }
 
     fn reset(&mut self) {
-        self.interpreter.reset();
+        self.reset_all();
     }
 
     fn rom_insert(&mut self, filename: &str) {
-        self.interpreter.insert_rom(filename, None);
+        // The runtime has no way to be told; report the error
+        if let Err(error) = self.load_rom(filename) {
+            eprintln!("error: {}: {}", filename, error);
+        }
     }
 
     fn rom_remove(&mut self) {
+        if self.auto_save {
+            self.suspend_state();
+        }
+
         self.interpreter.remove_rom();
+        self.rom = None;
+        self.set_symbols(None);
+        self.rate = Default::default();
+        self.rewind.clear();
     }
 
-    // Run core for a _single_ frame
+    // Run core for a _single_ frame (or, catching up, for those that were missed)
     fn run_next(&mut self, r: &mut axal::Runtime) {
-        // Interpreter: Run 8 instructions = 1 frame ~> 480 Hz
-        for _ in 0..8 {
-            self.interpreter.run_next(r);
+        #[cfg(feature = "dap")]
+        self.poll_dap(r);
+
+        if self.paused {
+            let samples = audio::SAMPLE_RATE as usize / self.interpreter.frame_rate().hz();
+            self.silence.resize(samples * 2, 0);
+            r.audio_sample_batch(&self.silence);
+
+            self.frame_dupe = true;
+            return;
+        }
+
+        let start = Instant::now();
+
+        for _ in 0..self.frames_due() {
+            self.run_frame(r);
+            self.frames += 1;
+
+            if self.interpreter.is_stopped() {
+                break;
+            }
+        }
+
+        // Frames run ahead would run past the breakpoint (or watchpoint)
+        let stopped = self.interpreter.is_stopped();
+        if self.run_ahead > 0 && !stopped {
+            self.run_ahead(r);
+        } else {
+            self.present(r);
+        }
+
+        if !self.frame_dupe {
+            self.frames_presented += 1;
+        }
+
+        self.frame_time = start.elapsed();
+        if let Some(rate) = self.rate.update(self.interpreter.instructions()) {
+            self.measured_rate = rate;
+
+            if self.print_stats {
+                println!("xCHIP: {}", self.stats());
+            }
+        }
+
+        if stopped {
+            if let Some(address) = self.interpreter.breakpoint_hit() {
+                match self.symbols {
+                    Some(ref symbols) => {
+                        println!("xCHIP: stopped at breakpoint ${:03X} ({})",
+                                 address,
+                                 symbols.describe(address))
+                    }
+
+                    None => println!("xCHIP: stopped at breakpoint ${:03X}", address),
+                }
+            }
+
+            if let Some(hit) = self.interpreter.watch_hit() {
+                println!("xCHIP: stopped at watchpoint: {}", hit);
+            }
+
+            if let Some(address) = self.interpreter.step_hit() {
+                println!("xCHIP: stepped to ${:03X}", address);
+            }
+
+            self.print_source_line();
+            self.pause();
+        }
+
+        if self.break_on_vblank {
+            self.pause();
+        }
+    }
+
+    // Savestates: see `Core::serialize` / `Core::deserialize` (axal has no hook for
+    // them yet)
+}
+
+impl Core {
+    // Path of the state saved when the inserted ROM is removed
+    //  <data dir>/resume/<rom hash>.state
+    fn resume_path(&self) -> Option<PathBuf> {
+        if self.interpreter.is_lockstep() {
+            return None;
         }
 
+        self.interpreter.rom_hash().and_then(|hash| storage::rom_path("resume", hash, "state"))
+    }
+
+    // Save the state of the inserted ROM to resume from
+    fn suspend_state(&mut self) {
+        let path = match self.resume_path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Some(state) = self.serialize() {
+            if let Err(error) = storage::write(&path, &state) {
+                eprintln!("warning: failed to save state to {}: {}", path.display(), error);
+            }
+        }
+    }
+
+    // Restore the state saved when the inserted ROM was last removed (if any)
+    fn resume_state(&mut self, filename: &str) {
+        let state = match self.resume_path().and_then(|path| storage::read(&path)) {
+            Some(state) => state,
+            None => return,
+        };
+
+        match self.deserialize(&state) {
+            Ok(()) => println!("xCHIP: resumed {} where it was left", filename),
+            Err(error) => eprintln!("warning: {}: failed to resume: {}", filename, error),
+        }
+    }
+
+    fn reset_all(&mut self) {
+        self.interpreter.reset();
+        self.rewind.clear();
+    }
+
+    // Present the screen
+    fn present(&mut self, r: &mut axal::Runtime) {
         // Video: Refresh
-        let (framebuffer, width, height) = self.interpreter.screen_as_framebuffer();
-        r.video_refresh(framebuffer, width as u32, height as u32);
+        //  The dimensions are taken from the active screen so a runtime that
+        //  changes resolution (CHIP-10, SUPER-CHIP) is presented correctly.
+        //  Nothing is presented if the screen is unchanged; the runtime
+        //  keeps showing the previous frame (frame dupe).
+        self.frame_dupe = true;
+        if let Some((framebuffer, width, height)) = self.interpreter.screen_as_framebuffer() {
+            if !framebuffer.is_empty() {
+                r.video_refresh(framebuffer, width as u32, height as u32);
+                self.frame_dupe = false;
+            }
+        }
     }
 
-    // fn serialize() { }
-    // fn deserialize() { }
+    // Run the interpreter for a frame (and render its audio)
+    fn run_frame(&mut self, r: &mut axal::Runtime) {
+        let interpreter = &self.interpreter;
+        self.rewind.record(|| interpreter.save_state(true));
+
+        // The keys are latched for the frame (and run-ahead)
+        let input = self.next_input(r);
+        self.interpreter.set_input(input);
+        if let Some(ref mut movie) = self.recording {
+            // A ROM is inserted while recording
+            let interpreter = &self.interpreter;
+            movie.push(input, || interpreter.state_hash().unwrap_or(0));
+        }
+
+        if self.resumed {
+            self.interpreter.resync_keys();
+            self.resumed = false;
+        }
+
+        self.run_frame_silently(r);
+
+        // Audio: 1 frame (1/60 s or 1/50 s) of samples
+        let samples = audio::SAMPLE_RATE as usize / self.interpreter.frame_rate().hz();
+        r.audio_sample_batch(self.interpreter.audio_frame(samples));
+    }
+
+    // Keys down for the next frame: from the movie played back, or else the runtime
+    fn next_input(&mut self, r: &mut axal::Runtime) -> interpreter::Input {
+        let frame = self.playback_frame;
+        let input = match self.playback {
+            Some(ref movie) => {
+                if self.desync.is_none() && movie.is_checked(frame) {
+                    if let Some(state_hash) = self.interpreter.state_hash() {
+                        if !movie.verify(frame, state_hash) {
+                            eprintln!("warning: movie desynced at frame {}", frame);
+                            self.desync = Some(frame);
+                        }
+                    }
+                }
+
+                movie.input(frame)
+            }
+
+            None => None,
+        };
+
+        match input {
+            Some(input) => {
+                self.playback_frame += 1;
+                input
+            }
+
+            None => {
+                if self.playback.take().is_some() {
+                    println!("xCHIP: movie ended at frame {}", frame);
+                }
+
+                self.interpreter.read_input(r)
+            }
+        }
+    }
+
+    // Run the interpreter for a frame (without rendering its audio)
+    fn run_frame_silently(&mut self, r: &mut axal::Runtime) {
+        // Interpreter: Run 8 instructions = 1 frame ~> 480 Hz
+        //  (unless the runtime needs more, eg. Octo runs 1000, or the speed is set)
+        //  A frame is cut short when a sprite is drawn by a variant that waits
+        //  for the vertical blank, or when the program is idle (jumping to
+        //  itself or waiting for a key); the keys only change between frames
+        //  so the same instruction is only run once per frame.
+        //  With the COSMAC VIP timing, instructions are run for the machine
+        //  cycles of a frame instead.
+        self.interpreter.vblank();
+        let mut cycles = self.interpreter.next_frame_cycles();
+        while cycles > 0 {
+            if self.interpreter.is_halted() || self.interpreter.is_waiting_for_vblank() {
+                break;
+            }
+
+            let taken = self.interpreter.run_next(r);
+            if self.interpreter.is_stopped() {
+                break;
+            }
+
+            if taken > cycles {
+                self.interpreter.owe_cycles(taken - cycles);
+            }
+
+            cycles = cycles.saturating_sub(taken);
+
+            if self.interpreter.is_idle() {
+                break;
+            }
+        }
+
+        self.interpreter.fade();
+    }
+
+    // Run the frames of run-ahead (with the input as it is now), present the last,
+    // and return to where emulation was
+    fn run_ahead(&mut self, r: &mut axal::Runtime) {
+        let snapshot = self.interpreter.snapshot();
+
+        self.interpreter.set_speculative(true);
+        for _ in 0..self.run_ahead {
+            self.run_frame_silently(r);
+        }
+
+        self.present(r);
+        self.interpreter.restore(snapshot);
+        self.interpreter.set_speculative(false);
+    }
+
+    // Number of frames to run for this call of `run_next`: 1, or (catching up) as
+    // many as have passed by the wall clock since the last call
+    //  At most `MAX_CATCH_UP_FRAMES` are run; time beyond that is dropped so a
+    //  host that can't keep up slows the game down rather than falling further
+    //  behind with every call.
+    fn frames_due(&mut self) -> usize {
+        if !self.catch_up || self.interpreter.is_lockstep() {
+            return 1;
+        }
+
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            let elapsed = now.duration_since(last_frame);
+            self.frame_debt += (elapsed.as_secs() * 1_000_000_000) + (elapsed.subsec_nanos() as u64);
+        }
+
+        self.last_frame = Some(now);
+
+        let frame_duration = 1_000_000_000 / self.interpreter.frame_rate().hz() as u64;
+        let frames = cmp::max(1, self.frame_debt / frame_duration) as usize;
+        if frames > MAX_CATCH_UP_FRAMES {
+            self.frame_debt = 0;
+            return MAX_CATCH_UP_FRAMES;
+        }
+
+        self.frame_debt = self.frame_debt.saturating_sub(frames as u64 * frame_duration);
+
+        frames
+    }
 }
 
-// impl axal::Debug for Core { }
+// NOTE: Until axal has a debugging interface (`axal::Debug`), it is `Debugger`
+impl Debugger for Core {
+    fn registers(&self) -> State {
+        self.interpreter.state()
+    }
+
+    fn register(&self, register: Register) -> usize {
+        self.interpreter.register(register)
+    }
+
+    fn set_register(&mut self, register: Register, value: usize) {
+        self.interpreter.set_register(register, value);
+    }
+
+    fn memory_size(&self) -> usize {
+        self.interpreter.memory_size()
+    }
+
+    fn read_memory(&self, address: usize) -> u8 {
+        self.interpreter.peek(address)
+    }
+
+    fn write_memory(&mut self, address: usize, value: u8) {
+        self.interpreter.poke(address, value);
+    }
+
+    fn read_range(&self, address: usize, len: usize) -> &[u8] {
+        self.interpreter.peek_range(address, len)
+    }
+
+    fn write_range(&mut self, address: usize, bytes: &[u8]) {
+        self.interpreter.poke_range(address, bytes);
+    }
+
+    fn take_memory_changed(&mut self) -> Option<(usize, usize)> {
+        self.interpreter.take_memory_changed()
+    }
+
+    fn pause(&mut self) {
+        Core::pause(self);
+    }
+
+    fn resume(&mut self) {
+        Core::resume(self);
+    }
+
+    fn is_paused(&self) -> bool {
+        Core::is_paused(self)
+    }
+
+    fn step(&mut self, r: &mut axal::Runtime) -> State {
+        self.step_instruction(r)
+    }
+
+    fn step_frame(&mut self, r: &mut axal::Runtime) -> State {
+        Core::step_frame(self, r)
+    }
+
+    fn step_over(&mut self) {
+        Core::step_over(self);
+    }
+
+    fn step_out(&mut self) {
+        Core::step_out(self);
+    }
+
+    fn add_breakpoint(&mut self, address: usize) {
+        self.interpreter.add_breakpoint(address);
+    }
+
+    fn add_conditional_breakpoint(&mut self, address: usize, condition: &str) -> Result<(), Error> {
+        self.interpreter.add_conditional_breakpoint(address, condition)
+    }
+
+    fn remove_breakpoint(&mut self, address: usize) {
+        self.interpreter.remove_breakpoint(address);
+    }
+
+    fn breakpoints(&self) -> Vec<usize> {
+        self.interpreter.breakpoints()
+    }
+
+    fn breakpoint_hit(&self) -> Option<usize> {
+        self.interpreter.breakpoint_hit()
+    }
+
+    fn call_stack(&self) -> Vec<Call> {
+        self.interpreter.call_stack().to_vec()
+    }
+
+    fn trace(&self) -> Vec<TraceEntry> {
+        self.interpreter.trace()
+    }
+
+    fn disassemble(&self, address: usize, count: usize) -> Vec<Disassembly> {
+        // There are no more instructions than words of RAM
+        let size = self.interpreter.memory_size();
+        let count = cmp::min(count, size / 2);
+
+        let read = |address| self.interpreter.peek(address);
+        let mut instructions = disasm::disassemble(read, size, address, count);
+
+        if let Some(ref symbols) = self.symbols {
+            for instruction in &mut instructions {
+                instruction.label = symbols.label(instruction.address).map(|label| label.to_string());
+                instruction.target_label = instruction.target
+                    .and_then(|target| symbols.locate(target).map(|_| symbols.describe(target)));
+            }
+        }
+
+        instructions
+    }
+
+    fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref()
+    }
+
+    fn add_breakpoint_by_name(&mut self, name: &str) -> Result<(), Error> {
+        let address = self.symbols
+            .as_ref()
+            .and_then(|symbols| symbols.address(name))
+            .ok_or_else(|| Error::UnknownSymbol(name.to_string()))?;
+
+        self.interpreter.add_breakpoint(address);
+
+        Ok(())
+    }
+
+    fn add_line_breakpoint(&mut self, line: usize) -> Result<usize, Error> {
+        let address = self.symbols
+            .as_ref()
+            .and_then(|symbols| symbols.line_address(line))
+            .ok_or(Error::NoCodeAtLine { line: line })?;
+
+        self.interpreter.add_breakpoint(address);
+
+        Ok(address)
+    }
+
+    fn variable(&self, name: &str) -> Option<Vec<u8>> {
+        let symbols = self.symbols.as_ref()?;
+        let (address, len) = match symbols.monitors().iter().find(|monitor| monitor.0 == name) {
+            Some(monitor) => (monitor.1, monitor.2),
+            None => (symbols.address(name)?, 1),
+        };
+
+        let len = cmp::min(len, self.interpreter.memory_size());
+
+        Some((0..len).map(|i| self.interpreter.peek(address + i)).collect())
+    }
+
+    fn add_watchpoint(&mut self, address: usize, len: usize, watch: Watch) {
+        self.interpreter.add_watchpoint(address, len, watch);
+    }
+
+    fn remove_watchpoint(&mut self, address: usize, len: usize) {
+        self.interpreter.remove_watchpoint(address, len);
+    }
+
+    fn watch_hit(&self) -> Option<WatchHit> {
+        self.interpreter.watch_hit()
+    }
+}
 
 // impl axal::UI (name?) for Core { }
 
@@ -0,0 +1,540 @@
+use std::cmp;
+use std::vec::Vec;
+
+use super_chip;
+use audio;
+use opcode::Opcode;
+use mmu::Mmu;
+use interpreter::{Runtime, Context};
+use axal;
+use quirks::Quirks;
+use error::Error;
+use savestate::{Reader, Writer};
+
+// Mega-CHIP screen size
+const WIDTH: usize = 256;
+const HEIGHT: usize = 192;
+
+// Sprites drawn from below this address are 1-bit font sprites (even in Mega-CHIP mode)
+const FONT_END: usize = 0x100;
+
+// How sprite dots are combined with the screen
+#[derive(Clone, Copy, PartialEq)]
+enum Blend {
+    Normal,
+    Alpha25,
+    Alpha50,
+    Add,
+    Multiply,
+}
+
+impl Default for Blend {
+    fn default() -> Self {
+        Blend::Normal
+    }
+}
+
+// Scale each channel of `color` (0xRRGGBB) by `f` and add `g` of `other`
+fn mix(color: u32, other: u32, f: f32, g: f32) -> u32 {
+    let mut r = 0;
+    for shift in &[0, 8, 16] {
+        let a = ((color >> shift) & 0xFF) as f32;
+        let b = ((other >> shift) & 0xFF) as f32;
+
+        r |= ((a * f + b * g).min(255.0) as u32) << shift;
+    }
+
+    r
+}
+
+#[derive(Clone)]
+pub struct MegaChip {
+    // Mega-CHIP starts from the SUPER-CHIP
+    super_chip: super_chip::SuperChip,
+
+    // Mega-CHIP mode (`0011`); the SUPER-CHIP display is used until enabled
+    enabled: bool,
+
+    // 256x192 truecolor screen (0xRRGGBB); drawn to and then presented on `00E0`
+    screen: Vec<u32>,
+    presented: Vec<u32>,
+
+    // Palette index of each dot in the screen (for collision detection)
+    indices: Vec<u8>,
+
+    // Sprite palette (0xAARRGGBB); index 0 is transparent
+    palette: Vec<u32>,
+
+    // Dimensions of sprites (`03NN`, `04NN`)
+    sprite_width: usize,
+    sprite_height: usize,
+
+    // Sprite opacity (`05NN`) and blend mode (`080N`)
+    alpha: u8,
+    blend: Blend,
+
+    // Drawing over a dot of this palette index is a collision (`09NN`)
+    collision_index: u8,
+
+    // Digitized sound (`060N`); unsigned 8-bit samples
+    sound: Vec<u8>,
+    sound_rate: u32,
+    sound_loop: bool,
+
+    // Position (sample) of the digitized sound; kept apart from the position in the
+    // 1-bit pattern (which continues once the sound stops)
+    sound_position: f32,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        MegaChip {
+            super_chip: Default::default(),
+            enabled: false,
+            screen: vec![0; WIDTH * HEIGHT],
+            presented: vec![0; WIDTH * HEIGHT],
+            indices: vec![0; WIDTH * HEIGHT],
+            palette: vec![0; 256],
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xFF,
+            blend: Blend::Normal,
+            collision_index: 0,
+            sound: Vec::new(),
+            sound_rate: 0,
+            sound_loop: false,
+            sound_position: 0.0,
+        }
+    }
+}
+
+impl MegaChip {
+    fn clear(&mut self) {
+        for dot in &mut self.screen {
+            *dot = 0;
+        }
+
+        for index in &mut self.indices {
+            *index = 0;
+        }
+    }
+
+    // Scroll the screen by (dx, dy) dots; dots scrolled in are black
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let previous = self.screen.clone();
+        let previous_indices = self.indices.clone();
+        let (width, height) = (WIDTH as isize, HEIGHT as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                let sx = x - dx;
+                let sy = y - dy;
+                let offset = (y * width + x) as usize;
+
+                if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    self.screen[offset] = previous[(sy * width + sx) as usize];
+                    self.indices[offset] = previous_indices[(sy * width + sx) as usize];
+                } else {
+                    self.screen[offset] = 0;
+                    self.indices[offset] = 0;
+                }
+            }
+        }
+    }
+
+    // Combine a sprite dot with the screen dot at `offset`
+    fn plot(&mut self, offset: usize, index: u8, color: u32) {
+        let dst = self.screen[offset];
+        let src = color & 0xFFFFFF;
+
+        // Opacity from the palette entry and the sprite alpha
+        let opacity = ((color >> 24) as f32 / 255.0) * (self.alpha as f32 / 255.0);
+
+        let blended = match self.blend {
+            Blend::Normal => mix(src, dst, opacity, 1.0 - opacity),
+            Blend::Alpha25 => mix(src, dst, opacity * 0.25, 1.0 - opacity * 0.25),
+            Blend::Alpha50 => mix(src, dst, opacity * 0.5, 1.0 - opacity * 0.5),
+            Blend::Add => mix(src, dst, opacity, 1.0),
+            Blend::Multiply => {
+                let mut r = 0;
+                for shift in &[0, 8, 16] {
+                    let a = (src >> shift) & 0xFF;
+                    let b = (dst >> shift) & 0xFF;
+
+                    r |= ((a * b) / 0xFF) << shift;
+                }
+
+                mix(r, dst, opacity, 1.0 - opacity)
+            }
+        };
+
+        self.screen[offset] = blended;
+        self.indices[offset] = index;
+    }
+
+    // Show a `sprite_width`x`sprite_height` sprite of palette indices from [I] at (x, y)
+    //  VF := 1 if a dot was drawn over the collision color
+    fn show(&mut self, c: &mut Context, m: &mut Mmu, x: usize, y: usize, n: u8) {
+        c.draws += 1;
+        c.v[0xF] = 0;
+
+        if c.i < FONT_END {
+            // Font sprites are 8xN 1-bit sprites drawn in white
+            for i in 0..(n as usize) {
+                let row = m.read(c.i + i);
+
+                for j in 0..8 {
+                    if (row >> (7 - j)) & 1 != 0 {
+                        let offset = ((y + i) % HEIGHT) * WIDTH + ((x + j) % WIDTH);
+
+                        c.v[0xF] |= (self.indices[offset] == self.collision_index) as u8;
+                        self.plot(offset, 1, 0xFFFFFFFF);
+                    }
+                }
+            }
+
+            return;
+        }
+
+        for i in 0..self.sprite_height {
+            let sy = y + i;
+            if sy >= HEIGHT {
+                break;
+            }
+
+            for j in 0..self.sprite_width {
+                let sx = x + j;
+                let index = m.read(c.i + i * self.sprite_width + j);
+
+                // Index 0 is transparent; dots past the right edge are clipped
+                if index == 0 || sx >= WIDTH {
+                    continue;
+                }
+
+                let offset = sy * WIDTH + sx;
+                let color = self.palette[index as usize];
+
+                c.v[0xF] |= (self.indices[offset] == self.collision_index) as u8;
+                self.plot(offset, index, color);
+            }
+        }
+    }
+}
+
+impl Runtime for MegaChip {
+    fn save_state(&self, w: &mut Writer) {
+        self.super_chip.save_state(w);
+
+        w.bool(self.enabled);
+        w.u32s(&self.screen);
+        w.u32s(&self.presented);
+        w.bytes(&self.indices);
+        w.u32s(&self.palette);
+        w.usize(self.sprite_width);
+        w.usize(self.sprite_height);
+        w.u8(self.alpha);
+        w.u8(match self.blend {
+            Blend::Normal => 0,
+            Blend::Alpha25 => 1,
+            Blend::Alpha50 => 2,
+            Blend::Add => 3,
+            Blend::Multiply => 4,
+        });
+        w.u8(self.collision_index);
+        w.bytes(&self.sound);
+        w.u32(self.sound_rate);
+        w.bool(self.sound_loop);
+        w.f32(self.sound_position);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error> {
+        self.super_chip.load_state(r)?;
+
+        self.enabled = r.bool()?;
+        self.screen = r.u32s()?;
+        self.presented = r.u32s()?;
+        self.indices = r.bytes()?.to_vec();
+        self.palette = r.u32s()?;
+        if self.screen.len() != WIDTH * HEIGHT || self.presented.len() != WIDTH * HEIGHT ||
+           self.indices.len() != WIDTH * HEIGHT || self.palette.len() != 256 {
+            return Err(Error::InvalidState);
+        }
+
+        self.sprite_width = r.usize()?;
+        self.sprite_height = r.usize()?;
+        self.alpha = r.u8()?;
+        self.blend = match r.u8()? {
+            0 => Blend::Normal,
+            1 => Blend::Alpha25,
+            2 => Blend::Alpha50,
+            3 => Blend::Add,
+            4 => Blend::Multiply,
+            _ => return Err(Error::InvalidState),
+        };
+        self.collision_index = r.u8()?;
+        self.sound = r.bytes()?.to_vec();
+        self.sound_rate = r.u32()?;
+        self.sound_loop = r.bool()?;
+        self.sound_position = r.f32()?;
+        if !(self.sound_position >= 0.0) {
+            return Err(Error::InvalidState);
+        }
+
+        Ok(())
+    }
+
+    fn configure(&mut self, c: &mut Context) {
+        // Configure SUPER-CHIP
+        self.super_chip.configure(c);
+    }
+
+    fn quirks(&self) -> Quirks {
+        self.super_chip.quirks()
+    }
+
+    fn reset(&mut self, c: &mut Context) {
+        // Reset SUPER-CHIP
+        self.super_chip.reset(c);
+
+        self.enabled = false;
+        self.clear();
+        self.presented.clone_from(&self.screen);
+
+        for color in &mut self.palette {
+            *color = 0;
+        }
+
+        self.sprite_width = 0;
+        self.sprite_height = 0;
+        self.alpha = 0xFF;
+        self.blend = Blend::Normal;
+        self.collision_index = 0;
+        self.sound.clear();
+        self.sound_position = 0.0;
+    }
+
+    fn memory_size(&self) -> usize {
+        // 16 MiB; all of which can be addressed by `01NN NNNN`
+        0x1000000
+    }
+
+    fn insert_rom(&mut self, m: &mut Mmu, buffer: &[u8]) {
+        self.super_chip.insert_rom(m, buffer);
+    }
+
+    fn update_framebuffer(&mut self, c: &mut Context) {
+        if !self.enabled {
+            return self.super_chip.update_framebuffer(c);
+        }
+
+        c.framebuffer.clone_from(&self.presented);
+    }
+
+    fn framebuffer_size(&self, c: &Context) -> (usize, usize) {
+        if !self.enabled {
+            return self.super_chip.framebuffer_size(c);
+        }
+
+        (WIDTH, HEIGHT)
+    }
+
+    fn render_audio(&mut self,
+                    c: &Context,
+                    position: &mut f32,
+                    count: usize,
+                    out: &mut Vec<i16>) {
+        if self.sound.is_empty() {
+            return audio::render(c, position, count, out);
+        }
+
+        // Digitized sound; resampled to the output rate
+        out.clear();
+
+        let step = self.sound_rate as f32 / audio::SAMPLE_RATE as f32;
+
+        for _ in 0..count {
+            let sample = if (self.sound_position as usize) < self.sound.len() {
+                (self.sound[self.sound_position as usize] as i16 - 0x80) * 0x100
+            } else {
+                0
+            };
+
+            self.sound_position += step;
+            if (self.sound_position as usize) >= self.sound.len() {
+                if self.sound_loop {
+                    self.sound_position = 0.0;
+                } else {
+                    self.sound.clear();
+                    self.sound_position = 0.0;
+
+                    break;
+                }
+            }
+
+            out.push(sample);
+            out.push(sample);
+        }
+
+        // Pad (the sound stopped during this frame)
+        out.resize(count * 2, 0);
+    }
+
+    fn execute(&mut self,
+               r: &mut axal::Runtime,
+               c: &mut Context,
+               m: &mut Mmu,
+               opcode: Opcode)
+               -> bool {
+        match opcode.unwrap() {
+            // MEGAOFF
+            (0x0, 0x0, 0x1, 0x0) => {
+                // Disable Mega-CHIP mode
+                self.enabled = false;
+                c.dirty = true;
+            }
+
+            // MEGAON
+            (0x0, 0x0, 0x1, 0x1) => {
+                // Enable Mega-CHIP mode (256x192)
+                self.enabled = true;
+                self.clear();
+                self.presented.clone_from(&self.screen);
+                c.dirty = true;
+            }
+
+            // SCUP (Mega-CHIP)
+            (0x0, 0x0, 0xB, n) if self.enabled => {
+                // Scroll screen N lines up
+                self.scroll(0, -(n as isize));
+            }
+
+            // SCDOWN (Mega-CHIP)
+            (0x0, 0x0, 0xC, n) if self.enabled => {
+                // Scroll screen N lines down
+                self.scroll(0, n as isize);
+            }
+
+            // CLS (Mega-CHIP)
+            (0x0, 0x0, 0xE, 0x0) if self.enabled => {
+                // Present the screen and then clear it
+                self.presented.clone_from(&self.screen);
+                self.clear();
+                c.dirty = true;
+            }
+
+            // SCRIGHT (Mega-CHIP)
+            (0x0, 0x0, 0xF, 0xB) if self.enabled => {
+                // Scroll screen 4 dots right
+                self.scroll(4, 0);
+            }
+
+            // SCLEFT (Mega-CHIP)
+            (0x0, 0x0, 0xF, 0xC) if self.enabled => {
+                // Scroll screen 4 dots left
+                self.scroll(-4, 0);
+            }
+
+            // LDHI I, u24
+            (0x0, 0x1, ..) => {
+                // Set I = u24; the low 16 bits are the word following the instruction
+                let lo = Opcode::read_next(&mut c.pc, m);
+
+                c.i = ((opcode.extract_u8() as usize) << 16) | (lo.extract_u16() as usize);
+            }
+
+            // LDPAL u8
+            (0x0, 0x2, ..) => {
+                // Load u8 colors (0xAARRGGBB) from [I] into the palette (from index 1)
+                for j in 0..(opcode.extract_u8() as usize) {
+                    let address = c.i + j * 4;
+                    let color = ((m.read(address) as u32) << 24) |
+                                ((m.read(address + 1) as u32) << 16) |
+                                ((m.read(address + 2) as u32) << 8) |
+                                (m.read(address + 3) as u32);
+
+                    self.palette[cmp::min(j + 1, 0xFF)] = color;
+                }
+            }
+
+            // SPRW u8
+            (0x0, 0x3, ..) => {
+                // Set the sprite width; 0 is 256
+                let width = opcode.extract_u8() as usize;
+                self.sprite_width = if width == 0 { 256 } else { width };
+            }
+
+            // SPRH u8
+            (0x0, 0x4, ..) => {
+                // Set the sprite height; 0 is 256
+                let height = opcode.extract_u8() as usize;
+                self.sprite_height = if height == 0 { 256 } else { height };
+            }
+
+            // ALPHA u8
+            (0x0, 0x5, ..) => {
+                // Set the sprite opacity
+                self.alpha = opcode.extract_u8();
+            }
+
+            // DIGISND u4
+            (0x0, 0x6, 0x0, n) => {
+                // Play the digitized sound at [I]; loop unless N is 1
+                //  [I] is the rate (16-bit), length (24-bit), and then (after a reserved
+                //  byte) the unsigned 8-bit samples
+                let rate = ((m.read(c.i) as u32) << 8) | (m.read(c.i + 1) as u32);
+                let length = ((m.read(c.i + 2) as usize) << 16) |
+                             ((m.read(c.i + 3) as usize) << 8) |
+                             (m.read(c.i + 4) as usize);
+
+                self.sound.clear();
+                for j in 0..length {
+                    self.sound.push(m.read(c.i + 6 + j));
+                }
+
+                self.sound_rate = rate;
+                self.sound_loop = n == 0;
+                self.sound_position = 0.0;
+            }
+
+            // STOPSND
+            (0x0, 0x7, 0x0, 0x0) => {
+                // Stop the digitized sound
+                self.sound.clear();
+                self.sound_position = 0.0;
+            }
+
+            // BMODE u4
+            (0x0, 0x8, 0x0, n) => {
+                // Set how sprites are blended with the screen
+                self.blend = match n {
+                    1 => Blend::Alpha25,
+                    2 => Blend::Alpha50,
+                    3 => Blend::Add,
+                    4 => Blend::Multiply,
+                    _ => Blend::Normal,
+                };
+            }
+
+            // CCOL u8
+            (0x0, 0x9, ..) => {
+                // Set the collision color (palette index)
+                self.collision_index = opcode.extract_u8();
+            }
+
+            // SHOW Vx, Vy, N (Mega-CHIP)
+            (0xD, x, y, n) if self.enabled => {
+                let x = c.v[x as usize] as usize;
+                let y = c.v[y as usize] as usize;
+
+                self.show(c, m, x, y, n);
+            }
+
+            _ => {
+                // Unhandled by Mega-CHIP
+                return self.super_chip.execute(r, c, m, opcode);
+            }
+        }
+
+        true
+    }
+}
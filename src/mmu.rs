@@ -1,7 +1,106 @@
+use std::cmp;
+use std::fmt;
 use std::vec::Vec;
 
+// Default size of RAM; 4 KiB (CHIP-8, SUPER-CHIP)
+pub const DEFAULT_SIZE: usize = 0x1000;
+
+// What is accessed for addresses past the end of RAM
+#[derive(Clone, Copy, PartialEq)]
+pub enum AddressPolicy {
+    // Wrap around to the start of RAM (as the hardware does)
+    Wrap,
+
+    // The last byte of RAM
+    Clamp,
+
+    // Wrap around but record the fault; the interpreter stops at the instruction
+    // that made the access (for finding bugs in ROMs)
+    Trap,
+
+    // Wrap around but record the fault; the interpreter reports the first one
+    // (eg. a sprite read through an I that was left pointing past the end of RAM)
+    // and keeps running
+    Warn,
+}
+
+impl Default for AddressPolicy {
+    fn default() -> Self {
+        AddressPolicy::Wrap
+    }
+}
+
+// Accesses of the program a watchpoint stops at
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    fn matches(&self, write: bool) -> bool {
+        match *self {
+            Watch::Read => !write,
+            Watch::Write => write,
+            Watch::ReadWrite => true,
+        }
+    }
+}
+
+// Access that hit a watchpoint: the instruction (at `pc`) that made it and the
+// value of the byte before and after (the same for a read)
+#[derive(Clone, Copy, Debug)]
+pub struct WatchHit {
+    pub pc: usize,
+    pub address: usize,
+    pub write: bool,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.write {
+            write!(f,
+                   "${:03X} written with ${:02X} (was ${:02X}) at ${:03X}",
+                   self.address,
+                   self.new,
+                   self.old,
+                   self.pc)
+        } else {
+            write!(f,
+                   "${:03X} read (${:02X}) at ${:03X}",
+                   self.address,
+                   self.new,
+                   self.pc)
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Mmu {
+    // RAM; the size is always a power of 2 so addresses can wrap around with a mask
     ram: Vec<u8>,
+
+    policy: AddressPolicy,
+
+    // First address past the end of RAM accessed since the last `take_fault` (`Trap`, `Warn`)
+    fault: Option<usize>,
+
+    // Writes below this address are dropped (0 if nothing is protected)
+    protected_end: usize,
+
+    // First protected address written since the last `take_write_fault`
+    write_fault: Option<usize>,
+
+    // Ranges of addresses (start, end) watched for accesses by the program, and
+    // the first access that hit one since the last `take_watch_hit`
+    watchpoints: Vec<(usize, usize, Watch)>,
+    watch_hit: Option<WatchHit>,
+
+    // Range of addresses (start, end) written since the last `take_changed`
+    changed: Option<(usize, usize)>,
 }
 
 impl Mmu {
@@ -9,38 +108,228 @@ impl Mmu {
         for b in &mut self.ram {
             *b = 0;
         }
+
+        self.mark_all_changed();
+    }
+
+    // Resize (and clear) RAM; `size` must be a power of 2
+    pub fn resize(&mut self, size: usize) {
+        debug_assert!(size.is_power_of_two());
+
+        self.ram.clear();
+        self.ram.resize(size, 0);
+        self.fault = None;
+        self.mark_all_changed();
+        self.protected_end = 0;
+        self.write_fault = None;
+    }
+
+    pub fn size(&self) -> usize {
+        self.ram.len()
+    }
+
+    // RAM as-is (without the address policy; for savestates)
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // Replace RAM (and its size; a power of 2)
+    pub fn load_ram(&mut self, ram: &[u8]) {
+        debug_assert!(ram.len().is_power_of_two());
+
+        self.ram.clear();
+        self.ram.extend_from_slice(ram);
+        self.fault = None;
+        self.write_fault = None;
+        self.mark_all_changed();
+    }
+
+    // RAM (to be written as-is; eg. restoring a state of the same size)
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        self.mark_all_changed();
+
+        &mut self.ram
+    }
+
+    // Take the range of addresses (start, end) written since the last call (if
+    // any); for debuggers to refresh their view of RAM
+    pub fn take_changed(&mut self) -> Option<(usize, usize)> {
+        self.changed.take()
+    }
+
+    // Add addresses `start` to `end` to the range written
+    pub fn mark_changed(&mut self, start: usize, end: usize) {
+        self.changed = Some(match self.changed {
+            Some((changed_start, changed_end)) => {
+                (cmp::min(changed_start, start), cmp::max(changed_end, end))
+            }
+
+            None => (start, end),
+        });
     }
 
-    fn extend(&mut self, address: usize) {
-        if address >= self.ram.len() {
-            self.ram.resize(address + 1, 0);
+    fn mark_all_changed(&mut self) {
+        let end = self.ram.len();
+        self.mark_changed(0, end);
+    }
+
+    pub fn policy(&self) -> AddressPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: AddressPolicy) {
+        self.policy = policy;
+    }
+
+    // Protect RAM below `end` from writes by the program (0 protects nothing)
+    pub fn set_protected(&mut self, end: usize) {
+        self.protected_end = end;
+    }
+
+    // Take the address of the faulting write to protected RAM (if any)
+    pub fn take_write_fault(&mut self) -> Option<usize> {
+        self.write_fault.take()
+    }
+
+    // Take the address of the faulting access (if any)
+    pub fn take_fault(&mut self) -> Option<usize> {
+        self.fault.take()
+    }
+
+    // Addresses past the end of RAM wrap around (or are clamped or trapped)
+    #[inline]
+    fn mask(&mut self, address: usize) -> usize {
+        if address < self.ram.len() {
+            return address;
+        }
+
+        match self.policy {
+            AddressPolicy::Wrap => {}
+            AddressPolicy::Clamp => return self.ram.len() - 1,
+            AddressPolicy::Trap | AddressPolicy::Warn => {
+                if self.fault.is_none() {
+                    self.fault = Some(address);
+                }
+            }
+        }
+
+        address & (self.ram.len() - 1)
+    }
+
+    // Stop at accesses of `watch` to `len` bytes from `address`
+    pub fn add_watchpoint(&mut self, address: usize, len: usize, watch: Watch) {
+        self.watchpoints.push((address, address + len, watch));
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize, len: usize) {
+        self.watchpoints.retain(|w| (w.0, w.1) != (address, address + len));
+    }
+
+    // Take the first access that hit a watchpoint (if any); `pc` is left 0
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    #[inline]
+    fn watch(&mut self, address: usize, write: bool, new: u8) {
+        if self.watchpoints.is_empty() || self.watch_hit.is_some() {
+            return;
+        }
+
+        let hit = self.watchpoints
+            .iter()
+            .any(|w| address >= w.0 && address < w.1 && w.2.matches(write));
+
+        if hit {
+            self.watch_hit = Some(WatchHit {
+                pc: 0,
+                address: address,
+                write: write,
+                old: self.ram[address],
+                new: new,
+            });
         }
     }
 
     pub fn read(&mut self, address: usize) -> u8 {
-        self.extend(address);
+        let address = self.mask(address);
+        let value = self.ram[address];
+        self.watch(address, false, value);
+
+        value
+    }
 
-        self.ram[address as usize]
+    // Read an instruction (not watched as a read)
+    pub fn fetch(&mut self, address: usize) -> u8 {
+        let address = self.mask(address);
+
+        self.ram[address]
     }
 
     pub fn write(&mut self, address: usize, value: u8) {
-        self.extend(address);
+        let address = self.mask(address);
+
+        if address < self.protected_end {
+            if self.write_fault.is_none() {
+                self.write_fault = Some(address);
+            }
+
+            return;
+        }
 
-        self.ram[address as usize] = value;
+        self.watch(address, true, value);
+        self.ram[address] = value;
+        self.mark_changed(address, address + 1);
     }
 
-    #[allow(needless_range_loop, unknown_lints)]
-    pub fn write_all(&mut self, address: usize, buffer: &[u8]) {
-        self.extend(address + buffer.len());
+    // Write regardless of protection (for the interpreter's own use of RAM; eg. the stack)
+    pub fn write_privileged(&mut self, address: usize, value: u8) {
+        let address = self.mask(address);
 
-        for i in 0..(buffer.len()) {
-            self.ram[address + i] = buffer[i];
+        self.watch(address, true, value);
+        self.ram[address] = value;
+        self.mark_changed(address, address + 1);
+    }
+
+    // Read / write as a debugger does: addresses wrap around (whatever the policy)
+    // and nothing is protected or faults
+    pub fn peek(&self, address: usize) -> u8 {
+        self.ram[address & (self.ram.len() - 1)]
+    }
+
+    pub fn poke(&mut self, address: usize, value: u8) {
+        let address = address & (self.ram.len() - 1);
+
+        self.ram[address] = value;
+        self.mark_changed(address, address + 1);
+    }
+
+    // RAM from `address` (up to `len` bytes; not past the end of RAM)
+    pub fn peek_range(&self, address: usize, len: usize) -> &[u8] {
+        let start = cmp::min(address, self.ram.len());
+        let end = cmp::min(start.saturating_add(len), self.ram.len());
+
+        &self.ram[start..end]
+    }
+
+    pub fn write_all(&mut self, address: usize, buffer: &[u8]) {
+        for (i, value) in buffer.iter().enumerate() {
+            self.write(address + i, *value);
         }
     }
 }
 
 impl Default for Mmu {
     fn default() -> Self {
-        Mmu { ram: Default::default() }
+        Mmu {
+            ram: vec![0; DEFAULT_SIZE],
+            policy: Default::default(),
+            fault: None,
+            protected_end: 0,
+            write_fault: None,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            changed: None,
+        }
     }
 }
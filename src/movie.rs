@@ -0,0 +1,370 @@
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::vec::Vec;
+
+use error::Error;
+use interpreter::{Input, Mode};
+use quirks::Quirks;
+use savestate::{self, Reader, Writer};
+use storage;
+
+// Movie format
+//  `MAGIC` and the version of the format, followed by tagged sections (as in
+//  savestates; compressed as they are mostly the same keys frame after frame):
+//   HEAD  hash of the ROM, variant, quirks, the seed set (if any), the
+//         number of re-records and the frames between hashes
+//   STAT  savestate the movie starts from
+//   INPT  keys down on each keypad for each frame (keypad 2 in the high 16 bits)
+//   HASH  hash of the state at the start of every `HASH_INTERVAL`th frame (see
+//         `check_hash`); a movie without it is played back without checks
+//  Version 2 changed how the state is hashed; the hashes of a version 1 movie
+//  are dropped as it is read (it is played back without checks). Version 3
+//  hashes every few frames; version 2 hashed every frame.
+const MAGIC: &'static [u8; 4] = b"xCHM";
+
+pub const VERSION: u16 = 3;
+
+// Frames between the hashes of a movie recorded (a second at 60 Hz); hashing the
+// state reads all of RAM (16 MiB for MEGA-CHIP)
+const HASH_INTERVAL: u32 = 60;
+
+// Recording of the keys down in each frame from a savestate; running the frames
+// from the state with the same keys runs them the same
+#[derive(Clone)]
+pub struct Movie {
+    rom_hash: u64,
+    variant: Mode,
+    quirks: Quirks,
+    seed: Option<u64>,
+
+    // Times a state was loaded while recording (and the frames after it recorded again)
+    rerecords: u32,
+
+    // Frames between hashes (the frames from the first on that are a multiple of it)
+    hash_interval: u32,
+
+    state: Vec<u8>,
+    frames: Vec<Input>,
+    hashes: Vec<u32>,
+}
+
+// Hash of the state (see `Interpreter::state_hash`) kept for each frame; half
+// the size (a mismatch is as likely to be caught)
+pub fn check_hash(state_hash: u64) -> u32 {
+    (state_hash ^ (state_hash >> 32)) as u32
+}
+
+impl Movie {
+    // Empty movie starting from `state` (a savestate of the ROM identified by `rom_hash`)
+    pub fn new(rom_hash: u64,
+               variant: Mode,
+               quirks: Quirks,
+               seed: Option<u64>,
+               state: Vec<u8>)
+               -> Self {
+        Movie {
+            rom_hash: rom_hash,
+            variant: variant,
+            quirks: quirks,
+            seed: seed,
+            rerecords: 0,
+            hash_interval: HASH_INTERVAL,
+            state: state,
+            frames: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    // Add a frame (run with `input`); `state_hash` is called for the hash of the
+    // state the frame starts from if the frame is checked (see `is_checked`)
+    pub fn push<F: FnOnce() -> u64>(&mut self, input: Input, state_hash: F) {
+        if self.is_checked(self.frames.len()) {
+            self.hashes.push(check_hash(state_hash()));
+        }
+
+        self.frames.push(input);
+    }
+
+    // Hash of the ROM the movie was recorded with (see `hash::fnv1a`)
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn variant(&self) -> Mode {
+        self.variant
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // Seed of `CXNN` (RND) set when recording started; `None` if seeded from
+    // entropy (the state of the generator is in the starting state regardless)
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn rerecords(&self) -> u32 {
+        self.rerecords
+    }
+
+    // Drop the frames from `frame` on to record them again
+    pub fn rerecord(&mut self, frame: usize) {
+        let interval = self.hash_interval as usize;
+
+        self.frames.truncate(frame);
+        self.hashes.truncate((frame + interval - 1) / interval);
+        self.rerecords += 1;
+    }
+
+    // Savestate the movie starts from
+    pub fn state(&self) -> &[u8] {
+        &self.state
+    }
+
+    // Number of frames recorded
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Keys down in `frame`; `None` past the end
+    pub fn input(&self, frame: usize) -> Option<Input> {
+        self.frames.get(frame).cloned()
+    }
+
+    // True if the state `frame` starts from is hashed (every few frames); the
+    // state need not be hashed for the others
+    pub fn is_checked(&self, frame: usize) -> bool {
+        frame % self.hash_interval as usize == 0
+    }
+
+    // True if the state with `state_hash` is that of the start of `frame` as
+    // recorded (or there is no hash to check against)
+    pub fn verify(&self, frame: usize, state_hash: u64) -> bool {
+        if !self.is_checked(frame) {
+            return true;
+        }
+
+        let index = frame / self.hash_interval as usize;
+        self.hashes.get(index).map_or(true, |hash| *hash == check_hash(state_hash))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut body: Writer = Default::default();
+        body.section(b"HEAD", |w| {
+            w.u64(self.rom_hash);
+            w.str(self.variant.name());
+            self.quirks.save(w);
+            w.bool(self.seed.is_some());
+            w.u64(self.seed.unwrap_or(0));
+            w.u32(self.rerecords);
+            w.u32(self.hash_interval);
+        });
+
+        body.section(b"STAT", |w| w.bytes(&self.state));
+        body.section(b"INPT", |w| {
+            let frames: Vec<u32> = self.frames
+                .iter()
+                .map(|input| (input[0] as u32) | ((input[1] as u32) << 16))
+                .collect();
+
+            w.u32s(&frames);
+        });
+
+        body.section(b"HASH", |w| w.u32s(&self.hashes));
+
+        let mut w: Writer = Default::default();
+        w.tag(MAGIC);
+        w.u16(VERSION);
+
+        let mut buffer = w.into_bytes();
+        savestate::compress(&body.into_bytes(), &mut buffer);
+
+        buffer
+    }
+
+    pub fn deserialize(buffer: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader::new(buffer);
+        if r.tag().ok() != Some(*MAGIC) {
+            return Err(Error::InvalidMovie);
+        }
+
+        let version = r.u16().map_err(|_| Error::InvalidMovie)?;
+        if version > VERSION {
+            return Err(Error::UnsupportedMovie { version: version });
+        }
+
+        savestate::decompress(r.remaining())
+            .and_then(|body| Movie::read(&mut Reader::new(&body), version))
+            .map_err(|_| Error::InvalidMovie)
+    }
+
+    fn read(r: &mut Reader, version: u16) -> Result<Self, Error> {
+        let sections = r.sections()?;
+
+        let mut r = sections.get(b"HEAD")?;
+        let rom_hash = r.u64()?;
+        let variant = Mode::from_name(r.str()?).ok_or(Error::InvalidState)?;
+        let quirks = Quirks::load(&mut r)?;
+        let seed_set = r.bool()?;
+        let seed = r.u64()?;
+        let seed = if seed_set { Some(seed) } else { None };
+        let rerecords = if r.is_empty() { 0 } else { r.u32()? };
+        let hash_interval = if version >= 3 { cmp::max(r.u32()?, 1) } else { 1 };
+
+        let state = sections.get(b"STAT")?.bytes()?.to_vec();
+        let frames = sections.get(b"INPT")?
+            .u32s()?
+            .iter()
+            .map(|frame| [*frame as u16, (*frame >> 16) as u16])
+            .collect();
+
+        let hashes = match sections.find(b"HASH") {
+            Some(mut r) if version >= 2 => r.u32s()?,
+            _ => Vec::new(),
+        };
+
+        Ok(Movie {
+            rom_hash: rom_hash,
+            variant: variant,
+            quirks: quirks,
+            seed: seed,
+            rerecords: rerecords,
+            hash_interval: hash_interval,
+            state: state,
+            frames: frames,
+            hashes: hashes,
+        })
+    }
+
+    // Write the movie to `path` (creating any missing parent directories)
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        storage::write(path, &self.serialize())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let mut buffer = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut buffer)).map_err(Error::StateIo)?;
+
+        Movie::deserialize(&buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie(frames: usize) -> Movie {
+        let mut movie = Movie::new(0x1234, Mode::SuperChip, Quirks::xo_chip(), Some(7), vec![1, 2, 3]);
+        for frame in 0..frames {
+            movie.push([frame as u16, 0x8000], || frame as u64);
+        }
+
+        movie
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut movie = movie(130);
+        movie.rerecord(125);
+
+        let read = Movie::deserialize(&movie.serialize()).unwrap();
+        assert_eq!(read.rom_hash(), 0x1234);
+        assert!(read.variant() == Mode::SuperChip);
+        assert!(read.quirks() == Quirks::xo_chip());
+        assert_eq!(read.seed(), Some(7));
+        assert_eq!(read.rerecords(), 1);
+        assert_eq!(read.state(), &[1, 2, 3]);
+        assert_eq!(read.len(), 125);
+        assert_eq!(read.input(124), Some([124, 0x8000]));
+        assert_eq!(read.input(125), None);
+        assert!(read.verify(120, 120));
+        assert!(!read.verify(120, 121));
+    }
+
+    #[test]
+    fn hash_every_interval() {
+        let movie = movie(HASH_INTERVAL as usize * 2 + 1);
+        let interval = HASH_INTERVAL as usize;
+
+        assert_eq!(movie.hashes.len(), 3);
+        assert!(movie.is_checked(0) && movie.is_checked(interval));
+        assert!(!movie.is_checked(1));
+
+        // Frames between are not checked (whatever the state)
+        assert!(movie.verify(1, 0xDEAD));
+        assert!(!movie.verify(interval, 0xDEAD));
+        assert!(movie.verify(interval * 2, (interval * 2) as u64));
+
+        // Past the end there is nothing to check against
+        assert!(movie.verify(interval * 3, 0xDEAD));
+    }
+
+    #[test]
+    fn rerecord_drops_hashes() {
+        let interval = HASH_INTERVAL as usize;
+        let mut movie = movie(interval * 2 + 1);
+
+        movie.rerecord(interval + 1);
+        assert_eq!(movie.hashes.len(), 2);
+
+        movie.rerecord(interval);
+        assert_eq!(movie.hashes.len(), 1);
+
+        movie.push([0, 0], || 0xBEEF);
+        assert!(movie.verify(interval, 0xBEEF));
+    }
+
+    #[test]
+    fn read_version_2() {
+        // Hashed every frame, with no interval in HEAD
+        let mut body: Writer = Default::default();
+        body.section(b"HEAD", |w| {
+            w.u64(0x1234);
+            w.str("chip-8");
+            Quirks::xo_chip().save(w);
+            w.bool(false);
+            w.u64(0);
+            w.u32(3);
+        });
+
+        body.section(b"STAT", |w| w.bytes(&[]));
+        body.section(b"INPT", |w| w.u32s(&[1, 2, 3]));
+        body.section(b"HASH", |w| w.u32s(&[check_hash(10), check_hash(11), check_hash(12)]));
+
+        let mut w: Writer = Default::default();
+        w.tag(MAGIC);
+        w.u16(2);
+
+        let mut buffer = w.into_bytes();
+        savestate::compress(&body.into_bytes(), &mut buffer);
+
+        let movie = Movie::deserialize(&buffer).unwrap();
+        assert_eq!(movie.len(), 3);
+        assert_eq!(movie.rerecords(), 3);
+        assert!(movie.is_checked(1));
+        assert!(movie.verify(1, 11));
+        assert!(!movie.verify(2, 11));
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert!(Movie::deserialize(b"xCHP\0\x02").is_err());
+        assert!(Movie::deserialize(b"xCHM\0\x02").is_err());
+
+        let mut w: Writer = Default::default();
+        w.tag(MAGIC);
+        w.u16(VERSION + 1);
+
+        match Movie::deserialize(&w.into_bytes()) {
+            Err(Error::UnsupportedMovie { version }) => assert_eq!(version, VERSION + 1),
+            _ => panic!("a newer movie was read"),
+        }
+    }
+}
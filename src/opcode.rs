@@ -12,8 +12,8 @@ impl Opcode {
     #[inline]
     pub fn read_next(pc: &mut usize, m: &mut Mmu) -> Self {
         let r = Opcode {
-            hi: m.read(*pc),
-            lo: m.read(*pc + 1),
+            hi: m.fetch(*pc),
+            lo: m.fetch(*pc + 1),
         };
 
         *pc += 2;
@@ -26,6 +26,13 @@ impl Opcode {
         ((self.hi >> 4), (self.hi & 0xF), (self.lo >> 4), (self.lo & 0xF))
     }
 
+    // Extract all 16-bits
+    //  XO-CHIP uses this for the address that follows `F000`.
+    #[inline]
+    pub fn extract_u16(&self) -> u16 {
+        (self.lo as u16) | ((self.hi as u16) << 8)
+    }
+
     // Extract the lower 12-bits
     #[inline]
     pub fn extract_u12(&self) -> u16 {
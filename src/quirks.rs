@@ -0,0 +1,246 @@
+// Behaviors that differ between interpreters for the same instructions
+//  The runtime provides the quirks of its variant; these can be overridden
+//  (see `Core::set_quirks`) for ROMs that were written against another one.
+
+use error::Error;
+use savestate::{Reader, Writer};
+
+// How `FX55` / `FX65` leave I after storing / loading V0 through Vx
+#[derive(Clone, Copy, PartialEq)]
+pub enum LoadStoreIncrement {
+    // I += X + 1 (CHIP-8, XO-CHIP)
+    XPlusOne,
+
+    // I += X (CHIP-48, SUPER-CHIP 1.0)
+    X,
+
+    // I is unchanged (SUPER-CHIP 1.1)
+    None,
+}
+
+impl LoadStoreIncrement {
+    // Amount I is incremented by after `FX55` / `FX65`
+    pub fn amount(&self, x: usize) -> usize {
+        match *self {
+            LoadStoreIncrement::XPlusOne => x + 1,
+            LoadStoreIncrement::X => x,
+            LoadStoreIncrement::None => 0,
+        }
+    }
+}
+
+// What `DXY0` draws in standard (lores) display mode
+#[derive(Clone, Copy, PartialEq)]
+pub enum LoresDxy0 {
+    // Nothing (a 0 row sprite; CHIP-8)
+    None,
+
+    // An 8x16 sprite (SUPER-CHIP)
+    Tall,
+
+    // A 16x16 sprite; as in extended display mode (XO-CHIP)
+    Big,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Quirks {
+    // `8XY6` / `8XYE` shift VY into VX (CHIP-8, XO-CHIP) rather than shifting VX in place
+    pub shift_vy: bool,
+
+    pub load_store_increment: LoadStoreIncrement,
+
+    // `BNNN` jumps to XNN + VX (CHIP-48, SUPER-CHIP) rather than NNN + V0; the high
+    // nibble of the address doubles as the register
+    pub jump_vx: bool,
+
+    // `8XY1` / `8XY2` / `8XY3` reset VF to 0 (CHIP-8 on the COSMAC VIP)
+    pub vf_reset: bool,
+
+    // `DXYN` drops the parts of sprites past the edges of the screen (CHIP-8, SUPER-CHIP)
+    // rather than wrapping them around to the opposite edge (XO-CHIP)
+    //  The coordinates sprites are drawn at always wrap.
+    pub clip_sprites: bool,
+
+    // `DXYN` waits for the vertical blank (CHIP-8 on the COSMAC VIP); no further
+    // instructions are run in the frame once a sprite is to be drawn and it is
+    // drawn at the start of the next
+    pub display_wait: bool,
+
+    pub lores_dxy0: LoresDxy0,
+
+    // `FX0A` registers a key when it is released (CHIP-8 on the COSMAC VIP, XO-CHIP)
+    // rather than when it is pressed (CHIP-48, SUPER-CHIP)
+    pub key_release: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::vip()
+    }
+}
+
+impl Quirks {
+    // The original CHIP-8 (COSMAC VIP)
+    pub fn vip() -> Self {
+        Quirks {
+            shift_vy: true,
+            load_store_increment: LoadStoreIncrement::XPlusOne,
+            jump_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+            lores_dxy0: LoresDxy0::None,
+            key_release: true,
+        }
+    }
+
+    // CHIP-48 (HP48)
+    //  `8XY6` / `8XYE` shift Vx in place (Vy is ignored), `FX55` / `FX65` leave I
+    //  incremented by X, `BXNN` jumps to XNN + Vx, and VF is left as-is by
+    //  `8XY1` / `8XY2` / `8XY3`.
+    pub fn chip_48() -> Self {
+        Quirks {
+            shift_vy: false,
+            load_store_increment: LoadStoreIncrement::X,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            lores_dxy0: LoresDxy0::None,
+            key_release: false,
+        }
+    }
+
+    // SUPER-CHIP 1.0; the quirks of CHIP-48 and `DXY0` draws an 8x16 sprite in
+    // standard display mode
+    pub fn super_chip_1_0() -> Self {
+        Quirks { lores_dxy0: LoresDxy0::Tall, ..Quirks::chip_48() }
+    }
+
+    // SUPER-CHIP 1.1; as 1.0 except that `FX55` / `FX65` leave I unchanged
+    pub fn super_chip_1_1() -> Self {
+        Quirks { load_store_increment: LoadStoreIncrement::None, ..Quirks::super_chip_1_0() }
+    }
+
+    // XO-CHIP (as run by Octo)
+    //  `8XY6` / `8XYE` shift Vy and `FX55` / `FX65` leave I incremented by X + 1
+    //  (the CHIP-8 behavior, which SUPER-CHIP did not keep); VF is left as-is by
+    //  `8XY1` / `8XY2` / `8XY3`, sprites wrap around the edges of the screen, and
+    //  `DXY0` draws a 16x16 sprite in either display mode.
+    pub fn xo_chip() -> Self {
+        Quirks {
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            lores_dxy0: LoresDxy0::Big,
+            ..Quirks::vip()
+        }
+    }
+
+    // Set a quirk by name (as the field) from an option value: `on` / `off`, or for
+    // `load_store_increment`, `x+1` / `x` / `none`, and for `lores_dxy0`, `none` /
+    // `8x16` / `16x16`; false if either is unknown
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        if name == "lores_dxy0" {
+            self.lores_dxy0 = match value {
+                "none" => LoresDxy0::None,
+                "8x16" => LoresDxy0::Tall,
+                "16x16" => LoresDxy0::Big,
+
+                _ => return false,
+            };
+
+            return true;
+        }
+
+        if name == "load_store_increment" {
+            self.load_store_increment = match value {
+                "x+1" => LoadStoreIncrement::XPlusOne,
+                "x" => LoadStoreIncrement::X,
+                "none" => LoadStoreIncrement::None,
+
+                _ => return false,
+            };
+
+            return true;
+        }
+
+        let value = match value {
+            "on" => true,
+            "off" => false,
+
+            _ => return false,
+        };
+
+        *match name {
+            "shift_vy" => &mut self.shift_vy,
+            "jump_vx" => &mut self.jump_vx,
+            "vf_reset" => &mut self.vf_reset,
+            "clip_sprites" => &mut self.clip_sprites,
+            "display_wait" => &mut self.display_wait,
+            "key_release" => &mut self.key_release,
+
+            _ => return false,
+        } = value;
+
+        true
+    }
+
+    // Preset by name: `vip`, `chip48`, `schip-1.0`, `schip-1.1`, or `xo-chip`
+    pub fn preset(name: &str) -> Option<Self> {
+        Some(match &*name.to_lowercase() {
+            "vip" | "chip-8" => Quirks::vip(),
+            "chip48" | "chip-48" => Quirks::chip_48(),
+            "schip-1.0" => Quirks::super_chip_1_0(),
+            "schip-1.1" | "schip" => Quirks::super_chip_1_1(),
+            "xo-chip" | "octo" => Quirks::xo_chip(),
+
+            _ => return None,
+        })
+    }
+}
+
+// Savestates
+impl Quirks {
+    pub fn save(&self, w: &mut Writer) {
+        w.bool(self.shift_vy);
+        w.u8(match self.load_store_increment {
+            LoadStoreIncrement::XPlusOne => 0,
+            LoadStoreIncrement::X => 1,
+            LoadStoreIncrement::None => 2,
+        });
+        w.bool(self.jump_vx);
+        w.bool(self.vf_reset);
+        w.bool(self.clip_sprites);
+        w.bool(self.display_wait);
+        w.u8(match self.lores_dxy0 {
+            LoresDxy0::None => 0,
+            LoresDxy0::Tall => 1,
+            LoresDxy0::Big => 2,
+        });
+        w.bool(self.key_release);
+    }
+
+    pub fn load(r: &mut Reader) -> Result<Self, Error> {
+        Ok(Quirks {
+            shift_vy: r.bool()?,
+            load_store_increment: match r.u8()? {
+                0 => LoadStoreIncrement::XPlusOne,
+                1 => LoadStoreIncrement::X,
+                2 => LoadStoreIncrement::None,
+                _ => return Err(Error::InvalidState),
+            },
+            jump_vx: r.bool()?,
+            vf_reset: r.bool()?,
+            clip_sprites: r.bool()?,
+            display_wait: r.bool()?,
+            lores_dxy0: match r.u8()? {
+                0 => LoresDxy0::None,
+                1 => LoresDxy0::Tall,
+                2 => LoresDxy0::Big,
+                _ => return Err(Error::InvalidState),
+            },
+            key_release: r.bool()?,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+// Savestates of the recent past to rewind to
+//  A state is kept every `interval` frames; once there are `length` the oldest
+//  is dropped. States are compressed (see `savestate::compress`) as they are
+//  mostly the same RAM.
+#[derive(Default)]
+pub struct Rewind {
+    length: usize,
+    interval: usize,
+
+    // Frames run since the buffer was cleared (as rewound)
+    frame: u64,
+
+    // States by the frame they were saved at (oldest first)
+    states: VecDeque<(u64, Vec<u8>)>,
+}
+
+impl Rewind {
+    // Keep `length` states, one every `interval` frames; a `length` of 0 disables
+    // rewind (and drops the states kept)
+    pub fn configure(&mut self, length: usize, interval: usize) {
+        self.length = length;
+        self.interval = if interval == 0 { 1 } else { interval };
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.frame = 0;
+        self.states.clear();
+    }
+
+    // Count a frame as run; `state` is asked for a savestate when one is due
+    pub fn record<F: FnOnce() -> Option<Vec<u8>>>(&mut self, state: F) {
+        if self.length == 0 {
+            return;
+        }
+
+        if self.frame % self.interval as u64 == 0 {
+            if let Some(state) = state() {
+                if self.states.len() == self.length {
+                    self.states.pop_front();
+                }
+
+                self.states.push_back((self.frame, state));
+            }
+        }
+
+        self.frame += 1;
+    }
+
+    // Savestate of (at least) `frames` frames ago, or of as far back as is kept;
+    // returns the number of frames it is back
+    //  The state is kept (to rewind further or again); those after it are dropped.
+    pub fn rewind(&mut self, frames: usize) -> Option<(usize, &[u8])> {
+        let target = self.frame.saturating_sub(frames as u64);
+        while self.states.len() > 1 && self.states.back().map_or(false, |s| s.0 > target) {
+            self.states.pop_back();
+        }
+
+        let frame = self.frame;
+        let state = self.states.back()?;
+        self.frame = state.0;
+
+        Some(((frame - state.0) as usize, &state.1))
+    }
+}
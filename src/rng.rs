@@ -0,0 +1,46 @@
+use rand;
+
+// xorshift64* (Vigna)
+//  Used for `CXNN` (RND); owned by the interpreter so that a run can be
+//  reproduced from its seed (replays, netplay). Not cryptographic.
+#[derive(Clone, Copy)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero; spread the seed (splitmix64) so that
+        // close seeds (0, 1, ..) give unrelated sequences
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+
+        Rng { state: if z == 0 { 1 } else { z } }
+    }
+
+    // State of the generator (for savestates); `from_state` continues the sequence
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn from_state(state: u64) -> Self {
+        Rng { state: if state == 0 { 1 } else { state } }
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+    }
+}
+
+impl Default for Rng {
+    // Seeded from entropy
+    fn default() -> Self {
+        Rng::new(rand::random())
+    }
+}
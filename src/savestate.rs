@@ -0,0 +1,487 @@
+use std::str;
+use std::vec::Vec;
+
+use error::Error;
+
+// Savestate format
+//  A savestate starts with `MAGIC`, the version of the format and flags (from
+//  version 2), followed by tagged sections (a 4 byte tag and the length of the
+//  contents) that each hold the state of one part of the interpreter. Sections
+//  that are not known are skipped; the fields of a section are read in order and
+//  all must be there. So states of older releases keep loading, any change to the
+//  fields of a section (including adding one) raises the version, with a step in
+//  `upgrade` that rewrites states of the version before.
+//  With `FLAG_COMPRESSED`, everything after the header is compressed (see
+//  `compress`).
+const MAGIC: &'static [u8; 4] = b"xCHP";
+
+pub const VERSION: u16 = 2;
+
+// Oldest version that can be upgraded
+const OLDEST_VERSION: u16 = 1;
+
+const FLAG_COMPRESSED: u8 = 1;
+
+// Make a savestate of the sections in `body` (optionally compressed)
+pub fn encode(body: &[u8], compressed: bool) -> Vec<u8> {
+    let mut w: Writer = Default::default();
+    w.tag(MAGIC);
+    w.u16(VERSION);
+
+    if compressed {
+        w.u8(FLAG_COMPRESSED);
+        compress(body, &mut w.buffer);
+    } else {
+        w.u8(0);
+        w.buffer.extend_from_slice(body);
+    }
+
+    w.into_bytes()
+}
+
+// Sections of the savestate `state` (decompressed)
+pub fn decode(state: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut r = Reader::new(state);
+    if r.tag()? != *MAGIC {
+        return Err(Error::InvalidState);
+    }
+
+    let version = r.u16()?;
+    if version > VERSION {
+        return Err(Error::UnsupportedState { version: version });
+    } else if version < OLDEST_VERSION {
+        return Err(Error::IncompatibleState { version: version });
+    }
+
+    // Version 1 has no flags
+    let flags = if version >= 2 { r.u8()? } else { 0 };
+    let body = if flags & FLAG_COMPRESSED != 0 {
+        decompress(r.remaining())?
+    } else {
+        r.remaining().to_vec()
+    };
+
+    upgrade(version, body)
+}
+
+// Bring the sections of a state of `version` to the layout of `VERSION`, a
+// version at a time
+//  Every version from `OLDEST_VERSION` must have an upgrade to the next; when
+//  one can't be written (the state lacks what is needed) `OLDEST_VERSION` is
+//  raised instead, and such states are rejected as incompatible.
+fn upgrade(version: u16, body: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let mut body = body;
+    for from in version..VERSION {
+        body = match from {
+            // Flags were added to the header (compression); the sections are as they were
+            1 => body,
+
+            _ => return Err(Error::IncompatibleState { version: version }),
+        };
+    }
+
+    Ok(body)
+}
+
+// Sync states (see `Interpreter::save_sync_state`)
+//  `SYNC_MAGIC` and `SYNC_VERSION` followed by the state (compressed). The
+//  layout is fixed; a sync state is only read by the release that wrote it.
+const SYNC_MAGIC: &'static [u8; 4] = b"xCHS";
+
+pub const SYNC_VERSION: u16 = 1;
+
+pub fn encode_sync(body: &[u8]) -> Vec<u8> {
+    let mut w: Writer = Default::default();
+    w.tag(SYNC_MAGIC);
+    w.u16(SYNC_VERSION);
+
+    let mut buffer = w.into_bytes();
+    compress(body, &mut buffer);
+
+    buffer
+}
+
+pub fn decode_sync(state: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut r = Reader::new(state);
+    if r.tag()? != *SYNC_MAGIC {
+        return Err(Error::InvalidState);
+    }
+
+    let version = r.u16()?;
+    if version > SYNC_VERSION {
+        return Err(Error::UnsupportedState { version: version });
+    } else if version < SYNC_VERSION {
+        return Err(Error::IncompatibleState { version: version });
+    }
+
+    decompress(r.remaining())
+}
+
+// Read the section `tag` of the savestate `state`; `None` if it has none (or
+// the state or section is invalid)
+pub fn read_section<T, F>(state: &[u8], tag: &[u8; 4], read: F) -> Option<T>
+    where F: FnOnce(&mut Reader) -> Result<T, Error>
+{
+    let body = decode(state).ok()?;
+    let sections = Reader::new(&body).sections().ok()?;
+
+    read(&mut sections.find(tag)?).ok()
+}
+
+// Compress `data` into `out` by runs (PackBits)
+//  Savestates are mostly runs of zeros (RAM, the screen); a control byte below
+//  0x80 is followed by that many + 1 bytes as-is, and one of 0x80 or above by a
+//  byte that is repeated that many - 0x80 + 3 times.
+pub fn compress(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    let mut literal = 0;
+
+    while i < data.len() {
+        let mut run = 1;
+        while i + run < data.len() && data[i + run] == data[i] && run < 130 {
+            run += 1;
+        }
+
+        if run >= 3 {
+            flush_literal(&data[literal..i], out);
+            out.push((0x80 + run - 3) as u8);
+            out.push(data[i]);
+
+            i += run;
+            literal = i;
+        } else {
+            i += run;
+        }
+    }
+
+    flush_literal(&data[literal..], out);
+}
+
+fn flush_literal(bytes: &[u8], out: &mut Vec<u8>) {
+    for chunk in bytes.chunks(0x80) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut r = Reader::new(data);
+    let mut out = Vec::with_capacity(data.len() * 4);
+
+    while !r.is_empty() {
+        let control = r.u8()? as usize;
+        if control < 0x80 {
+            out.extend_from_slice(r.take(control + 1)?);
+        } else {
+            let value = r.u8()?;
+            for _ in 0..(control - 0x80 + 3) {
+                out.push(value);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+// Encoder of savestates
+//  Values are written in order (big-endian; as opcodes are) and must be read
+//  back in the same order by `Reader`; sizes are written as 32-bit.
+#[derive(Default)]
+pub struct Writer {
+    buffer: Vec<u8>,
+}
+
+impl Writer {
+    // Write a section with the contents written by `contents`
+    pub fn section<F: FnOnce(&mut Writer)>(&mut self, tag: &[u8; 4], contents: F) {
+        let mut w: Writer = Default::default();
+        contents(&mut w);
+
+        self.tag(tag);
+        self.bytes(&w.buffer);
+    }
+
+    // Write a tag (or magic); 4 bytes as-is
+    pub fn tag(&mut self, tag: &[u8; 4]) {
+        self.buffer.extend_from_slice(tag);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.u8((value >> 8) as u8);
+        self.u8(value as u8);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.u16((value >> 16) as u16);
+        self.u16(value as u16);
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.u32((value >> 32) as u32);
+        self.u32(value as u32);
+    }
+
+    pub fn usize(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.usize(bytes.len());
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn u32s(&mut self, values: &[u32]) {
+        self.usize(values.len());
+        for value in values {
+            self.u32(*value);
+        }
+    }
+
+    pub fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+// Decoder of savestates written by `Writer`
+//  Reading past the end (or anything else that was not written) is an
+//  `Error::InvalidState`.
+pub struct Reader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Reader { buffer: buffer }
+    }
+
+    // Read the (remaining) sections
+    pub fn sections(&mut self) -> Result<Sections<'a>, Error> {
+        let mut sections = Vec::new();
+        while !self.is_empty() {
+            let tag = self.tag()?;
+            let contents = self.bytes()?;
+
+            sections.push((tag, contents));
+        }
+
+        Ok(Sections { sections: sections })
+    }
+
+    // True if everything was read
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    // What is left to read
+    pub fn remaining(&self) -> &'a [u8] {
+        self.buffer
+    }
+
+    pub fn tag(&mut self) -> Result<[u8; 4], Error> {
+        let tag = self.take(4)?;
+
+        Ok([tag[0], tag[1], tag[2], tag[3]])
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.buffer.len() < len {
+            return Err(Error::InvalidState);
+        }
+
+        let (bytes, rest) = self.buffer.split_at(len);
+        self.buffer = rest;
+
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        Ok(((self.u8()? as u16) << 8) | (self.u8()? as u16))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(((self.u16()? as u32) << 16) | (self.u16()? as u32))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(((self.u32()? as u64) << 32) | (self.u32()? as u64))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, Error> {
+        Ok(self.u32()? as usize)
+    }
+
+    pub fn f32(&mut self) -> Result<f32, Error> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.usize()?;
+
+        self.take(len)
+    }
+
+    // Read exactly `out.len()` bytes (written by `bytes`) into `out`
+    pub fn bytes_into(&mut self, out: &mut [u8]) -> Result<(), Error> {
+        let bytes = self.bytes()?;
+        if bytes.len() != out.len() {
+            return Err(Error::InvalidState);
+        }
+
+        out.copy_from_slice(bytes);
+
+        Ok(())
+    }
+
+    pub fn u32s(&mut self) -> Result<Vec<u32>, Error> {
+        let len = self.usize()?;
+
+        // Each value is 4 bytes; don't allocate for more than there is
+        if self.buffer.len() / 4 < len {
+            return Err(Error::InvalidState);
+        }
+
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(self.u32()?);
+        }
+
+        Ok(values)
+    }
+
+    pub fn str(&mut self) -> Result<&'a str, Error> {
+        str::from_utf8(self.bytes()?).map_err(|_| Error::InvalidState)
+    }
+}
+
+// Sections of a savestate by tag
+pub struct Sections<'a> {
+    sections: Vec<([u8; 4], &'a [u8])>,
+}
+
+impl<'a> Sections<'a> {
+    // Reader of the contents of the section; `None` if there is none (eg. the
+    // state is from a release before the section was added)
+    pub fn find(&self, tag: &[u8; 4]) -> Option<Reader<'a>> {
+        self.sections
+            .iter()
+            .find(|section| &section.0 == tag)
+            .map(|section| Reader::new(section.1))
+    }
+
+    // As `find` for sections every savestate has
+    pub fn get(&self, tag: &[u8; 4]) -> Result<Reader<'a>, Error> {
+        self.find(tag).ok_or(Error::InvalidState)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        compress(data, &mut compressed);
+
+        decompress(&compressed).unwrap()
+    }
+
+    #[test]
+    fn compress_runs_and_literals() {
+        let mut compressed = Vec::new();
+        compress(&[0, 0, 0, 0, 1, 2, 2], &mut compressed);
+        assert_eq!(compressed, vec![0x81, 0, 2, 1, 2, 2]);
+
+        let mut data = vec![7; 1000];
+        data.extend((0..300).map(|i| i as u8));
+        data.extend(vec![0; 2]);
+        assert_eq!(round_trip(&data), data);
+
+        assert_eq!(round_trip(&[]), Vec::<u8>::new());
+        assert_eq!(round_trip(&[9]), vec![9]);
+        assert_eq!(round_trip(&[0; 130]), vec![0; 130]);
+        assert_eq!(round_trip(&[0; 131]), vec![0; 131]);
+    }
+
+    #[test]
+    fn reject_truncated() {
+        assert!(decompress(&[0x81]).is_err());
+        assert!(decompress(&[3, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn sections() {
+        let mut body: Writer = Default::default();
+        body.section(b"ONE ", |w| {
+            w.u16(0x1234);
+            w.str("name");
+            w.u32s(&[1, 2, 3]);
+        });
+
+        body.section(b"TWO ", |w| w.bool(true));
+
+        for &compressed in &[false, true] {
+            let state = encode(&body.buffer, compressed);
+            let decoded = decode(&state).unwrap();
+            let sections = Reader::new(&decoded).sections().unwrap();
+
+            let mut r = sections.get(b"ONE ").unwrap();
+            assert_eq!(r.u16().unwrap(), 0x1234);
+            assert_eq!(r.str().unwrap(), "name");
+            assert_eq!(r.u32s().unwrap(), vec![1, 2, 3]);
+            assert!(r.is_empty());
+
+            assert_eq!(sections.get(b"TWO ").unwrap().bool().unwrap(), true);
+            assert!(sections.find(b"NONE").is_none());
+
+            assert_eq!(read_section(&state, b"TWO ", |r| r.bool()), Some(true));
+        }
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        let mut w: Writer = Default::default();
+        w.tag(MAGIC);
+        w.u16(VERSION + 1);
+        w.u8(0);
+
+        match decode(&w.into_bytes()) {
+            Err(Error::UnsupportedState { version }) => assert_eq!(version, VERSION + 1),
+            _ => panic!("a newer state was decoded"),
+        }
+
+        assert!(decode(b"xCHS\0\x01").is_err());
+        assert!(decode(b"xC").is_err());
+    }
+
+    #[test]
+    fn sync_round_trip() {
+        let body = vec![0, 0, 0, 0, 5, 6, 7];
+
+        assert_eq!(decode_sync(&encode_sync(&body)).unwrap(), body);
+        assert!(decode_sync(&encode(&body, true)).is_err());
+    }
+}
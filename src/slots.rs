@@ -0,0 +1,70 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::vec::Vec;
+
+use error::Error;
+use storage;
+
+// A savestate slot that has something saved in it
+#[derive(Clone, Copy, Debug)]
+pub struct Slot {
+    pub slot: usize,
+
+    // When it was last saved
+    pub modified: SystemTime,
+}
+
+// Path of the savestate in `slot` for the ROM identified by `rom_hash`
+//  <data dir>/states/<rom hash>/<slot>.state
+fn path(rom_hash: u64, slot: usize) -> Result<PathBuf, Error> {
+    match storage::rom_dir("states", rom_hash) {
+        Some(dir) => Ok(dir.join(format!("{}.state", slot))),
+        None => {
+            Err(Error::StateIo(io::Error::new(io::ErrorKind::NotFound,
+                                              "no data directory (HOME is not set)")))
+        }
+    }
+}
+
+pub fn save(rom_hash: u64, slot: usize, state: &[u8]) -> Result<(), Error> {
+    let path = path(rom_hash, slot)?;
+
+    storage::write(&path, state).map_err(Error::StateIo)
+}
+
+pub fn load(rom_hash: u64, slot: usize) -> Result<Vec<u8>, Error> {
+    let path = path(rom_hash, slot)?;
+
+    storage::read(&path).ok_or(Error::EmptySlot { slot: slot })
+}
+
+// Slots with something saved in them (by slot)
+pub fn list(rom_hash: u64) -> Vec<Slot> {
+    let entries = match storage::rom_dir("states", rom_hash).and_then(|dir| fs::read_dir(dir).ok()) {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+
+    let mut slots = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.extension().map_or(true, |extension| extension != "state") {
+            continue;
+        }
+
+        let slot = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse().ok());
+        let modified = entry.metadata().and_then(|metadata| metadata.modified()).ok();
+        if let (Some(slot), Some(modified)) = (slot, modified) {
+            slots.push(Slot {
+                slot: slot,
+                modified: modified,
+            });
+        }
+    }
+
+    slots.sort_by_key(|slot| slot.slot);
+
+    slots
+}
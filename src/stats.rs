@@ -0,0 +1,76 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Counters of the work done by the core (since the ROM was inserted); to
+// diagnose speed problems (eg. on weak hardware)
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    // Instructions run
+    pub instructions: u64,
+
+    // Frames run and frames presented (not a duplicate of the one before)
+    pub frames: u64,
+    pub frames_presented: u64,
+
+    // Sprites drawn (`DXYN`)
+    pub draws: u64,
+
+    // Instructions run per second of wall time (over the last second)
+    pub instructions_per_second: f64,
+
+    // Wall time taken by the last call of `run_next`
+    pub frame_time: Duration,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frame_time = self.frame_time.as_secs() as f64 * 1000.0 +
+                         self.frame_time.subsec_nanos() as f64 / 1_000_000.0;
+
+        write!(f,
+               "{} instructions ({:.0}/s), {} frames ({} presented), {} draws, {:.2} ms/frame",
+               self.instructions,
+               self.instructions_per_second,
+               self.frames,
+               self.frames_presented,
+               self.draws,
+               frame_time)
+    }
+}
+
+// Measures the instructions run per second of wall time
+#[derive(Default)]
+pub struct Rate {
+    // Start of the window and the instructions run before it
+    start: Option<Instant>,
+    instructions: u64,
+}
+
+impl Rate {
+    // Returns the rate once a second has passed since the window started
+    pub fn update(&mut self, instructions: u64) -> Option<f64> {
+        let now = Instant::now();
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some(now);
+                self.instructions = instructions;
+
+                return None;
+            }
+        };
+
+        let elapsed = now.duration_since(start);
+        if elapsed.as_secs() < 1 {
+            return None;
+        }
+
+        let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+        let rate = instructions.saturating_sub(self.instructions) as f64 / seconds;
+
+        self.start = Some(now);
+        self.instructions = instructions;
+
+        Some(rate)
+    }
+}
@@ -0,0 +1,55 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+// Directory for data persisted across sessions (RPL user flags, etc.)
+//  $XDG_DATA_HOME/xchip; falling back to ~/.local/share/xchip
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+        return Some(PathBuf::from(dir).join("xchip"));
+    }
+
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/xchip"))
+}
+
+// Directory for configuration (`xchip.toml`)
+//  $XDG_CONFIG_HOME/xchip; falling back to ~/.config/xchip
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("xchip"));
+    }
+
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/xchip"))
+}
+
+// Path of the `kind` file (eg. "flags") for the ROM identified by `rom_hash`
+//  <data dir>/<kind>/<rom hash>.<extension>
+pub fn rom_path(kind: &str, rom_hash: u64, extension: &str) -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(kind).join(format!("{:016x}.{}", rom_hash, extension)))
+}
+
+// Directory of the `kind` files (eg. "states") for the ROM identified by `rom_hash`
+//  <data dir>/<kind>/<rom hash>
+pub fn rom_dir(kind: &str, rom_hash: u64) -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(kind).join(format!("{:016x}", rom_hash)))
+}
+
+// Read the entire file; `None` if it does not exist (or can't be read)
+pub fn read(path: &Path) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    match File::open(path).and_then(|mut f| f.read_to_end(&mut buffer)) {
+        Ok(_) => Some(buffer),
+        Err(_) => None,
+    }
+}
+
+// Write the entire file; creating any missing parent directories
+pub fn write(path: &Path, buffer: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    File::create(path)?.write_all(buffer)
+}
@@ -2,56 +2,328 @@ use std::cmp;
 
 use chip_8;
 use opcode::Opcode;
+use instruction::Instruction;
 use mmu::Mmu;
-use interpreter::{Runtime, Context};
+use interpreter::{Runtime, Context, DisplayMode, FONT_10_ADDRESS, PLANES};
 use axal;
+use hash;
+use storage;
+use quirks::{Quirks, LoresDxy0};
+use error::Error;
+use savestate::{Reader, Writer};
 
-#[derive(PartialEq)]
-enum DisplayMode {
-    Standard,
-    Extended,
+// Revision of the SUPER-CHIP interpreter to behave as
+#[derive(Clone, Copy, PartialEq)]
+pub enum Revision {
+    // SUPER-CHIP 1.0
+    //  - Scrolling in standard display mode moves by whole (2x2) dots
+    //  - `FX55` / `FX65` leave I incremented by X
+    //  - VF is a flag after DRW in extended display mode
+    V1_0,
+
+    // SUPER-CHIP 1.1
+    //  - Scrolling in standard display mode moves by half-dots
+    //  - `FX55` / `FX65` leave I unchanged
+    //  - VF is the number of collided rows after DRW in extended display mode
+    V1_1,
 }
 
-impl Default for DisplayMode {
+impl Default for Revision {
     fn default() -> Self {
-        DisplayMode::Standard
+        Revision::V1_1
     }
 }
 
-#[derive(Default)]
+#[derive(Clone)]
 pub struct SuperChip {
     // SUPER-CHIP starts from the CHIP-8
     chip_8: chip_8::Chip8,
 
+    revision: Revision,
+
     // Adds scratch storage for up to 8 general (V) registers
     // NOTE: The limitation comes from the original SUPER-CHIP's usage
     //       of the HP48 RPL user flags for the storage (of which there
     //       were only 8).
+    //  The flags persist across sessions (per ROM) as games use them for high scores.
     v_scratch: [u8; 8],
 
-    // SUPER-CHIP can access the display as 128x64 dots or 64x32 2x2 dot regions
-    mode: DisplayMode,
+    // Hash of the inserted ROM; identifies where the RPL user flags are persisted
+    rom_hash: u64,
+
+    // Clear the screen when the display mode is changed
+    //  The SUPER-CHIP leaves the screen as-is; XO-CHIP clears it.
+    clear_on_display_mode_change: bool,
+
+    // In extended mode, VF is set to the number of rows that collided (SUPER-CHIP 1.1)
+    // rather than 1 if any collided
+    count_collided_rows: bool,
+
+    // Scrolling in standard display mode moves by whole (2x2) dots rather than half-dots
+    scroll_whole_dots: bool,
+}
+
+impl Default for SuperChip {
+    fn default() -> Self {
+        SuperChip::new(Default::default())
+    }
+}
+
+impl SuperChip {
+    pub fn new(revision: Revision) -> Self {
+        SuperChip {
+            chip_8: Default::default(),
+            revision: revision,
+            v_scratch: Default::default(),
+            rom_hash: 0,
+            clear_on_display_mode_change: false,
+            count_collided_rows: revision == Revision::V1_1,
+            scroll_whole_dots: revision == Revision::V1_0,
+        }
+    }
+
+    // SUPER-CHIP as extended by XO-CHIP
+    //  - Changing the display mode clears the screen
+    //  - VF is always a collision flag
+    //  - Scrolling in standard display mode moves by whole dots
+    pub fn xo_chip() -> Self {
+        SuperChip {
+            clear_on_display_mode_change: true,
+            count_collided_rows: false,
+            scroll_whole_dots: true,
+            ..SuperChip::new(Revision::V1_1)
+        }
+    }
+
+    // Scroll the screen by (dx, dy) dots as addressed in extended display mode
+    //  SUPER-CHIP 1.0 scrolls by whole dots in standard display mode.
+    pub fn scroll(&self, c: &mut Context, dx: isize, dy: isize) {
+        let scale = if self.scroll_whole_dots {
+            self.scale(c) as isize
+        } else {
+            1
+        };
+
+        c.scroll(dx * scale, dy * scale);
+    }
+
+    // Size (in screen dots) of a single dot as addressed by the active display mode
+    //  The screen is always 128x64; standard mode addresses it as 64x32 2x2 dot regions.
+    fn scale(&self, c: &Context) -> usize {
+        if c.display_mode == DisplayMode::Extended { 1 } else { 2 }
+    }
+
+    // Load the RPL user flags persisted for the ROM (cleared if there are none,
+    // or in lockstep)
+    fn load_flags(&mut self, c: &Context) {
+        let flags = if c.lockstep {
+            Vec::new()
+        } else {
+            storage::rom_path("flags", self.rom_hash, "bin")
+                .and_then(|path| storage::read(&path))
+                .unwrap_or_default()
+        };
+
+        for (i, v) in self.v_scratch.iter_mut().enumerate() {
+            *v = flags.get(i).cloned().unwrap_or(0);
+        }
+    }
+
+    fn save_flags(&self, c: &Context) {
+        if c.lockstep || c.speculative {
+            return;
+        }
+
+        if let Some(path) = storage::rom_path("flags", self.rom_hash, "bin") {
+            if let Err(error) = storage::write(&path, &self.v_scratch) {
+                eprintln!("warning: failed to save RPL user flags to {}: {}",
+                          path.display(),
+                          error);
+            }
+        }
+    }
+
+    fn set_display_mode(&mut self, c: &mut Context, mode: DisplayMode) {
+        if self.clear_on_display_mode_change {
+            // All planes are cleared (regardless of selection)
+            for dot in &mut c.screen {
+                *dot = 0;
+            }
+        }
+
+        c.display_mode = mode;
+        c.dirty = true;
+    }
+
+    // Show a `width`x`height` sprite (`width` is 8 or 16) from [I] at (x, y); VF := collision
+    //  The sprite is drawn to each selected plane in turn; each plane takes the
+    //  next `width`x`height` sprite from memory (XO-CHIP).
+    fn show(&mut self,
+            c: &mut Context,
+            m: &mut Mmu,
+            x: usize,
+            y: usize,
+            width: usize,
+            height: usize) {
+        // Draw in the vertical blank if the variant does so (display wait quirk)
+        if c.wait_for_vblank() {
+            return;
+        }
+
+        c.draws += 1;
+
+        let scale = self.scale(c);
+        let bytes_per_row = width / 8;
+
+        // Dimensions of the screen as addressed by the active display mode
+        let screen_width = c.screen_width / scale;
+        let screen_height = c.screen_height / scale;
+
+        // The coordinates wrap; the sprite is clipped at the edges of the screen
+        // or wraps around if the variant does so (clipping quirk)
+        let x = x % screen_width;
+        let y = y % screen_height;
+        let clip = c.quirks.clip_sprites;
+
+        // In extended mode, VF counts the rows that collided or were clipped at the
+        // bottom of the screen (instead of being a flag)
+        let count_rows = self.count_collided_rows && c.display_mode == DisplayMode::Extended;
+
+        // VF is cleared at the start of DRW so collision can be set easily
+        c.v[0xF] = 0;
+        c.dirty = true;
+
+        let mut address = c.i;
+        for plane in 0..PLANES {
+            let plane = 1 << plane;
+            if (c.plane_mask & plane) == 0 {
+                continue;
+            }
+
+            for i in 0..height {
+                if count_rows && y + i >= screen_height {
+                    // Rows past the bottom are clipped
+                    c.v[0xF] += 1;
+                    continue;
+                }
+
+                if clip && y + i >= screen_height {
+                    break;
+                }
+
+                let sy = (y + i) % screen_height;
+                let mut collided = false;
+
+                for j in 0..width {
+                    if clip && x + j >= screen_width {
+                        break;
+                    }
+
+                    let sx = (x + j) % screen_width;
+
+                    // Read memory to get the _set_ value
+                    let row = m.read(address + i * bytes_per_row + j / 8);
+                    if (row >> (7 - (j % 8))) & 1 == 0 {
+                        // Unset dots in a sprite leave the screen unchanged
+                        continue;
+                    }
+
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let offset = (sy * scale + dy) * c.screen_width + (sx * scale + dx);
+
+                            // Collision is the transition 1 -> 0
+                            let dot = &mut c.screen[offset];
+                            collided |= (*dot & plane) != 0;
+
+                            *dot ^= plane;
+                        }
+                    }
+                }
+
+                if count_rows {
+                    c.v[0xF] += collided as u8;
+                } else {
+                    c.v[0xF] |= collided as u8;
+                }
+            }
+
+            address += height * bytes_per_row;
+        }
+    }
 }
 
 impl Runtime for SuperChip {
+    fn save_state(&self, w: &mut Writer) {
+        w.bool(self.revision == Revision::V1_0);
+        w.bytes(&self.v_scratch);
+        w.u64(self.rom_hash);
+        w.bool(self.clear_on_display_mode_change);
+        w.bool(self.count_collided_rows);
+        w.bool(self.scroll_whole_dots);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error> {
+        self.revision = if r.bool()? { Revision::V1_0 } else { Revision::V1_1 };
+        r.bytes_into(&mut self.v_scratch)?;
+        self.rom_hash = r.u64()?;
+        self.clear_on_display_mode_change = r.bool()?;
+        self.count_collided_rows = r.bool()?;
+        self.scroll_whole_dots = r.bool()?;
+
+        Ok(())
+    }
+
     fn configure(&mut self, c: &mut Context) {
         // Increase screen size to 128x64
-        c.screen_width = 128;
-        c.screen_height = 64;
-        c.screen.resize(c.screen_width * c.screen_height, Default::default());
+        c.resize_screen(128, 64);
+
+        // Restore scratch storage (as persisted for the ROM inserted)
+        self.load_flags(c);
+    }
+
+    fn quirks(&self) -> Quirks {
+        match self.revision {
+            Revision::V1_0 => Quirks::super_chip_1_0(),
+            Revision::V1_1 => Quirks::super_chip_1_1(),
+        }
     }
 
     fn reset(&mut self, c: &mut Context) {
         // Reset CHIP-8
         self.chip_8.reset(c);
 
-        // Clear scratch storage
-        for v in &mut self.v_scratch {
-            *v = 0;
+        // Restore scratch storage (as persisted)
+        self.load_flags(c);
+    }
+
+    fn insert_rom(&mut self, m: &mut Mmu, buffer: &[u8]) {
+        m.write_all(self.rom_address(), buffer);
+
+        self.rom_hash = hash::fnv1a(buffer);
+    }
+
+    fn update_framebuffer(&mut self, c: &mut Context) {
+        // Standard mode presents the screen at 64x32 (one framebuffer pixel
+        // per 2x2 dot region)
+        let scale = self.scale(c);
+        let (width, height) = self.framebuffer_size(c);
+
+        c.framebuffer.resize(width * height, 0);
+
+        for y in 0..height {
+            for x in 0..width {
+                // Get (phase of) dot from screen
+                let phase = c.phase[(y * scale) * c.screen_width + (x * scale)];
+
+                // Blit to framebuffer
+                c.framebuffer[y * width + x] = phase.color(&c.palette);
+            }
         }
+    }
 
-        // Reset display mode to Standard
-        self.mode = DisplayMode::Standard;
+    fn framebuffer_size(&self, c: &Context) -> (usize, usize) {
+        (c.screen_width / self.scale(c), c.screen_height / self.scale(c))
     }
 
     fn execute(&mut self,
@@ -60,76 +332,101 @@ impl Runtime for SuperChip {
                m: &mut Mmu,
                opcode: Opcode)
                -> bool {
-        match opcode.unwrap() {
+        match Instruction::decode(opcode.extract_u16()) {
             // SCDOWN
-            (0x0, 0x0, 0xC, n) => {
+            Instruction::ScrollDown(n) => {
                 // Scroll screen N lines down
                 // NOTE: This always operates on a 128x64 display regardless of the active mode
-                unimplemented!();
+                self.scroll(c, 0, n as isize);
             }
 
             // SCRIGHT
-            (0x0, 0x0, 0xF, 0xB) => {
+            Instruction::ScrollRight => {
                 // Scroll screen 4 dots right
                 // NOTE: This always operates on a 128x64 display regardless of the active mode
-                unimplemented!();
+                self.scroll(c, 4, 0);
             }
 
             // SCLEFT
-            (0x0, 0x0, 0xF, 0xC) => {
+            Instruction::ScrollLeft => {
                 // Scroll screen 4 dots left
                 // NOTE: This always operates on a 128x64 display regardless of the active mode
-                unimplemented!();
+                self.scroll(c, -4, 0);
             }
 
-            // SED
-            // TODO: If you have a better idea for a mnemonic; a PR would be appreciated
-            (0x0, 0x0, 0xF, 0xE) => {
-                // Set extended display mode
-                self.mode = DisplayMode::Extended;
+            // EXIT
+            Instruction::Exit => {
+                // Exit the interpreter; halt until reset
+                c.halted = true;
             }
 
-            // CLD
-            // TODO: If you have a better idea for a mnemonic; a PR would be appreciated
-            (0x0, 0x0, 0xF, 0xF) => {
+            // LOW
+            Instruction::Low => {
                 // Clear extended display mode (revert to standard)
-                self.mode = DisplayMode::Standard;
+                self.set_display_mode(c, DisplayMode::Standard);
+            }
+
+            // HIGH
+            Instruction::High => {
+                // Set extended display mode
+                self.set_display_mode(c, DisplayMode::Extended);
             }
 
             // SHOW16 Vx, Vy
-            (0xD, _, _, 0x0) if self.mode == DisplayMode::Extended => {
+            Instruction::Draw(x, y, 0) => {
                 // Show 16x16 sprite from [I] at coordinates (Vx, Vy); VF := collision
-                unimplemented!();
+                //  The sprite is 32 bytes; 2 bytes per row
+                //  In standard display mode, an 8x16 sprite or nothing may be shown
+                //  instead (lores DXY0 quirk).
+                let x = c.v[x as usize] as usize;
+                let y = c.v[y as usize] as usize;
+
+                let (width, height) = if c.display_mode == DisplayMode::Extended {
+                    (16, 16)
+                } else {
+                    match c.quirks.lores_dxy0 {
+                        LoresDxy0::None => (8, 0),
+                        LoresDxy0::Tall => (8, 16),
+                        LoresDxy0::Big => (16, 16),
+                    }
+                };
+
+                self.show(c, m, x, y, width, height);
             }
 
             // SHOW Vx, Vy, N
-            (0xD, _, _, _) => {
-                // Show 8x8 sprite from [I] at coordinates (Vx, Vy); VF := collision
+            Instruction::Draw(x, y, n) => {
+                // Show 8xN sprite from [I] at coordinates (Vx, Vy); VF := collision
                 // NOTE: This must be re-implemented from CHIP-8 because in standard display mode
                 //       2x2 dot blocks are shown instead of single dots
-                unimplemented!();
+                let x = c.v[x as usize] as usize;
+                let y = c.v[y as usize] as usize;
+
+                self.show(c, m, x, y, 8, n as usize);
             }
 
             // LD I, FONT10 Vx
-            (0xF, _, 0x3, 0x0) => {
+            Instruction::LoadBigFont(x) => {
                 // Point I to 10-byte font sprite for digit Vx
-                unimplemented!();
+                c.i = FONT_10_ADDRESS + ((c.v[x as usize] & 0xF) as usize) * 10;
             }
 
             // SAVE Vx .. Vy
-            (0xF, x, 0x7, 0x5) => {
+            Instruction::SaveFlags(x) => {
                 // Store V0..Vx into private interpreter memory; at most 8
                 //  registers can be stored
-                for i in 0..cmp::min(x as usize, 7) {
+                for i in 0..(cmp::min(x as usize, 7) + 1) {
                     self.v_scratch[i] = c.v[i];
                 }
+
+                self.save_flags(c);
             }
 
             // RESTORE Vx .. Vy
-            (0xF, x, 0x8, 0x5) => {
+            Instruction::LoadFlags(x) => {
                 // Restore V0..Vx from private interpreter memory; at most 8
                 //  registers can be restored
-                for i in 0..cmp::min(x as usize, 7) {
+                for i in 0..(cmp::min(x as usize, 7) + 1) {
                     c.v[i] = self.v_scratch[i];
                 }
             }
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::vec::Vec;
+
+// Symbols of a program assembled by Octo (for symbolic debugging)
+//  A symbol map is read from next to the ROM (`<rom>.sym`); a line each, of:
+//   :label NAME ADDRESS         (or `NAME ADDRESS`) an address of the program
+//   :const NAME VALUE           a constant (found by name; not an address)
+//   :breakpoint NAME ADDRESS    a breakpoint (`:breakpoint` in the source)
+//   :monitor NAME ADDRESS [LEN] a variable of `LEN` bytes (`:monitor`; 1 if not given)
+//   :source PATH                the source (relative to the map; `<rom>.8o` if not given)
+//   :line LINE ADDRESS          the first instruction of a line of the source
+//  Numbers are decimal, `0x` or `$` hex. Blank lines, lines starting with `#`
+//  and lines that are not understood are skipped.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    // Labels by address (in order of address)
+    labels: Vec<(usize, String)>,
+
+    constants: Vec<(String, usize)>,
+    breakpoints: Vec<(String, usize)>,
+
+    // Variables (name, address, length)
+    monitors: Vec<(String, usize, usize)>,
+
+    // Source (and its lines, if it could be read) and the first address of its
+    // lines (line, address; in order of address)
+    source: Option<PathBuf>,
+    source_lines: Vec<String>,
+    lines: Vec<(usize, usize)>,
+}
+
+impl Symbols {
+    // Load the symbol map next to the ROM at `filename`; `None` if there is none
+    pub fn load(filename: &str) -> Option<Symbols> {
+        let path = Path::new(filename).with_extension("sym");
+        let mut symbols = Symbols::parse(&read(&path)?);
+
+        // The source is relative to the map
+        let source = match symbols.source.take() {
+            Some(source) => path.parent().map_or(source.clone(), |dir| dir.join(source)),
+            None => Path::new(filename).with_extension("8o"),
+        };
+
+        if let Some(text) = read(&source) {
+            symbols.source_lines = text.lines().map(|line| line.to_string()).collect();
+            symbols.source = Some(source);
+        }
+
+        Some(symbols)
+    }
+
+    pub fn parse(text: &str) -> Symbols {
+        let mut symbols: Symbols = Default::default();
+
+        for line in text.lines() {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let number = |i: usize| tokens.get(i).and_then(|token| parse_number(token));
+
+            match tokens.get(0).cloned() {
+                None => {}
+                Some(token) if token.starts_with('#') => {}
+
+                Some(":label") => {
+                    if let (Some(name), Some(address)) = (tokens.get(1), number(2)) {
+                        symbols.labels.push((address, name.to_string()));
+                    }
+                }
+
+                Some(":const") => {
+                    if let (Some(name), Some(value)) = (tokens.get(1), number(2)) {
+                        symbols.constants.push((name.to_string(), value));
+                    }
+                }
+
+                Some(":breakpoint") => {
+                    if let (Some(name), Some(address)) = (tokens.get(1), number(2)) {
+                        symbols.breakpoints.push((name.to_string(), address));
+                    }
+                }
+
+                Some(":source") => {
+                    if let Some(path) = tokens.get(1) {
+                        symbols.source = Some(PathBuf::from(path));
+                    }
+                }
+
+                Some(":line") => {
+                    if let (Some(line), Some(address)) = (number(1), number(2)) {
+                        symbols.lines.push((line, address));
+                    }
+                }
+
+                Some(":monitor") => {
+                    if let (Some(name), Some(address)) = (tokens.get(1), number(2)) {
+                        let len = number(3).unwrap_or(1);
+                        symbols.monitors.push((name.to_string(), address, len));
+                    }
+                }
+
+                Some(name) if !name.starts_with(':') => {
+                    if let (Some(address), 2) = (number(1), tokens.len()) {
+                        symbols.labels.push((address, name.to_string()));
+                    }
+                }
+
+                Some(_) => {}
+            }
+        }
+
+        symbols.labels.sort_by_key(|label| label.0);
+        symbols.lines.sort_by_key(|line| line.1);
+        symbols
+    }
+
+    // Address (or value) of the symbol `name`
+    pub fn address(&self, name: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .find(|label| label.1 == name)
+            .map(|label| label.0)
+            .or_else(|| self.constants.iter().find(|c| c.0 == name).map(|c| c.1))
+            .or_else(|| self.breakpoints.iter().find(|b| b.0 == name).map(|b| b.1))
+            .or_else(|| self.monitors.iter().find(|m| m.0 == name).map(|m| m.1))
+    }
+
+    // Label at `address` (exactly)
+    pub fn label(&self, address: usize) -> Option<&str> {
+        self.labels.iter().find(|label| label.0 == address).map(|label| &label.1[..])
+    }
+
+    // Label `address` is in: the nearest at or before it, and the offset from it
+    pub fn locate(&self, address: usize) -> Option<(&str, usize)> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|label| label.0 <= address)
+            .map(|label| (&label.1[..], address - label.0))
+    }
+
+    // `address` by its label (eg. `main+4`), or as-is if it has none
+    pub fn describe(&self, address: usize) -> String {
+        match self.locate(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+{}", name, offset),
+            None => format!("${:03X}", address),
+        }
+    }
+
+    // Breakpoints of the source (name and address)
+    pub fn breakpoints(&self) -> &[(String, usize)] {
+        &self.breakpoints
+    }
+
+    // Variables of the source (name, address and length)
+    pub fn monitors(&self) -> &[(String, usize, usize)] {
+        &self.monitors
+    }
+
+    // Path of the source; `None` if it is not known (or could not be read)
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_ref().map(|source| source.as_path())
+    }
+
+    // Line of the source (from 1) the instruction at `address` is of
+    pub fn line(&self, address: usize) -> Option<usize> {
+        self.lines.iter().rev().find(|line| line.1 <= address).map(|line| line.0)
+    }
+
+    // First address of `line`, or of the next line with an instruction (a
+    // breakpoint on a blank line or comment stops at the code after it)
+    pub fn line_address(&self, line: usize) -> Option<usize> {
+        self.lines
+            .iter()
+            .filter(|l| l.0 >= line)
+            .min_by_key(|l| (l.0, l.1))
+            .map(|l| l.1)
+    }
+
+    // Text of `line` of the source
+    pub fn source_line(&self, line: usize) -> Option<&str> {
+        self.source_lines.get(line.wrapping_sub(1)).map(|text| &text[..])
+    }
+}
+
+fn read(path: &Path) -> Option<String> {
+    let mut text = String::new();
+    File::open(path).and_then(|mut file| file.read_to_string(&mut text)).ok()?;
+
+    Some(text)
+}
+
+fn parse_number(token: &str) -> Option<usize> {
+    if token.starts_with("0x") || token.starts_with("0X") {
+        usize::from_str_radix(&token[2..], 16).ok()
+    } else if token.starts_with('$') {
+        usize::from_str_radix(&token[1..], 16).ok()
+    } else {
+        token.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAP: &'static str = "# assembled by Octo
+:label main 0x200
+:label draw $210
+loop 530
+:const SPEED 4
+:breakpoint check 0x20A
+:monitor score 0x300 2
+:monitor lives 0x302
+:source game.8o
+:line 3 0x200
+:line 5 0x202
+:line 9 0x210
+:unknown x 1
+:label broken
+";
+
+    #[test]
+    fn parse_map() {
+        let symbols = Symbols::parse(MAP);
+
+        assert_eq!(symbols.address("main"), Some(0x200));
+        assert_eq!(symbols.address("loop"), Some(0x212));
+        assert_eq!(symbols.address("SPEED"), Some(4));
+        assert_eq!(symbols.address("check"), Some(0x20A));
+        assert_eq!(symbols.address("score"), Some(0x300));
+        assert_eq!(symbols.address("broken"), None);
+        assert_eq!(symbols.breakpoints(), &[("check".to_string(), 0x20A)]);
+        assert_eq!(symbols.monitors(),
+                   &[("score".to_string(), 0x300, 2), ("lives".to_string(), 0x302, 1)]);
+        assert_eq!(symbols.source, Some(PathBuf::from("game.8o")));
+    }
+
+    #[test]
+    fn labels_by_address() {
+        let symbols = Symbols::parse(MAP);
+
+        assert_eq!(symbols.label(0x210), Some("draw"));
+        assert_eq!(symbols.label(0x211), None);
+        assert_eq!(symbols.locate(0x20C), Some(("main", 0xC)));
+        assert_eq!(symbols.describe(0x200), "main");
+        assert_eq!(symbols.describe(0x214), "loop+2");
+        assert_eq!(symbols.describe(0x1FE), "$1FE");
+    }
+
+    #[test]
+    fn lines_by_address() {
+        let symbols = Symbols::parse(MAP);
+
+        assert_eq!(symbols.line(0x200), Some(3));
+        assert_eq!(symbols.line(0x208), Some(5));
+        assert_eq!(symbols.line(0x1FE), None);
+
+        // A line without code stops at the next one with it
+        assert_eq!(symbols.line_address(5), Some(0x202));
+        assert_eq!(symbols.line_address(6), Some(0x210));
+        assert_eq!(symbols.line_address(10), None);
+    }
+}
@@ -0,0 +1,123 @@
+use interpreter::Context;
+use opcode::Opcode;
+use instruction::Instruction;
+
+// How long instructions take to run
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timing {
+    // Every instruction takes the same time (a number of instructions per frame;
+    // see `Core::set_instructions_per_frame`)
+    Uniform,
+
+    // Every instruction takes the machine cycles it took on the COSMAC VIP (the
+    // original interpreter); `DXYN` and `00E0` are much slower than the rest
+    //  This gives the flicker and pacing of games as they were on the VIP; the
+    //  speed set is not used. The cycle counts are approximate (from analysis
+    //  of the VIP interpreter) for the CHIP-8 instructions; others take as
+    //  long as the fetch and decode.
+    CosmacVip,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::Uniform
+    }
+}
+
+// Machine cycles per second of the VIP (1.76 MHz; 8 clocks per machine cycle)
+const VIP_CYCLES_PER_SECOND: usize = 220_080;
+
+// Machine cycles taken from each frame by the display (DMA of the 256 bytes of
+// video RAM for 128 lines) and its interrupt routine (which also clocks DT / ST)
+const VIP_DISPLAY_CYCLES: usize = 1024 + 46;
+
+// Machine cycles taken by the fetch and decode of every instruction
+const VIP_FETCH_CYCLES: usize = 40;
+
+// Machine cycles available to the program in a frame at `hz`
+pub fn vip_cycles_per_frame(hz: usize) -> usize {
+    VIP_CYCLES_PER_SECOND / hz - VIP_DISPLAY_CYCLES
+}
+
+// Machine cycles taken on the VIP by `opcode` (with the context as it is before
+// the instruction runs); see `vip_skip_cycles` for skips
+pub fn vip_cycles(c: &Context, opcode: Opcode) -> usize {
+    VIP_FETCH_CYCLES +
+    match Instruction::decode(opcode.extract_u16()) {
+        // CLS
+        Instruction::ClearScreen => 3078,
+
+        // RET
+        Instruction::Return => 10,
+
+        // JP addr
+        Instruction::Jump(_) => 12,
+
+        // CALL addr
+        Instruction::Call(_) => 26,
+
+        // SE / SNE Vx, u8
+        Instruction::SkipEqual(..) | Instruction::SkipNotEqual(..) => 10,
+
+        // SE / SNE Vx, Vy
+        Instruction::SkipEqualRegisters(..) | Instruction::SkipNotEqualRegisters(..) => 14,
+
+        // LD Vx, u8
+        Instruction::Load(..) => 6,
+
+        // ADD Vx, u8
+        Instruction::Add(..) => 10,
+
+        // Arithmetic (all run through the same RAM-patched routine)
+        Instruction::Move(..) | Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..) |
+        Instruction::AddRegisters(..) | Instruction::Sub(..) | Instruction::ShiftRight(..) |
+        Instruction::SubN(..) | Instruction::ShiftLeft(..) => 44,
+
+        // LD I, addr
+        Instruction::LoadI(_) => 12,
+
+        // JP V0, addr
+        Instruction::JumpOffset(_) => 22,
+
+        // RND Vx, u8
+        Instruction::Random(..) => 36,
+
+        // DRW Vx, Vy, n
+        //  Rows that are not aligned to a byte of video RAM are shifted into two
+        //  bytes, which takes twice as long.
+        Instruction::Draw(x, _, n) => {
+            let row = if c.v[x as usize] % 8 == 0 { 22 } else { 48 };
+
+            26 + (n as usize) * row
+        }
+
+        // SKP / SKNP Vx
+        Instruction::SkipKey(_) | Instruction::SkipNotKey(_) => 14,
+
+        // LD Vx, DT / LD DT, Vx / LD ST, Vx
+        Instruction::LoadDelay(_) | Instruction::SetDelay(_) | Instruction::SetSound(_) => 10,
+
+        // LD Vx, K (while waiting)
+        Instruction::WaitKey(_) => 18,
+
+        // ADD I, Vx / LD F, Vx
+        Instruction::AddI(_) | Instruction::LoadFont(_) => 16,
+
+        // LD B, Vx
+        Instruction::LoadBcd(_) => 84,
+
+        // LD [I], Vx / LD Vx, [I]
+        Instruction::Store(x) | Instruction::Restore(x) => 14 + 14 * (x as usize + 1),
+
+        _ => 0,
+    }
+}
+
+// Additional machine cycles taken on the VIP by `opcode` when it skips the next instruction
+pub fn vip_skip_cycles(opcode: Opcode) -> usize {
+    if Instruction::decode(opcode.extract_u16()).is_skip() {
+        4
+    } else {
+        0
+    }
+}
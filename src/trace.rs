@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::vec::Vec;
+
+use disasm;
+use interpreter::Register;
+
+// Instruction run (see `Trace`)
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub opcode: u16,
+
+    // Word after the opcode (the address of `F000 NNNN`)
+    pub next: u16,
+
+    // Registers the instruction changed (and their values after)
+    pub changes: Vec<(Register, usize)>,
+}
+
+impl TraceEntry {
+    // Mnemonic of the instruction (see `disasm::mnemonic`)
+    pub fn disassembly(&self) -> String {
+        disasm::mnemonic(self.opcode, self.next)
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "${:03X}: {:04X}  {:<18}", self.pc, self.opcode, self.disassembly())?;
+
+        for &(register, value) in &self.changes {
+            match register {
+                Register::I => write!(f, " {}=${:03X}", register, value)?,
+                _ => write!(f, " {}={:02X}", register, value)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The last instructions run (oldest first); to tell how a program came to fail
+//  Off (`len` of 0) by default; recording slows every instruction.
+#[derive(Clone, Default)]
+pub struct Trace {
+    len: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Trace {
+    // Keep the last `len` instructions; 0 disables the trace (and drops it)
+    pub fn configure(&mut self, len: usize) {
+        self.len = len;
+        self.entries.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.len > 0
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.entries.len() == self.len {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    // Print the trace (as a crash report)
+    pub fn dump(&self) {
+        for entry in &self.entries {
+            eprintln!("  {}", entry);
+        }
+    }
+}
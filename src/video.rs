@@ -0,0 +1,369 @@
+use std::vec::Vec;
+
+use axal;
+
+use error::Error;
+use savestate::{Reader, Writer};
+
+// Pixel format of the framebuffer presented to the runtime
+#[derive(Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    // 8-bit; 3 bits red, 3 bits green, 2 bits blue
+    R3G3B2,
+
+    // 16-bit; 5 bits red, 6 bits green, 5 bits blue
+    R5G6B5,
+
+    // 32-bit; 8 bits per channel with the high byte unused
+    X8R8G8B8,
+}
+
+impl Default for PixelFormat {
+    fn default() -> Self {
+        PixelFormat::R3G3B2
+    }
+}
+
+impl PixelFormat {
+    // Number of bytes used by a single pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            PixelFormat::R3G3B2 => 1,
+            PixelFormat::R5G6B5 => 2,
+            PixelFormat::X8R8G8B8 => 4,
+        }
+    }
+
+    pub fn to_axal(&self) -> axal::PixelFormat {
+        match *self {
+            PixelFormat::R3G3B2 => axal::PixelFormat::R3_G3_B2,
+            PixelFormat::R5G6B5 => axal::PixelFormat::R5_G6_B5,
+            PixelFormat::X8R8G8B8 => axal::PixelFormat::X8_R8_G8_B8,
+        }
+    }
+}
+
+// Integer factor the presented frame is scaled up by
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scale {
+    X1,
+    X2,
+    X4,
+    X8,
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale::X1
+    }
+}
+
+impl Scale {
+    pub fn factor(&self) -> usize {
+        match *self {
+            Scale::X1 => 1,
+            Scale::X2 => 2,
+            Scale::X4 => 4,
+            Scale::X8 => 8,
+        }
+    }
+}
+
+// Rate frames are run (and presented) at
+//  Some historical interpreters (eg. the ETI-660) and displays run at 50 Hz;
+//  the timers are clocked by the frame as they were by the vertical blank.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FrameRate {
+    Hz60,
+    Hz50,
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        FrameRate::Hz60
+    }
+}
+
+impl FrameRate {
+    pub fn hz(&self) -> usize {
+        match *self {
+            FrameRate::Hz60 => 60,
+            FrameRate::Hz50 => 50,
+        }
+    }
+}
+
+// Clockwise rotation of the presented frame
+#[derive(Clone, Copy, PartialEq)]
+pub enum Rotation {
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Rotation::R0
+    }
+}
+
+impl Rotation {
+    // Dimensions of a `width`x`height` frame after rotation
+    pub fn apply_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match *self {
+            Rotation::R0 | Rotation::R180 => (width, height),
+            Rotation::R90 | Rotation::R270 => (height, width),
+        }
+    }
+}
+
+// Rotate the `width`x`height` frame in `src` into `dst`; returns the dimensions of the result
+pub fn rotate(rotation: Rotation,
+              src: &[u32],
+              width: usize,
+              height: usize,
+              dst: &mut Vec<u32>)
+              -> (usize, usize) {
+    let (dst_width, dst_height) = rotation.apply_size(width, height);
+
+    dst.resize(dst_width * dst_height, 0);
+
+    for y in 0..height {
+        for x in 0..width {
+            let (dx, dy) = match rotation {
+                Rotation::R0 => (x, y),
+                Rotation::R90 => (height - 1 - y, x),
+                Rotation::R180 => (width - 1 - x, height - 1 - y),
+                Rotation::R270 => (y, width - 1 - x),
+            };
+
+            dst[dy * dst_width + dx] = src[y * width + x];
+        }
+    }
+
+    (dst_width, dst_height)
+}
+
+// Amount a dot's phase (brightness) drops per frame after it was turned off; at
+// 0.5 a dot takes 2 frames to fade out which hides most sprite flicker
+pub const PHASE_TICK: f32 = 0.5;
+
+// Slowest rate of `PhosphorDecay::Rate`; a dot fades out within a second (at 60 Hz)
+const MIN_PHASE_TICK: f32 = 1.0 / 60.0;
+
+// Fade-out of dots that were turned off (flicker reduction)
+#[derive(Clone, Copy, PartialEq)]
+pub enum PhosphorDecay {
+    // Dots turn off immediately (pixel-exact output)
+    Off,
+
+    // Phase lost per frame (0, 1]; clamped to fade out within a second (the
+    // default rate if NaN)
+    Rate(f32),
+}
+
+impl Default for PhosphorDecay {
+    fn default() -> Self {
+        PhosphorDecay::Rate(PHASE_TICK)
+    }
+}
+
+impl PhosphorDecay {
+    fn rate(&self) -> f32 {
+        match *self {
+            PhosphorDecay::Off => 1.0,
+            PhosphorDecay::Rate(rate) if rate.is_nan() => PHASE_TICK,
+            PhosphorDecay::Rate(rate) => rate.max(MIN_PHASE_TICK).min(1.0),
+        }
+    }
+}
+
+// A 0xRRGGBB color
+pub type Color = u32;
+
+// Colors of a dot by the planes it is set in: off, plane 1, plane 2, both planes
+pub const DEFAULT_PALETTE: [Color; 4] = [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555];
+
+// Default colors of Octo (which many XO-CHIP games are designed for)
+pub const OCTO_PALETTE: [Color; 4] = [0x996600, 0xFFCC00, 0xFF6600, 0x662200];
+
+// Widest thumbnail (in pixels); larger frames are scaled down by a whole factor
+const THUMBNAIL_MAX_WIDTH: usize = 64;
+
+// Downscaled copy of a presented frame (before filters); kept in savestates
+// to preview them
+#[derive(Clone, Debug, Default)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+
+    // Pixels (0xRRGGBB) by row
+    pub pixels: Vec<Color>,
+}
+
+impl Thumbnail {
+    // Scale `frame` down to fit `THUMBNAIL_MAX_WIDTH`; each pixel is the average
+    // of the pixels it covers
+    pub fn new(frame: &[Color], width: usize, height: usize) -> Self {
+        let factor = (width + THUMBNAIL_MAX_WIDTH - 1) / THUMBNAIL_MAX_WIDTH;
+        if factor <= 1 {
+            return Thumbnail {
+                width: width,
+                height: height,
+                pixels: frame.to_vec(),
+            };
+        }
+
+        let (thumb_width, thumb_height) = (width / factor, height / factor);
+        let mut pixels = Vec::with_capacity(thumb_width * thumb_height);
+        for y in 0..thumb_height {
+            for x in 0..thumb_width {
+                let mut sum = [0; 3];
+                for dy in 0..factor {
+                    for dx in 0..factor {
+                        let color = frame[(y * factor + dy) * width + x * factor + dx];
+                        for (i, shift) in [16, 8, 0].iter().enumerate() {
+                            sum[i] += (color >> shift) & 0xFF;
+                        }
+                    }
+                }
+
+                let n = (factor * factor) as u32;
+                pixels.push(((sum[0] / n) << 16) | ((sum[1] / n) << 8) | (sum[2] / n));
+            }
+        }
+
+        Thumbnail {
+            width: thumb_width,
+            height: thumb_height,
+            pixels: pixels,
+        }
+    }
+
+    // Pixels are written as RGB (3 bytes)
+    pub fn save(&self, w: &mut Writer) {
+        let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+        for color in &self.pixels {
+            rgb.push((color >> 16) as u8);
+            rgb.push((color >> 8) as u8);
+            rgb.push(*color as u8);
+        }
+
+        w.usize(self.width);
+        w.usize(self.height);
+        w.bytes(&rgb);
+    }
+
+    pub fn load(r: &mut Reader) -> Result<Self, Error> {
+        let width = r.usize()?;
+        let height = r.usize()?;
+        let rgb = r.bytes()?;
+        let len = width.checked_mul(height).and_then(|dots| dots.checked_mul(3));
+        if len != Some(rgb.len()) {
+            return Err(Error::InvalidState);
+        }
+
+        Ok(Thumbnail {
+            width: width,
+            height: height,
+            pixels: rgb.chunks(3)
+                .map(|c| ((c[0] as u32) << 16) | ((c[1] as u32) << 8) | (c[2] as u32))
+                .collect(),
+        })
+    }
+}
+
+// Phase of a dot on the screen
+#[derive(Clone, Copy, Default)]
+pub struct Phase {
+    // Planes the dot was set in when it was last on
+    pub planes: u8,
+
+    // Brightness; 1 while the dot is on
+    pub level: f32,
+}
+
+impl Phase {
+    // Color of the dot (from a palette indexed by planes)
+    pub fn color(&self, palette: &[u32; 4]) -> u32 {
+        blend(palette[0], palette[(self.planes & 0b11) as usize], self.level)
+    }
+}
+
+// Step the phase of each dot towards its state in the screen; returns true if
+// any dot is still fading
+pub fn decay(decay: PhosphorDecay, screen: &[u8], phase: &mut [Phase]) -> bool {
+    let rate = decay.rate();
+    let mut fading = false;
+
+    for (dot, phase) in screen.iter().zip(phase.iter_mut()) {
+        if *dot != 0 {
+            phase.planes = *dot;
+            phase.level = 1.0;
+        } else if phase.level > 0.0 {
+            phase.level = (phase.level - rate).max(0.0);
+            fading = true;
+        }
+    }
+
+    fading
+}
+
+// Blend between two 0xRRGGBB colors; `t` of 0 is `off` and 1 is `on`
+pub fn blend(off: u32, on: u32, t: f32) -> u32 {
+    if t >= 1.0 {
+        return on;
+    } else if t <= 0.0 {
+        return off;
+    }
+
+    let mut r = 0;
+    for shift in &[0, 8, 16] {
+        let a = ((off >> shift) & 0xFF) as f32;
+        let b = ((on >> shift) & 0xFF) as f32;
+
+        r |= ((a + (b - a) * t) as u32) << shift;
+    }
+
+    r
+}
+
+// Convert a buffer of 0xRRGGBB colors into `format`; `dst` is resized to fit
+//  Multi-byte pixels are written little-endian.
+pub fn convert(format: PixelFormat, src: &[u32], dst: &mut Vec<u8>) {
+    dst.resize(src.len() * format.bytes_per_pixel(), 0);
+
+    match format {
+        PixelFormat::R3G3B2 => {
+            for (i, color) in src.iter().enumerate() {
+                let r = (color >> 21) & 0b111;
+                let g = (color >> 13) & 0b111;
+                let b = (color >> 6) & 0b11;
+
+                dst[i] = ((r << 5) | (g << 2) | b) as u8;
+            }
+        }
+
+        PixelFormat::R5G6B5 => {
+            for (i, color) in src.iter().enumerate() {
+                let r = (color >> 19) & 0b11111;
+                let g = (color >> 10) & 0b111111;
+                let b = (color >> 3) & 0b11111;
+                let pixel = (r << 11) | (g << 5) | b;
+
+                dst[i * 2] = pixel as u8;
+                dst[i * 2 + 1] = (pixel >> 8) as u8;
+            }
+        }
+
+        PixelFormat::X8R8G8B8 => {
+            for (i, color) in src.iter().enumerate() {
+                dst[i * 4] = *color as u8;
+                dst[i * 4 + 1] = (color >> 8) as u8;
+                dst[i * 4 + 2] = (color >> 16) as u8;
+                dst[i * 4 + 3] = 0xFF;
+            }
+        }
+    }
+}
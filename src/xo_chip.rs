@@ -0,0 +1,200 @@
+use super_chip;
+use opcode::Opcode;
+use instruction::Instruction;
+use mmu::Mmu;
+use interpreter::{Runtime, Context};
+use axal;
+use video;
+use quirks::Quirks;
+use error::Error;
+use savestate::{Reader, Writer};
+
+// Registers Vx through Vy (inclusive); descending if x > y
+fn register_range(x: u8, y: u8) -> Box<Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+
+    if x <= y {
+        Box::new(x..(y + 1))
+    } else {
+        Box::new((y..(x + 1)).rev())
+    }
+}
+
+#[derive(Clone)]
+pub struct XoChip {
+    // XO-CHIP starts from the SUPER-CHIP
+    super_chip: super_chip::SuperChip,
+
+    // Behave exactly as the interpreter of Octo (which most XO-CHIP games are written in)
+    //  - VF is written after the result of `8XY5` / `8XY7`
+    //  - The Octo colors and 1000 instructions per frame
+    octo: bool,
+}
+
+impl Default for XoChip {
+    fn default() -> Self {
+        XoChip {
+            super_chip: super_chip::SuperChip::xo_chip(),
+            octo: false,
+        }
+    }
+}
+
+impl XoChip {
+    // XO-CHIP as run by Octo
+    pub fn octo() -> Self {
+        XoChip { octo: true, ..Default::default() }
+    }
+}
+
+impl Runtime for XoChip {
+    fn configure(&mut self, c: &mut Context) {
+        // Configure SUPER-CHIP
+        self.super_chip.configure(c);
+
+        if self.octo {
+            c.palette = video::OCTO_PALETTE;
+        }
+    }
+
+    fn quirks(&self) -> Quirks {
+        Quirks::xo_chip()
+    }
+
+    fn save_state(&self, w: &mut Writer) {
+        self.super_chip.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut Reader) -> Result<(), Error> {
+        self.super_chip.load_state(r)
+    }
+
+    fn reset(&mut self, c: &mut Context) {
+        // Reset SUPER-CHIP
+        self.super_chip.reset(c);
+    }
+
+    fn memory_size(&self) -> usize {
+        // 64 KiB; all of which can be addressed by `F000 NNNN`
+        0x10000
+    }
+
+    fn insert_rom(&mut self, m: &mut Mmu, buffer: &[u8]) {
+        self.super_chip.insert_rom(m, buffer);
+    }
+
+    fn instructions_per_frame(&self) -> usize {
+        if self.octo { 1000 } else { self.super_chip.instructions_per_frame() }
+    }
+
+    fn update_framebuffer(&mut self, c: &mut Context) {
+        self.super_chip.update_framebuffer(c);
+    }
+
+    fn framebuffer_size(&self, c: &Context) -> (usize, usize) {
+        self.super_chip.framebuffer_size(c)
+    }
+
+    fn execute(&mut self,
+               r: &mut axal::Runtime,
+               c: &mut Context,
+               m: &mut Mmu,
+               opcode: Opcode)
+               -> bool {
+        let instruction = Instruction::decode(opcode.extract_u16());
+
+        match instruction {
+            // SCUP
+            Instruction::ScrollUp(n) => {
+                // Scroll the selected planes N lines up
+                self.super_chip.scroll(c, 0, -(n as isize));
+            }
+
+            // SAVE Vx - Vy
+            Instruction::StoreRange(x, y) => {
+                // Store registers Vx through Vy (in either order) in memory starting at
+                // location I; I is unchanged
+                for (j, r) in register_range(x, y).enumerate() {
+                    m.write(c.i + j, c.v[r]);
+                }
+            }
+
+            // LOAD Vx - Vy
+            Instruction::RestoreRange(x, y) => {
+                // Read registers Vx through Vy (in either order) from memory starting at
+                // location I; I is unchanged
+                for (j, r) in register_range(x, y).enumerate() {
+                    c.v[r] = m.read(c.i + j);
+                }
+            }
+
+            // SUB Vx, Vy (Octo)
+            Instruction::Sub(x, y) if self.octo => {
+                // Set Vx = Vx - Vy; Set VF = !<borrow> (after Vx)
+                let vx = c.v[x as usize];
+                let vy = c.v[y as usize];
+
+                c.v[x as usize] = vx.wrapping_sub(vy);
+                c.v[0xF] = (vy <= vx) as u8;
+            }
+
+            // SUBN Vx, Vy (Octo)
+            Instruction::SubN(x, y) if self.octo => {
+                // Set Vx = Vy - Vx; Set VF = !<borrow> (after Vx)
+                let vx = c.v[x as usize];
+                let vy = c.v[y as usize];
+
+                c.v[x as usize] = vy.wrapping_sub(vx);
+                c.v[0xF] = (vx <= vy) as u8;
+            }
+
+            // LD I, u16
+            Instruction::LoadLongI => {
+                // Set I = u16; the address is the 16-bit word following the instruction
+                let address = Opcode::read_next(&mut c.pc, m);
+
+                c.i = address.extract_u16() as usize;
+            }
+
+            // PLANE u2
+            Instruction::Plane(n) => {
+                // Select the planes drawn to, cleared, and scrolled (bitmask)
+                c.plane_mask = n & 0b11;
+            }
+
+            // AUDIO
+            Instruction::Audio => {
+                // Load the 16-byte audio pattern buffer from [I]
+                for j in 0..16 {
+                    c.audio_pattern[j] = m.read(c.i + j);
+                }
+            }
+
+            // PITCH Vx
+            Instruction::Pitch(x) => {
+                // Set the audio playback rate to 4000 * 2^((Vx - 64) / 48) Hz
+                c.pitch = c.v[x as usize];
+            }
+
+            // SE, SNE, SKP, SKNP
+            _ if instruction.is_skip() => {
+                let pc = c.pc;
+                if !self.super_chip.execute(r, c, m, opcode) {
+                    return false;
+                }
+
+                // A skipped `F000 NNNN` is 4 bytes; skip the address as well
+                if c.pc == pc.wrapping_add(2) && m.fetch(pc) == 0xF0 && m.fetch(pc + 1) == 0x00 {
+                    c.pc = c.pc.wrapping_add(2);
+                }
+            }
+
+            _ => {
+                // Unhandled by XO-CHIP
+                return self.super_chip.execute(r, c, m, opcode);
+            }
+        }
+
+        true
+    }
+}